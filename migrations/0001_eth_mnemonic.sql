@@ -0,0 +1,4 @@
+-- Columns the eth_mnemonic search mode writes to, on top of the existing eth table. Safe to run again.
+
+ALTER TABLE eth ADD COLUMN IF NOT EXISTS mnemonic text;
+ALTER TABLE eth ADD COLUMN IF NOT EXISTS derivation_path text;
@@ -0,0 +1,7 @@
+-- The structured list of the rules a wallet matched, see vanity_generator::RuleMatch. Safe to run again.
+
+ALTER TABLE eth ADD COLUMN IF NOT EXISTS matches jsonb;
+ALTER TABLE polkadot ADD COLUMN IF NOT EXISTS matches jsonb;
+-- for containment queries like matches @> '[{"rule_id": "start_word"}]'
+CREATE INDEX IF NOT EXISTS eth_matches_idx ON eth USING gin (matches);
+CREATE INDEX IF NOT EXISTS polkadot_matches_idx ON polkadot USING gin (matches);
@@ -0,0 +1,4 @@
+-- The version of the rule set a wallet was found with, see rule_config::version. Safe to run again.
+
+ALTER TABLE eth ADD COLUMN IF NOT EXISTS rule_set_version text;
+ALTER TABLE polkadot ADD COLUMN IF NOT EXISTS rule_set_version text;
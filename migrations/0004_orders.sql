@@ -0,0 +1,16 @@
+-- Orders routed by orders::OrderBook and the order a wallet was found for. Safe to run again.
+
+CREATE TABLE IF NOT EXISTS orders (
+    id bigserial PRIMARY KEY,
+    customer text NOT NULL,
+    -- the chains of orders::ORDER_CHAINS, orders for other chains would never be searched
+    chain text NOT NULL CHECK (chain IN ('eth')),
+    pattern text NOT NULL,
+    case_sensitive boolean NOT NULL DEFAULT false,
+    status text NOT NULL DEFAULT 'open' CHECK (status IN ('open', 'fulfilled', 'closed')),
+    public_address text,
+    fulfilled_at timestamptz
+);
+CREATE INDEX IF NOT EXISTS orders_open_idx ON orders (chain, id) WHERE status = 'open';
+
+ALTER TABLE eth ADD COLUMN IF NOT EXISTS order_id bigint;
@@ -0,0 +1,21 @@
+-- The job queue of the jobs search mode and the job a wallet was found for. Safe to run again.
+
+CREATE TABLE IF NOT EXISTS jobs (
+    id bigserial PRIMARY KEY,
+    -- the chains of jobs::JOB_CHAINS, jobs for other chains would never be searched
+    chain text NOT NULL CHECK (chain IN ('eth')),
+    pattern text NOT NULL,
+    case_sensitive boolean NOT NULL DEFAULT false,
+    -- higher first
+    priority integer NOT NULL DEFAULT 0,
+    deadline timestamptz,
+    max_results bigint NOT NULL DEFAULT 1 CHECK (max_results > 0),
+    found bigint NOT NULL DEFAULT 0,
+    status text NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'claimed', 'done', 'expired', 'invalid')),
+    worker_id text,
+    claimed_at timestamptz,
+    heartbeat_at timestamptz
+);
+CREATE INDEX IF NOT EXISTS jobs_pending_idx ON jobs (priority DESC, id) WHERE status = 'pending';
+
+ALTER TABLE eth ADD COLUMN IF NOT EXISTS job_id bigint;
@@ -1,3 +1,16 @@
+// Building a Secp256k1 context allocates and precomputes tables, so every thread keeps one
+pub mod secp_context {
+    use secp256k1::{All, Secp256k1};
+
+    thread_local! {
+        static SECP256K1: Secp256k1<All> = Secp256k1::new();
+    }
+
+    pub fn with_secp<R>(f: impl FnOnce(&Secp256k1<All>) -> R) -> R {
+        SECP256K1.with(|secp| f(secp))
+    }
+}
+
 pub mod eth_wallet {
 
     use ethers::core::rand::thread_rng;
@@ -5,7 +18,9 @@ pub mod eth_wallet {
     use web3::types::Address;
     use eth_checksum;
 
-    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use secp256k1::{PublicKey, SecretKey};
+    use super::secp_context::with_secp;
+    use once_cell::sync::OnceCell;
 
     #[derive(Debug)]
     #[derive(Clone)]
@@ -13,26 +28,42 @@ pub mod eth_wallet {
         pub secret_key: String,
         pub public_key: String,
         pub address: String,
-        pub address_checksummed: String
+        // costs another keccak, so only computed when something asks for it
+        address_checksummed: OnceCell<String>
     }
 
     impl Wallet {
         pub fn new(secret_key: &SecretKey, public_key: &PublicKey) -> Self {
             let addr: Address = public_key_address(&public_key);
-            let address: String = hex::encode(addr);
-            let address_checksummed: String = checksummed(&address);
+            Wallet::from_parts(hex::encode(&secret_key.secret_bytes()), public_key.to_string(), hex::encode(addr))
+        }
+
+        pub fn from_parts(secret_key: String, public_key: String, address: String) -> Self {
             Wallet {
-                secret_key: hex::encode(&secret_key.secret_bytes()),
-                public_key: public_key.to_string(),
-                address: address,
-                address_checksummed: address_checksummed
+                secret_key,
+                public_key,
+                address,
+                address_checksummed: OnceCell::new()
             }
         }
+
+        pub fn address_checksummed(&self) -> &String {
+            self.address_checksummed.get_or_init(|| checksummed(&self.address))
+        }
+
+        // the checksummed address when it is already known, e.g. from checking the rules
+        pub fn known_address_checksummed(&self) -> Option<&String> {
+            self.address_checksummed.get()
+        }
+
+        // hands over a checksummed address computed elsewhere, it is then not computed again
+        pub fn set_address_checksummed(&self, checksummed: &[u8; 40]) {
+            let _ = self.address_checksummed.set(String::from_utf8_lossy(checksummed).into_owned());
+        }
     }
 
     pub fn generate_random_wallet() -> Wallet {
-        let secp = Secp256k1::new();
-        let (secret_key, public_key) = secp.generate_keypair(&mut thread_rng());
+        let (secret_key, public_key) = with_secp(|secp| secp.generate_keypair(&mut thread_rng()));
         Wallet::new(&secret_key, &public_key)
     }
 
@@ -47,13 +78,47 @@ pub mod eth_wallet {
         eth_checksum::checksum(address)[2..].to_string()
     }
 
+    const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
+    // Same as hex::encode(address) but on the stack
+    pub fn address_hex_bytes(address: &[u8; 20]) -> [u8; 40] {
+        let mut out = [0u8; 40];
+        for (i, byte) in address.iter().enumerate() {
+            out[2 * i] = HEX_CHARS[(byte >> 4) as usize];
+            out[2 * i + 1] = HEX_CHARS[(byte & 0x0f) as usize];
+        }
+        out
+    }
+
+    // EIP-55 on the stack: a letter is upper case when its nibble of keccak(lowercase hex) is 8 or more
+    pub fn checksummed_bytes(address_hex: &[u8; 40]) -> [u8; 40] {
+        let hash = keccak256(address_hex);
+        let mut out = *address_hex;
+        for (i, c) in out.iter_mut().enumerate() {
+            let nibble = if i % 2 == 0 { hash[i / 2] >> 4 } else { hash[i / 2] & 0x0f };
+            if nibble >= 8 {
+                c.make_ascii_uppercase();
+            }
+        }
+        out
+    }
+
+    // PublicKey::to_string of the uncompressed coordinates x || y
+    pub fn compressed_public_key_hex(coordinates: &[u8; 64]) -> String {
+        let tag = if coordinates[63] & 1 == 0 { 0x02 } else { 0x03 };
+        let mut compressed = vec![tag];
+        compressed.extend_from_slice(&coordinates[..32]);
+        hex::encode(compressed)
+    }
+
 }
 
 pub mod polka_wallet {
     use sp_core::{sr25519, Pair};
-    use sp_core::crypto::{Ss58Codec};
+    use sp_core::crypto::{Ss58Codec, default_ss58_version};
     use bip39::{Mnemonic, Language, Seed};
     use rand::RngCore;
+    use crate::base58_prefix::PayloadLayout;
 
     #[derive(Debug, Clone)]
     pub struct PolkaWallet {
@@ -75,22 +140,33 @@ pub mod polka_wallet {
         }
     }
 
-    pub fn generate_random_wallet() -> PolkaWallet {
+    pub fn generate_random_pair() -> (sr25519::Pair, String) {
         let mut entropy = [0u8; 16];
         rand::thread_rng().fill_bytes(&mut entropy);
         // mnemonic is not working
         let mnemonic = Mnemonic::from_entropy(&entropy, Language::English).unwrap();
         let seed = Seed::new(&mnemonic, std::env::var("POLKADOT_PASSWORD").unwrap().as_str());
         let pair = sr25519::Pair::from_seed_slice(&seed.as_bytes()[0..32]).expect("Invalid Seed");
+        (pair, mnemonic.phrase().to_string())
+    }
 
-        let public_key = pair.public();
-        let address = public_key.to_ss58check();
-        PolkaWallet {
-            secret_key: hex::encode(pair.to_raw_vec()),
-            public_key: hex::encode(public_key),
-            address,
-            mnemonic: mnemonic.phrase().to_string(),
-        }
+    pub fn generate_random_wallet() -> PolkaWallet {
+        let (pair, mnemonic) = generate_random_pair();
+        PolkaWallet::new(&pair, &mnemonic)
+    }
+
+    // Same version bytes as sp_core uses in to_ss58check, followed by the 32 bytes public key and 2 checksum bytes
+    pub fn ss58_payload_layout() -> PayloadLayout {
+        let ident: u16 = u16::from(default_ss58_version()) & 0b0011_1111_1111_1111;
+        let version = match ident {
+            0..=63 => vec![ident as u8],
+            _ => {
+                let first = ((ident & 0b0000_0000_1111_1100) as u8) >> 2;
+                let second = ((ident >> 8) as u8) | ((ident & 0b0000_0000_0000_0011) as u8) << 6;
+                vec![first | 0b01000000, second]
+            }
+        };
+        PayloadLayout::new(version, 32, 2)
     }
 }
 
@@ -108,7 +184,8 @@ pub mod eth_wallet_simple {
     use tiny_keccak::keccak256;
     use web3::types::Address;
 
-    use secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use secp256k1::{PublicKey, SecretKey};
+    use super::secp_context::with_secp;
 
     pub fn random_biguint_in_hex64_range() -> BigUint {
         let mut rng = rand::thread_rng();
@@ -168,8 +245,7 @@ pub mod eth_wallet_simple {
         let private_key_hex = generate_private_key(biguint);
         let secret_key = SecretKey::from_slice(&hex::decode(private_key_hex)?)?;
 
-        let secp = Secp256k1::new();
-        let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+        let public_key = with_secp(|secp| PublicKey::from_secret_key(secp, &secret_key));
         Ok(Wallet::new(&secret_key, &public_key))
     }
     
@@ -186,18 +262,12 @@ pub mod eth_wallet_simple_u64 {
     
     use rand::Rng;
     use rand::RngCore;
-    use once_cell::sync::Lazy;
 
     use tiny_keccak::keccak256;
     use web3::types::Address;
-    use std::sync::Mutex;
 
-    use secp256k1::{PublicKey, Secp256k1, SecretKey};
-    static SECRET_KEY_BYTES: Lazy<Mutex<[u8; 32]>> = Lazy::new(|| {
-        let mut bytes = [0u8; 32];
-        rand::thread_rng().fill_bytes(&mut bytes[0..24]);
-        Mutex::new(bytes)
-    });
+    use secp256k1::{PublicKey, SecretKey};
+    use super::secp_context::with_secp;
 
 
     #[derive(Debug)]
@@ -226,14 +296,18 @@ pub mod eth_wallet_simple_u64 {
     }
 
     pub struct Generator {
+        // the first 24 bytes of every secret key, the u64 fills the last 8
+        pub secret_key_prefix: [u8; 24],
         pub current_u64: u64,
     }
     
     impl Generator {
         pub fn new() -> Generator {
             let mut gen = Generator {
+                secret_key_prefix: [0u8; 24],
                 current_u64: 1,  // initialized to 1
             };
+            rand::thread_rng().fill_bytes(&mut gen.secret_key_prefix);
             gen.randomize();
             gen
         }
@@ -248,15 +322,13 @@ pub mod eth_wallet_simple_u64 {
         }
     }
     
-    pub fn generate_ecdsa_key_pair_from_private_key(u64_value: u64) -> Result<Wallet, Box<dyn std::error::Error>> {
-        {
-            let mut secret_key_bytes = SECRET_KEY_BYTES.lock().unwrap();
-            secret_key_bytes[24..32].copy_from_slice(&u64_value.to_be_bytes());
-        }
-        let secret_key = SecretKey::from_slice(&*SECRET_KEY_BYTES.lock().unwrap())?;
+    pub fn generate_ecdsa_key_pair_from_private_key(secret_key_prefix: &[u8; 24], u64_value: u64) -> Result<Wallet, Box<dyn std::error::Error>> {
+        let mut secret_key_bytes = [0u8; 32];
+        secret_key_bytes[0..24].copy_from_slice(secret_key_prefix);
+        secret_key_bytes[24..32].copy_from_slice(&u64_value.to_be_bytes());
+        let secret_key = SecretKey::from_slice(&secret_key_bytes)?;
         
-        let secp = Secp256k1::new();
-        let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+        let public_key = with_secp(|secp| PublicKey::from_secret_key(secp, &secret_key));
         Ok(Wallet::new(&secret_key, &public_key))
     }
         
@@ -264,26 +336,18 @@ pub mod eth_wallet_simple_u64 {
 
 
 pub mod eth_wallet_u64_contained {
-    
+    // Workers share one random keyspace (24 byte prefix and a u64 start) and each owns the counters
+    // start + worker_index + k * worker_count. Offsets of different workers differ modulo worker_count
+    // and stay below 2^64, so no key is generated twice across workers.
+
     use rand::Rng;
     use rand::RngCore;
-    use once_cell::sync::Lazy;
 
     use tiny_keccak::keccak256;
     use web3::types::Address;
-    use std::sync::Mutex;
-
-    use secp256k1::{PublicKey, Secp256k1, SecretKey};
-    static SECRET_KEY_BYTES: Lazy<Mutex<[u8; 32]>> = Lazy::new(|| {
-        let mut bytes = [0u8; 32];
-        rand::thread_rng().fill_bytes(&mut bytes[0..24]);
-        Mutex::new(bytes)
-    });    
-    static CURRENT_UINT64: Lazy<Mutex<u64>> = Lazy::new(|| {
-        let mut rng = rand::thread_rng();
-        Mutex::new(rng.gen::<u64>())
-    });
 
+    use secp256k1::{PublicKey, SecretKey};
+    use super::secp_context::with_secp;
 
     #[derive(Debug)]
     pub struct Wallet {
@@ -304,20 +368,72 @@ pub mod eth_wallet_u64_contained {
         
     }
 
-    pub fn set_secret_key_bytes() {
-        let mut secret_key_bytes = SECRET_KEY_BYTES.lock().unwrap();
-        rand::thread_rng().fill_bytes(&mut secret_key_bytes[0..24]);
+    // returned once a shard handed out all of its keys, unlike other key errors it does not go away
+    #[derive(Debug)]
+    pub struct ShardExhausted;
+
+    impl std::fmt::Display for ShardExhausted {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "Keyspace shard is exhausted")
+        }
     }
-    
-    pub fn set_current_u64() {
-        let mut current_u64 = CURRENT_UINT64.lock().unwrap();
-        *current_u64 = rand::thread_rng().gen::<u64>();
+
+    impl std::error::Error for ShardExhausted {}
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Keyspace {
+        pub prefix: [u8; 24],
+        pub start: u64,
     }
-    
-    pub fn increment_current_u64() -> u64{
-        let mut current_u64 = CURRENT_UINT64.lock().unwrap();
-        *current_u64 += 1;
-        *current_u64
+
+    impl Keyspace {
+        pub fn random() -> Self {
+            let mut prefix = [0u8; 24];
+            rand::thread_rng().fill_bytes(&mut prefix);
+            Keyspace { prefix, start: rand::thread_rng().gen::<u64>() }
+        }
+
+        pub fn shard(&self, worker_index: u64, worker_count: u64) -> KeyspaceShard {
+            assert!(worker_index < worker_count, "worker index {} out of {} workers", worker_index, worker_count);
+            KeyspaceShard { keyspace: *self, worker_index, worker_count, covered: 0 }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct KeyspaceShard {
+        pub keyspace: Keyspace,
+        pub worker_index: u64,
+        pub worker_count: u64,
+        // keys generated so far, the shard covered the first `covered` counters it owns
+        pub covered: u64,
+    }
+
+    impl KeyspaceShard {
+        fn offset(&self, k: u64) -> Option<u64> {
+            k.checked_mul(self.worker_count)?.checked_add(self.worker_index)
+        }
+
+        // None once the shard used up its part of the 2^64 counters
+        pub fn next_counter(&self) -> Option<u64> {
+            self.offset(self.covered).map(|offset| self.keyspace.start.wrapping_add(offset))
+        }
+
+        pub fn contains(&self, counter: u64) -> bool {
+            let offset = counter.wrapping_sub(self.keyspace.start);
+            offset % self.worker_count == self.worker_index && offset / self.worker_count < self.covered
+        }
+
+        pub fn next_secret_key(&mut self) -> Result<SecretKey, Box<dyn std::error::Error>> {
+            let counter = self.next_counter().ok_or(ShardExhausted)?;
+            self.covered += 1;
+            Ok(secret_key_from_counter(&self.keyspace.prefix, counter)?)
+        }
+
+        pub fn next_wallet(&mut self) -> Result<Wallet, Box<dyn std::error::Error>> {
+            let secret_key = self.next_secret_key()?;
+            let public_key = with_secp(|secp| PublicKey::from_secret_key(secp, &secret_key));
+            Ok(Wallet::new(&secret_key, &public_key))
+        }
     }
     
     pub fn public_key_address(public_key: &PublicKey) -> Address {
@@ -326,15 +442,11 @@ pub mod eth_wallet_u64_contained {
         Address::from_slice(&hash[12..])
     }
 
-    pub fn generate_ecdsa_key_pair_from_private_key() -> Result<Wallet, Box<dyn std::error::Error>> {
-        let u64_value: u64 = increment_current_u64();
-        let mut secret_key_bytes = SECRET_KEY_BYTES.lock().unwrap();
-        secret_key_bytes[24..32].copy_from_slice(&u64_value.to_be_bytes());
-        let secret_key = SecretKey::from_slice(&*secret_key_bytes)?;
-        
-        let secp = Secp256k1::new();
-        let public_key = PublicKey::from_secret_key(&secp, &secret_key);
-        Ok(Wallet::new(&secret_key, &public_key))
+    pub fn secret_key_from_counter(prefix: &[u8; 24], counter: u64) -> Result<SecretKey, secp256k1::Error> {
+        let mut secret_key_bytes = [0u8; 32];
+        secret_key_bytes[0..24].copy_from_slice(prefix);
+        secret_key_bytes[24..32].copy_from_slice(&counter.to_be_bytes());
+        SecretKey::from_slice(&secret_key_bytes)
     }
         
 }
@@ -367,14 +479,15 @@ mod tests {
     fn s_64_wallet(n_wallets: i32) { 
         let mut u64_generator = eth_wallet_simple_u64::Generator::new();
         for _ in 0..n_wallets {
-            let _wallet = eth_wallet_simple_u64::generate_ecdsa_key_pair_from_private_key(u64_generator.current_u64).unwrap();
+            let _wallet = eth_wallet_simple_u64::generate_ecdsa_key_pair_from_private_key(&u64_generator.secret_key_prefix, u64_generator.current_u64).unwrap();
             u64_generator.increment();
         }
     }
 
     fn s_64_reduced(n_wallets: i32) {
+        let mut shard = eth_wallet_u64_contained::Keyspace::random().shard(0, 1);
         for _ in 0..n_wallets {
-            let _wallet = eth_wallet_u64_contained::generate_ecdsa_key_pair_from_private_key().unwrap();
+            let _wallet = shard.next_wallet().unwrap();
         }
     }
 
@@ -384,6 +497,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn stack_hex_and_checksum_match_the_string_versions() {
+        for _ in 0..50 {
+            let wallet = eth_wallet::generate_random_wallet();
+            let address: [u8; 20] = hex::decode(&wallet.address).unwrap().try_into().unwrap();
+            let address_hex = eth_wallet::address_hex_bytes(&address);
+            assert_eq!(&address_hex[..], wallet.address.as_bytes());
+            assert_eq!(&eth_wallet::checksummed_bytes(&address_hex)[..], wallet.address_checksummed().as_bytes());
+        }
+    }
+
+    #[test]
+    fn keyspace_shards_do_not_overlap() {
+        // start close to u64::MAX so the counters wrap around
+        let keyspace = eth_wallet_u64_contained::Keyspace { prefix: [7u8; 24], start: u64::MAX - 5 };
+        let mut shards: Vec<_> = (0..3).map(|i| keyspace.shard(i, 3)).collect();
+        let mut counters = Vec::new();
+        let mut secret_keys = std::collections::HashSet::new();
+        for shard in shards.iter_mut() {
+            for _ in 0..10 {
+                counters.push((shard.worker_index, shard.next_counter().unwrap()));
+                let wallet = shard.next_wallet().unwrap();
+                assert!(secret_keys.insert(wallet.secret_key));
+            }
+            assert_eq!(shard.covered, 10);
+        }
+        assert_eq!(secret_keys.len(), 30);
+        for (worker_index, counter) in counters {
+            for shard in shards.iter() {
+                assert_eq!(shard.contains(counter), shard.worker_index == worker_index);
+            }
+        }
+
+        let mut exhausted = keyspace.shard(1, 2);
+        exhausted.covered = u64::MAX / 2 + 1;
+        assert!(exhausted.next_counter().is_none());
+        assert!(exhausted.next_wallet().unwrap_err().is::<eth_wallet_u64_contained::ShardExhausted>());
+    }
+
     #[test]
     fn wallet_generation_speed() {
         let n_wallets = 1000;
@@ -3,7 +3,11 @@
 
 use bip39::{Mnemonic, Language, MnemonicType, Seed};
 use tiny_hderive::bip32::ExtendedPrivKey;
+use tiny_hderive::bip44::{ChildNumber, DerivationPath};
 use secp256k1::{Secp256k1, SecretKey, PublicKey};
+use crate::address::eth_wallet;
+use std::env;
+use std::str::FromStr;
 use sha3::{Digest, Keccak256};
 use hex;
 use std::fmt;
@@ -92,7 +96,7 @@ pub fn create_eth_address(mnemonic: &str) -> (String, String) {
 
     // Hash the public key to create the address
     let mut hasher = Keccak256::new();
-    hasher.update(&public_key.serialize()[1..]);
+    hasher.update(&public_key.serialize_uncompressed()[1..]);
     let result = hasher.finalize();
 
     // Ethereum address is the last 20 bytes of the hash
@@ -109,6 +113,73 @@ pub fn create_eth_address(mnemonic: &str) -> (String, String) {
     (private_key_hex, address_no_0x)
 }
 
+// Mnemonic search: every mnemonic is checked at base_path/0 .. base_path/(index_count - 1)
+pub struct HdSearchConfig {
+    pub mnemonic_type: MnemonicType,
+    pub passphrase: String,
+    pub base_path: String,
+    pub index_count: u32,
+}
+
+impl HdSearchConfig {
+    pub fn new(word_count: usize, passphrase: &str, base_path: &str, index_count: u32) -> Result<Self, String> {
+        let mnemonic_type = match word_count {
+            12 => MnemonicType::Words12,
+            24 => MnemonicType::Words24,
+            _ => return Err(format!("Unsupported mnemonic word count {}, expected 12 or 24", word_count)),
+        };
+        DerivationPath::from_str(base_path).map_err(|e| format!("Invalid derivation path {}: {:?}", base_path, e))?;
+        if index_count == 0 {
+            return Err("Index count must be at least 1".to_string());
+        }
+        Ok(Self {
+            mnemonic_type,
+            passphrase: passphrase.to_string(),
+            base_path: base_path.to_string(),
+            index_count,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        let word_count = env::var("MNEMONIC_WORDS").unwrap_or_else(|_| "12".to_string())
+            .parse().map_err(|_| "Failed to parse MNEMONIC_WORDS".to_string())?;
+        let passphrase = env::var("MNEMONIC_PASSPHRASE").unwrap_or_default();
+        let base_path = env::var("HD_PATH").unwrap_or_else(|_| "m/44'/60'/0'/0".to_string());
+        let index_count = env::var("HD_INDEX_COUNT").unwrap_or_else(|_| "1".to_string())
+            .parse().map_err(|_| "Failed to parse HD_INDEX_COUNT".to_string())?;
+        Self::new(word_count, &passphrase, &base_path, index_count)
+    }
+
+    pub fn path_for_index(&self, index: u32) -> String {
+        format!("{}/{}", self.base_path, index)
+    }
+}
+
+pub struct HdCandidate {
+    pub index: u32,
+    pub wallet: eth_wallet::Wallet,
+}
+
+pub fn generate_mnemonic(config: &HdSearchConfig) -> Mnemonic {
+    Mnemonic::new(config.mnemonic_type, Language::English)
+}
+
+// The base path is derived once, the indices are single non hardened child steps from it
+pub fn derive_eth_wallets(mnemonic: &Mnemonic, config: &HdSearchConfig, secp: &Secp256k1<secp256k1::All>) -> Vec<HdCandidate> {
+    let seed = Seed::new(mnemonic, &config.passphrase);
+    let base = ExtendedPrivKey::derive(seed.as_bytes(), config.base_path.as_str()).unwrap();
+
+    (0..config.index_count).map(|index| {
+        let child = base.child(ChildNumber::non_hardened_from_u32(index)).unwrap();
+        let secret_key = SecretKey::from_slice(&child.secret()).unwrap();
+        let public_key = PublicKey::from_secret_key(secp, &secret_key);
+        HdCandidate {
+            index,
+            wallet: eth_wallet::Wallet::new(&secret_key, &public_key),
+        }
+    }).collect()
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -133,4 +204,35 @@ mod tests {
         println!("Generated {} key bundles in {:.2} seconds", n_bundles, duration_in_secs);
         println!("Generation speed: {:.2} key bundles/second", bundles_per_second);
     }
+
+    const TEST_MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn derive_eth_wallets_matches_known_addresses() {
+        let config = HdSearchConfig::new(12, "", "m/44'/60'/0'/0", 3).unwrap();
+        let mnemonic = Mnemonic::from_phrase(TEST_MNEMONIC, Language::English).unwrap();
+        let candidates = derive_eth_wallets(&mnemonic, &config, &Secp256k1::new());
+
+        assert_eq!(candidates.len(), 3);
+        assert_eq!(candidates[0].wallet.address, "9858effd232b4033e47d90003d41ec34ecaeda94");
+        assert_eq!(candidates[1].wallet.address, "6fac4d18c912343bf86fa7049364dd4e424ab9c0");
+        let (_, address) = create_eth_address(TEST_MNEMONIC);
+        assert_eq!(candidates[0].wallet.address, address);
+    }
+
+    #[test]
+    fn passphrase_changes_addresses() {
+        let mnemonic = Mnemonic::from_phrase(TEST_MNEMONIC, Language::English).unwrap();
+        let plain = HdSearchConfig::new(12, "", "m/44'/60'/0'/0", 1).unwrap();
+        let protected = HdSearchConfig::new(12, "secret", "m/44'/60'/0'/0", 1).unwrap();
+        let secp = Secp256k1::new();
+        assert_ne!(derive_eth_wallets(&mnemonic, &plain, &secp)[0].wallet.address, derive_eth_wallets(&mnemonic, &protected, &secp)[0].wallet.address);
+    }
+
+    #[test]
+    fn invalid_config_is_rejected() {
+        assert!(HdSearchConfig::new(15, "", "m/44'/60'/0'/0", 1).is_err());
+        assert!(HdSearchConfig::new(12, "", "44/60", 1).is_err());
+        assert!(HdSearchConfig::new(24, "", "m/44'/60'/0'/0", 0).is_err());
+    }
 }
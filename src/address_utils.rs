@@ -1,22 +1,27 @@
 pub mod address_utils {
-    use std::collections::HashMap;
+
+    // Both work on the ascii bytes of the address without allocating, so the search loop can
+    // score raw candidates before any String exists.
 
     pub fn calculate_proximity_coefficient(address: &str, proximity_max_distance: usize) -> f64 {
+        calculate_proximity_coefficient_bytes(address.as_bytes(), proximity_max_distance)
+    }
+
+    // Sum of 1 / distance from every char to its next proximity_max_distance occurrences
+    pub fn calculate_proximity_coefficient_bytes(address: &[u8], proximity_max_distance: usize) -> f64 {
         let mut proximity_coefficient = 0.0;
-        let mut indexes: HashMap<char, Vec<usize>> = HashMap::new();
-    
-        for (i, c) in address.chars().enumerate() {
-            indexes.entry(c).or_default().push(i);
+        if proximity_max_distance == 0 {
+            return proximity_coefficient;
         }
     
-        for c_indexes in indexes.values() {
-            for (ii, &first_value) in c_indexes.iter().enumerate() {
-                for j in 1..=proximity_max_distance {
-                    let ii2 = ii + j;
-                    if ii2 < c_indexes.len() {
-                        let second_value = c_indexes[ii2];
-                        let distance = second_value as f64 - first_value as f64;
-                        proximity_coefficient += 1.0 / distance;
+        for (first_index, &c) in address.iter().enumerate() {
+            let mut following = 0;
+            for (second_index, &other) in address.iter().enumerate().skip(first_index + 1) {
+                if other == c {
+                    proximity_coefficient += 1.0 / (second_index - first_index) as f64;
+                    following += 1;
+                    if following == proximity_max_distance {
+                        break;
                     }
                 }
             }
@@ -26,15 +31,47 @@ pub mod address_utils {
     }
 
     pub fn calculate_entropy(address: &str) -> f64 {
-        let mut char_freq = HashMap::new();
+        calculate_entropy_bytes(address.as_bytes())
+    }
+
+    pub fn calculate_entropy_bytes(address: &[u8]) -> f64 {
+        let mut char_freq = [0u32; 256];
     
-        for c in address.chars() {
-            *char_freq.entry(c).or_insert(0u32) += 1;
+        for &c in address {
+            char_freq[c as usize] += 1;
         }
     
         let total_chars = address.len() as f64;
-        let char_probs: Vec<f64> = char_freq.values().map(|&count| count as f64 / total_chars).collect();
-        
-        -char_probs.iter().fold(0.0, |acc, &p| acc + p * p.log2())
+        -char_freq.iter()
+            .filter(|&&count| count > 0)
+            .fold(0.0, |acc, &count| {
+                let p = count as f64 / total_chars;
+                acc + p * p.log2()
+            })
+    }
+
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn proximity_of_small_strings() {
+            assert_eq!(calculate_proximity_coefficient("abc", 3), 0.0);
+            assert_eq!(calculate_proximity_coefficient("aab", 3), 1.0);
+            // a at 0, 2, 3: 1/2 + 1/3 + 1/1
+            assert!((calculate_proximity_coefficient("abaa", 3) - (0.5 + 1.0 / 3.0 + 1.0)).abs() < 1e-12);
+            // only the next occurrence counts with distance 1
+            assert!((calculate_proximity_coefficient("abaa", 1) - (0.5 + 1.0)).abs() < 1e-12);
+            assert_eq!(calculate_proximity_coefficient("aaaa", 0), 0.0);
+        }
+
+        #[test]
+        fn entropy_of_small_strings() {
+            assert_eq!(calculate_entropy("aaaa"), 0.0);
+            assert_eq!(calculate_entropy("abab"), 1.0);
+            assert_eq!(calculate_entropy("0123456789abcdef"), 4.0);
+            assert_eq!(calculate_entropy_bytes(b"abab"), calculate_entropy("abab"));
+        }
     }
-}
\ No newline at end of file
+}
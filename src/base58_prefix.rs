@@ -0,0 +1,333 @@
+use num_bigint::BigUint;
+use num_traits::{One, ToPrimitive, Zero};
+use std::iter;
+
+pub const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+pub fn char_index(c: u8) -> Option<usize> {
+    BASE58_ALPHABET.iter().position(|&a| a == c)
+}
+
+// Byte layout of a Base58Check payload: fixed version bytes, then the key material, then the checksum.
+// For SS58 with a one byte network prefix this is [prefix] + 32 bytes public key + 2 bytes checksum.
+#[derive(Debug, Clone)]
+pub struct PayloadLayout {
+    pub version: Vec<u8>,
+    pub body_len: usize,
+    pub checksum_len: usize,
+}
+
+impl PayloadLayout {
+    pub fn new(version: Vec<u8>, body_len: usize, checksum_len: usize) -> Self {
+        Self {
+            version,
+            body_len,
+            checksum_len,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.version.len() + self.body_len + self.checksum_len
+    }
+
+    // Leading zero bytes are encoded as '1' each. Only the ones inside the version are known upfront.
+    pub fn fixed_leading_zeros(&self) -> usize {
+        self.version.iter().take_while(|&&b| b == 0).count()
+    }
+
+    fn version_is_zero(&self) -> bool {
+        self.fixed_leading_zeros() == self.version.len()
+    }
+
+    pub fn min_value(&self) -> BigUint {
+        self.bound_value(0x00)
+    }
+
+    pub fn max_value(&self) -> BigUint {
+        self.bound_value(0xff)
+    }
+
+    fn bound_value(&self, fill: u8) -> BigUint {
+        let bytes: Vec<u8> = self.version.iter().cloned()
+            .chain(iter::repeat_n(fill, self.body_len + self.checksum_len))
+            .collect();
+        BigUint::from_bytes_be(&bytes)
+    }
+
+    fn to_fixed_bytes(&self, value: &BigUint) -> Vec<u8> {
+        let bytes = value.to_bytes_be();
+        let mut padded = vec![0u8; self.len() - bytes.len()];
+        padded.extend(bytes);
+        padded
+    }
+}
+
+fn base58_digits(value: &BigUint) -> usize {
+    let base = BigUint::from(58u32);
+    let mut digits = 1;
+    let mut power = base.clone();
+    while &power <= value {
+        power *= &base;
+        digits += 1;
+    }
+    digits
+}
+
+// Numeric ranges (inclusive) of payloads whose Base58 encoding starts with `prefix`.
+// Payloads with more leading zero bytes than the version has are not covered here, see `Base58PrefixFilter::might_match`.
+pub fn prefix_ranges(layout: &PayloadLayout, prefix: &str) -> Vec<(BigUint, BigUint)> {
+    let prefix = prefix.as_bytes();
+    let zeros = layout.fixed_leading_zeros();
+    if prefix.iter().any(|&c| char_index(c).is_none()) {
+        return vec![];
+    }
+    if prefix.len() <= zeros {
+        return if prefix.iter().all(|&c| c == b'1') {
+            vec![(layout.min_value(), layout.max_value())]
+        } else {
+            vec![]
+        };
+    }
+    if prefix[..zeros].iter().any(|&c| c != b'1') || prefix[zeros] == b'1' {
+        return vec![];
+    }
+
+    let digits = &prefix[zeros..];
+    let base = BigUint::from(58u32);
+    let mut value = BigUint::zero();
+    for &c in digits {
+        value = value * &base + BigUint::from(char_index(c).unwrap());
+    }
+
+    let min = layout.min_value().max(BigUint::one());
+    let max = layout.max_value();
+    let mut ranges = Vec::new();
+    for length in base58_digits(&min)..=base58_digits(&max) {
+        if digits.len() > length {
+            continue;
+        }
+        let scale = base.pow((length - digits.len()) as u32);
+        let length_min = base.pow(length as u32 - 1);
+        let length_max = base.pow(length as u32) - BigUint::one();
+
+        let lo = (&value * &scale).max(length_min).max(min.clone());
+        let hi = ((&value + BigUint::one()) * &scale - BigUint::one()).min(length_max).min(max.clone());
+        if lo <= hi {
+            ranges.push((lo, hi));
+        }
+    }
+    ranges
+}
+
+// Every spelling of `word` that Base58 can produce, e.g. "dev" -> "dev", "Dev", "dEv", ...
+pub fn case_variants(word: &str) -> Vec<String> {
+    let mut variants = vec![String::new()];
+    for c in word.chars() {
+        let mut options: Vec<char> = vec![c.to_ascii_lowercase(), c.to_ascii_uppercase()];
+        options.dedup();
+        options.retain(|&o| o.is_ascii() && char_index(o as u8).is_some());
+        variants = variants.iter()
+            .flat_map(|v| options.iter().map(move |&o| format!("{}{}", v, o)))
+            .collect();
+    }
+    variants
+}
+
+// Sorted, disjoint ranges of all payloads matching `word` in any of its allowed spellings
+pub fn word_ranges(layout: &PayloadLayout, word: &str, case_sensitive: bool) -> Vec<(BigUint, BigUint)> {
+    let spellings = if case_sensitive { vec![word.to_string()] } else { case_variants(word) };
+    let ranges = spellings.iter().flat_map(|spelling| prefix_ranges(layout, spelling)).collect();
+    merge_ranges(ranges)
+}
+
+// Chance that a uniformly random body and checksum encode starting with `word`, from the sizes of
+// word_ranges. Bodies with extra leading zero bytes count as no match.
+pub fn prefix_probability(layout: &PayloadLayout, word: &str, case_sensitive: bool) -> f64 {
+    let total = layout.max_value() - layout.min_value() + BigUint::one();
+    // below it the body starts with a zero byte, encoded as one more '1'
+    let lowest = match layout.version_is_zero() {
+        true => BigUint::one() << (8 * (layout.body_len + layout.checksum_len - 1)),
+        false => BigUint::zero(),
+    };
+    let matching = word_ranges(layout, word, case_sensitive).into_iter()
+        .map(|(lo, hi)| (lo.max(lowest.clone()), hi))
+        .filter(|(lo, hi)| lo <= hi)
+        .fold(BigUint::zero(), |sum, (lo, hi)| sum + hi - lo + BigUint::one());
+    // scaled so both fit an f64
+    let shift = total.bits().saturating_sub(64);
+    (matching >> shift).to_f64().unwrap_or(0.0) / (total >> shift).to_f64().unwrap_or(1.0)
+}
+
+pub fn merge_ranges(mut ranges: Vec<(BigUint, BigUint)>) -> Vec<(BigUint, BigUint)> {
+    ranges.sort();
+    let mut merged: Vec<(BigUint, BigUint)> = Vec::with_capacity(ranges.len());
+    for (lo, hi) in ranges {
+        match merged.last_mut() {
+            Some(last) if lo <= last.1 => last.1 = last.1.clone().max(hi),
+            _ => merged.push((lo, hi)),
+        }
+    }
+    merged
+}
+
+// Tells from the key material alone whether the Base58 encoding can start with one of the configured prefixes.
+// The checksum is unknown at that point, so every candidate is treated as the range body||00.. to body||ff..
+pub struct Base58PrefixFilter {
+    layout: PayloadLayout,
+    // sorted, disjoint, inclusive bounds encoded as big endian bytes of layout.len()
+    ranges: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Base58PrefixFilter {
+    pub fn new(layout: PayloadLayout) -> Self {
+        Self {
+            layout,
+            ranges: Vec::new(),
+        }
+    }
+
+    pub fn add_prefixes(&mut self, prefixes: &[&str], case_sensitive: bool) {
+        let mut numeric: Vec<(BigUint, BigUint)> = self.ranges.iter()
+            .map(|(lo, hi)| (BigUint::from_bytes_be(lo), BigUint::from_bytes_be(hi)))
+            .collect();
+        for &prefix in prefixes {
+            numeric.extend(word_ranges(&self.layout, prefix, case_sensitive));
+        }
+        self.ranges = merge_ranges(numeric).iter()
+            .map(|(lo, hi)| (self.layout.to_fixed_bytes(lo), self.layout.to_fixed_bytes(hi)))
+            .collect();
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // false means no checksum can make the encoding start with a configured prefix, true means run the full encode
+    pub fn might_match(&self, body: &[u8]) -> bool {
+        debug_assert_eq!(body.len(), self.layout.body_len);
+        if self.layout.version_is_zero() && body.first() == Some(&0) {
+            // extra leading '1' chars, not covered by the precomputed ranges
+            return true;
+        }
+        // candidates span version||body||00.. to version||body||ff..
+        let first = self.ranges.partition_point(|(_, hi)| self.payload(body, 0x00).gt(hi.iter().cloned()));
+        match self.ranges.get(first) {
+            Some((lo, _)) => self.payload(body, 0xff).ge(lo.iter().cloned()),
+            None => false,
+        }
+    }
+
+    fn payload<'a>(&'a self, body: &'a [u8], checksum_fill: u8) -> impl Iterator<Item = u8> + 'a {
+        self.layout.version.iter().cloned()
+            .chain(body.iter().cloned())
+            .chain(iter::repeat_n(checksum_fill, self.layout.checksum_len))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::polka_wallet;
+    use rand::RngCore;
+    use sp_core::crypto::Ss58Codec;
+    use sp_core::{sr25519, Pair};
+
+    fn encode(layout: &PayloadLayout, body: &[u8], checksum: &[u8]) -> String {
+        let mut payload = layout.version.clone();
+        payload.extend(body);
+        payload.extend(checksum);
+        bs58::encode(payload).into_string()
+    }
+
+    fn random_body(len: usize) -> Vec<u8> {
+        let mut body = vec![0u8; len];
+        rand::thread_rng().fill_bytes(&mut body);
+        body
+    }
+
+    #[test]
+    fn filter_has_no_false_negatives() {
+        let layout = PayloadLayout::new(vec![42], 32, 2);
+        for _ in 0..200 {
+            let body = random_body(32);
+            let address = encode(&layout, &body, &[0x12, 0x34]);
+            let mut filter = Base58PrefixFilter::new(layout.clone());
+            filter.add_prefixes(&[&address[..4]], true);
+            assert!(filter.might_match(&body), "{} not matched", address);
+        }
+    }
+
+    #[test]
+    fn filter_rejects_only_impossible_bodies() {
+        let layout = PayloadLayout::new(vec![42], 32, 2);
+        let mut filter = Base58PrefixFilter::new(layout.clone());
+        filter.add_prefixes(&["5Dev", "5Zen"], false);
+        let mut passed = 0;
+        for _ in 0..2000 {
+            let body = random_body(32);
+            if filter.might_match(&body) {
+                passed += 1;
+                continue;
+            }
+            for checksum in [[0x00, 0x00], [0x80, 0x00], [0xff, 0xff]] {
+                let address = encode(&layout, &body, &checksum).to_lowercase();
+                assert!(!address.starts_with("5dev") && !address.starts_with("5zen"));
+            }
+        }
+        assert!(passed < 100);
+    }
+
+    #[test]
+    fn prefix_probability_counts_the_payloads() {
+        // P2PKH, bodies without a leading zero byte start with 2..z after the '1'
+        let layout = PayloadLayout::new(vec![0x00], 20, 4);
+        let total: f64 = BASE58_ALPHABET.iter().map(|&c| prefix_probability(&layout, &format!("1{}", c as char), true)).sum();
+        assert!((total - 255.0 / 256.0).abs() < 1e-9, "{}", total);
+        assert_eq!(prefix_probability(&layout, "11", true), 0.0);
+
+        let sampled = (0..2000).filter(|_| encode(&layout, &random_body(20), &[0x12, 0x34, 0x56, 0x78])[1..].starts_with('2')).count() as f64 / 2000.0;
+        assert!((sampled - prefix_probability(&layout, "12", true)).abs() < 0.02, "{}", sampled);
+    }
+
+    #[test]
+    fn leading_zero_version() {
+        let layout = PayloadLayout::new(vec![0], 20, 4);
+        let mut filter = Base58PrefixFilter::new(layout.clone());
+        filter.add_prefixes(&["1Bob"], true);
+        for _ in 0..200 {
+            let mut body = random_body(20);
+            body[0] |= 0x01;
+            let address = encode(&layout, &body, &[0, 0, 0, 0]);
+            if address.starts_with("1Bob") {
+                assert!(filter.might_match(&body));
+            }
+        }
+        // an extra zero byte adds a '1', the filter leaves that to the full encode
+        let mut body = random_body(20);
+        body[0] = 0;
+        assert!(filter.might_match(&body));
+    }
+
+    #[test]
+    fn impossible_prefixes_are_empty() {
+        let layout = PayloadLayout::new(vec![42], 32, 2);
+        let mut filter = Base58PrefixFilter::new(layout);
+        // '1' needs a zero byte, '0' and 'l' are not in the alphabet
+        filter.add_prefixes(&["1abc", "0abc", "lol"], true);
+        assert!(filter.is_empty());
+    }
+
+    #[test]
+    fn ss58_layout_matches_sp_core() {
+        let layout = polka_wallet::ss58_payload_layout();
+        for seed in 0..50u8 {
+            let pair = sr25519::Pair::from_seed(&[seed; 32]);
+            let address = pair.public().to_ss58check();
+            let mut filter = Base58PrefixFilter::new(layout.clone());
+            filter.add_prefixes(&[&address[..6]], true);
+            assert!(filter.might_match(pair.public().as_ref()));
+        }
+    }
+}
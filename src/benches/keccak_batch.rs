@@ -0,0 +1,31 @@
+// cargo bench --bench keccak_batch
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rand::RngCore;
+
+#[path = "../keccak_batch.rs"]
+#[allow(dead_code)]
+mod keccak_batch;
+
+// as many public keys as the generators hash per batch
+const INPUTS: usize = 256;
+
+fn keccak_benchmark(c: &mut Criterion) {
+    let inputs: Vec<[u8; 64]> = (0..INPUTS).map(|_| {
+        let mut input = [0u8; 64];
+        rand::thread_rng().fill_bytes(&mut input);
+        input
+    }).collect();
+
+    let mut group = c.benchmark_group("keccak256 of 64 byte public keys");
+    group.throughput(Throughput::Elements(INPUTS as u64));
+    group.bench_function("tiny_keccak", |b| {
+        b.iter(|| black_box(&inputs).iter().map(|input| tiny_keccak::keccak256(input)).collect::<Vec<_>>())
+    });
+    group.bench_function("keccak256_many", |b| {
+        b.iter(|| keccak_batch::keccak256_many(black_box(&inputs)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, keccak_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,154 @@
+// Position of sequential key generators, so a restart continues where the worker stopped instead of
+// re-randomizing. One small json file per profile and worker: <dir>/<profile>-<worker_index>-of-<worker_count>.json
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkerCheckpoint {
+    pub worker_index: u64,
+    pub worker_count: u64,
+    // hex of the 32 byte secret key the worker started from
+    pub base: String,
+    // keys generated since base
+    pub covered: u64,
+}
+
+impl WorkerCheckpoint {
+    fn to_json(&self) -> Value {
+        json!({
+            "worker_index": self.worker_index,
+            "worker_count": self.worker_count,
+            "base": self.base,
+            "covered": self.covered
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        Some(WorkerCheckpoint {
+            worker_index: value["worker_index"].as_u64()?,
+            worker_count: value["worker_count"].as_u64()?,
+            base: value["base"].as_str()?.to_string(),
+            covered: value["covered"].as_u64()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileCoverage {
+    pub checkpoints: usize,
+    pub covered: u64,
+}
+
+// "<worker_index>-of-<worker_count>.json"
+fn is_worker_suffix(rest: &str) -> bool {
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    rest.strip_suffix(".json")
+        .and_then(|rest| rest.split_once("-of-"))
+        .map(|(worker_index, worker_count)| is_number(worker_index) && is_number(worker_count))
+        .unwrap_or(false)
+}
+
+pub struct CheckpointStore {
+    dir: PathBuf,
+}
+
+impl CheckpointStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        CheckpointStore { dir: dir.into() }
+    }
+
+    pub fn from_env() -> Self {
+        CheckpointStore::new(std::env::var("CHECKPOINT_DIR").unwrap_or_else(|_| "checkpoints".to_string()))
+    }
+
+    fn path(&self, profile: &str, worker_index: u64, worker_count: u64) -> PathBuf {
+        self.dir.join(format!("{}-{}-of-{}.json", profile, worker_index, worker_count))
+    }
+
+    // written to a temporary file first so a crash never leaves a half written checkpoint
+    pub fn save(&self, profile: &str, checkpoint: &WorkerCheckpoint) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(profile, checkpoint.worker_index, checkpoint.worker_count);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, checkpoint.to_json().to_string())?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    pub fn load(&self, profile: &str, worker_index: u64, worker_count: u64) -> Option<WorkerCheckpoint> {
+        let content = fs::read_to_string(self.path(profile, worker_index, worker_count)).ok()?;
+        WorkerCheckpoint::from_json(&serde_json::from_str(&content).ok()?)
+    }
+
+    // Everything ever checkpointed for the profile, also by runs with a different worker count
+    pub fn coverage(&self, profile: &str) -> ProfileCoverage {
+        let mut coverage = ProfileCoverage { checkpoints: 0, covered: 0 };
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return coverage,
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let is_profile_file = file_name.strip_prefix(profile)
+                .and_then(|rest| rest.strip_prefix('-'))
+                .map(is_worker_suffix)
+                .unwrap_or(false);
+            if !is_profile_file {
+                continue;
+            }
+            let checkpoint = fs::read_to_string(entry.path()).ok()
+                .and_then(|content| serde_json::from_str(&content).ok())
+                .and_then(|value| WorkerCheckpoint::from_json(&value));
+            if let Some(checkpoint) = checkpoint {
+                coverage.checkpoints += 1;
+                coverage.covered += checkpoint.covered;
+            }
+        }
+        coverage
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(name: &str) -> CheckpointStore {
+        let dir = std::env::temp_dir().join(format!("vanity-checkpoints-{}-{}", name, rand::random::<u64>()));
+        CheckpointStore::new(dir)
+    }
+
+    #[test]
+    fn save_and_load() {
+        let store = temp_store("load");
+        assert!(store.load("eth_sequential", 0, 2).is_none());
+        let checkpoint = WorkerCheckpoint { worker_index: 1, worker_count: 2, base: "ab".repeat(32), covered: 42 };
+        store.save("eth_sequential", &checkpoint).unwrap();
+        assert_eq!(store.load("eth_sequential", 1, 2), Some(checkpoint.clone()));
+        assert!(store.load("eth_sequential", 1, 3).is_none());
+
+        let moved = WorkerCheckpoint { covered: 100, ..checkpoint };
+        store.save("eth_sequential", &moved).unwrap();
+        assert_eq!(store.load("eth_sequential", 1, 2), Some(moved));
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn coverage_sums_a_profile() {
+        let store = temp_store("coverage");
+        assert_eq!(store.coverage("eth_u64_contained"), ProfileCoverage { checkpoints: 0, covered: 0 });
+        for (worker_index, worker_count, covered) in [(0, 2, 10), (1, 2, 20), (0, 1, 5)] {
+            let checkpoint = WorkerCheckpoint { worker_index, worker_count, base: "01".repeat(32), covered };
+            store.save("eth_u64_contained", &checkpoint).unwrap();
+        }
+        // profiles whose name starts with the other one are not counted
+        store.save("eth_u64_contained_v2", &WorkerCheckpoint { worker_index: 0, worker_count: 1, base: "01".repeat(32), covered: 1000 }).unwrap();
+        store.save("eth_u64_contained-2", &WorkerCheckpoint { worker_index: 0, worker_count: 1, base: "01".repeat(32), covered: 1000 }).unwrap();
+
+        assert_eq!(store.coverage("eth_u64_contained"), ProfileCoverage { checkpoints: 3, covered: 35 });
+        assert_eq!(store.coverage("eth_u64_contained_v2"), ProfileCoverage { checkpoints: 1, covered: 1000 });
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+}
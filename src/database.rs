@@ -1,9 +1,10 @@
 pub mod database {
 
-    use crate::vanity_generator::VanityResult;
+    use crate::orders::{self, Order};
+    use crate::vanity_generator::{self, VanityResult, MnemonicVanityResult};
     use dotenv::dotenv;
     use std::env;
-    use postgres::{Client, NoTls, Error};
+    use postgres::{Client, GenericClient, NoTls, Error};
 
     pub fn create_client() -> Result<Client, Error> {
         dotenv().ok();
@@ -11,8 +12,25 @@ pub mod database {
         Ok(Client::connect(&database_url, NoTls)?)
     }
 
+    // matches is a jsonb list of all matched rules, addresses that hit several of them are found with e.g.
+    // SELECT public_address FROM eth WHERE matches @> '[{"rule_id": "zero_bytes"}, {"rule_id": "start_word"}]'
+    fn insert_eth_wallet(client: &mut impl GenericClient, vanity_result: &VanityResult, order_id: Option<i64>) -> Result<(), Error> {
+        let matches = vanity_generator::matches_to_json(&vanity_result.matches).to_string();
+        client.execute(
+            "INSERT INTO eth (public_address, public_address_checksummed, private_key, info, entropy_coefficient, proximity_coefficient,  entropy_coefficient_checksummed, proximity_coefficient_checksummed, matches, rule_set_version, order_id, job_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::text::jsonb, $10, $11, $12)",
+            &[&vanity_result.wallet.address, &vanity_result.wallet.address_checksummed(), &vanity_result.wallet.secret_key, &vanity_result.matched_rule, &vanity_result.entropy_coefficient, &vanity_result.proximity_coefficient, &vanity_result.entropy_coefficient_checksummed, &vanity_result.proximity_coefficient_checksummed, &matches, &vanity_result.rule_set_version, &order_id, &vanity_result.job_id],
+        )?;
+        Ok(())
+    }
+
     pub trait DatabaseHandler {
         fn write_eth_wallet(&self, vanity_result: &VanityResult) -> Result<(), Error>;
+        fn write_mnemonic_wallet(&self, mnemonic_result: &MnemonicVanityResult) -> Result<(), Error>;
+        fn read_private_key(&self, public_address: &str) -> Result<Option<String>, Error>;
+        fn read_open_orders(&self, chain: &str) -> Result<Vec<Order>, String>;
+        // Stores the wallet of an order hit and closes the order in one transaction. false when the
+        // order was fulfilled or closed in the meantime, the wallet is then only stored if it met the rules
+        fn write_order_wallet(&self, vanity_result: &VanityResult, order_id: i64) -> Result<bool, Error>;
     }
 
     // Actual database
@@ -20,12 +38,54 @@ pub mod database {
     impl DatabaseHandler for RealDatabase {
         fn write_eth_wallet(&self, vanity_result: &VanityResult) -> Result<(), Error> {
             let mut client: Client = create_client()?;
+            insert_eth_wallet(&mut client, vanity_result, vanity_result.order_id)
+        }
+
+        fn write_mnemonic_wallet(&self, mnemonic_result: &MnemonicVanityResult) -> Result<(), Error> {
+            let mut client: Client = create_client()?;
+            let vanity_result = &mnemonic_result.result;
+            let matches = vanity_generator::matches_to_json(&vanity_result.matches).to_string();
             client.execute(
-                "INSERT INTO eth (public_address, public_address_checksummed, private_key, info, entropy_coefficient, proximity_coefficient,  entropy_coefficient_checksummed, proximity_coefficient_checksummed) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
-                &[&vanity_result.wallet.address, &vanity_result.wallet.address_checksummed, &vanity_result.wallet.secret_key, &vanity_result.matched_rule, &vanity_result.entropy_coefficient, &vanity_result.proximity_coefficient, &vanity_result.entropy_coefficient_checksummed, &vanity_result.proximity_coefficient_checksummed],
+                "INSERT INTO eth (public_address, public_address_checksummed, private_key, info, entropy_coefficient, proximity_coefficient,  entropy_coefficient_checksummed, proximity_coefficient_checksummed, mnemonic, derivation_path, matches, rule_set_version, order_id, job_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11::text::jsonb, $12, $13, $14)",
+                &[&vanity_result.wallet.address, &vanity_result.wallet.address_checksummed(), &vanity_result.wallet.secret_key, &vanity_result.matched_rule, &vanity_result.entropy_coefficient, &vanity_result.proximity_coefficient, &vanity_result.entropy_coefficient_checksummed, &vanity_result.proximity_coefficient_checksummed, &mnemonic_result.mnemonic, &mnemonic_result.derivation_path, &matches, &vanity_result.rule_set_version, &vanity_result.order_id, &vanity_result.job_id],
             )?;
             Ok(())
         }
+
+        fn read_private_key(&self, public_address: &str) -> Result<Option<String>, Error> {
+            let mut client: Client = create_client()?;
+            let address = public_address.trim_start_matches("0x");
+            let row = client.query_opt(
+                "SELECT private_key FROM eth WHERE public_address = $1 OR public_address_checksummed = $1",
+                &[&address],
+            )?;
+            Ok(row.map(|r| r.get(0)))
+        }
+
+        // orders (id bigserial, customer text, chain text, pattern text, case_sensitive bool, status text, public_address text, fulfilled_at timestamptz)
+        fn read_open_orders(&self, chain: &str) -> Result<Vec<Order>, String> {
+            let mut client: Client = create_client().map_err(|e| e.to_string())?;
+            let rows = client.query(
+                "SELECT id, customer, chain, pattern, case_sensitive FROM orders WHERE chain = $1 AND status = 'open' ORDER BY id",
+                &[&chain],
+            ).map_err(|e| e.to_string())?;
+            Ok(rows.iter().map(|r| Order { id: r.get(0), customer: r.get(1), chain: r.get(2), pattern: r.get(3), case_sensitive: r.get(4) }).collect())
+        }
+
+        // only one worker can fulfil an order, the others find it no longer open
+        fn write_order_wallet(&self, vanity_result: &VanityResult, order_id: i64) -> Result<bool, Error> {
+            let mut client: Client = create_client()?;
+            let mut transaction = client.transaction()?;
+            let fulfilled = transaction.execute(
+                "UPDATE orders SET status = 'fulfilled', public_address = $2, fulfilled_at = now() WHERE id = $1 AND status = 'open'",
+                &[&order_id, &vanity_result.wallet.address],
+            )? == 1;
+            if fulfilled || vanity_result.met_criteria {
+                insert_eth_wallet(&mut transaction, vanity_result, fulfilled.then_some(order_id))?;
+            }
+            transaction.commit()?;
+            Ok(fulfilled)
+        }
     }
 
     // In-memory emulation
@@ -37,12 +97,37 @@ pub mod database {
             Ok(())
         }
 
+        fn write_mnemonic_wallet(&self, mnemonic_result: &MnemonicVanityResult) -> Result<(), Error> {
+            println!("Mock Database: Writing to emulated database {}", mnemonic_result);
+            Ok(())
+        }
+
+        fn read_private_key(&self, public_address: &str) -> Result<Option<String>, Error> {
+            println!("Mock Database: Nothing stored for {}", public_address);
+            Ok(None)
+        }
+
+        // the orders of ORDERS_FILE, none without it
+        fn read_open_orders(&self, chain: &str) -> Result<Vec<Order>, String> {
+            let path = match env::var("ORDERS_FILE") {
+                Ok(path) => path,
+                Err(_) => return Ok(Vec::new()),
+            };
+            let content = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+            let orders = orders::parse_orders(&content).map_err(|e| format!("{}: {}", path, e))?;
+            Ok(orders.into_iter().filter(|order| order.chain == chain).collect())
+        }
+
+        fn write_order_wallet(&self, vanity_result: &VanityResult, order_id: i64) -> Result<bool, Error> {
+            println!("Mock Database: Order {} fulfilled, writing to emulated database {}", order_id, vanity_result);
+            Ok(true)
+        }
     }
 }
 
 pub mod polkadot_database {
 
-    use crate::vanity_generator::PolkadotVanityResult;
+    use crate::vanity_generator::{self, PolkadotVanityResult};
     use dotenv::dotenv;
     use std::env;
     use postgres::{Client, NoTls, Error};
@@ -55,19 +140,31 @@ pub mod polkadot_database {
 
     pub trait DatabaseHandler {
         fn write_eth_wallet(&self, vanity_result: &PolkadotVanityResult) -> Result<(), Error>;
+        fn read_private_key(&self, public_address: &str) -> Result<Option<String>, Error>;
     }
 
     // Actual database
    pub struct RealDatabase;
     impl DatabaseHandler for RealDatabase {
+        // matches is a jsonb list of all matched rules, the same as for eth
         fn write_eth_wallet(&self, vanity_result: &PolkadotVanityResult) -> Result<(), Error> {
             let mut client: Client = create_client()?;
+            let matches = vanity_generator::matches_to_json(&vanity_result.matches).to_string();
             client.execute(
-                "INSERT INTO polkadot (public_address, private_key, info, entropy_coefficient, proximity_coefficient) VALUES ($1, $2, $3, $4, $5)",
-                &[&vanity_result.wallet.address, &vanity_result.wallet.secret_key, &vanity_result.matched_rule, &vanity_result.entropy_coefficient, &vanity_result.proximity_coefficient],
+                "INSERT INTO polkadot (public_address, private_key, info, entropy_coefficient, proximity_coefficient, matches, rule_set_version) VALUES ($1, $2, $3, $4, $5, $6::text::jsonb, $7)",
+                &[&vanity_result.wallet.address, &vanity_result.wallet.secret_key, &vanity_result.matched_rule, &vanity_result.entropy_coefficient, &vanity_result.proximity_coefficient, &matches, &vanity_result.rule_set_version],
             )?;
             Ok(())
         }
+
+        fn read_private_key(&self, public_address: &str) -> Result<Option<String>, Error> {
+            let mut client: Client = create_client()?;
+            let row = client.query_opt(
+                "SELECT private_key FROM polkadot WHERE public_address = $1",
+                &[&public_address],
+            )?;
+            Ok(row.map(|r| r.get(0)))
+        }
     }
 
     // In-memory emulation
@@ -79,6 +176,220 @@ pub mod polkadot_database {
             Ok(())
         }
 
+        fn read_private_key(&self, public_address: &str) -> Result<Option<String>, Error> {
+            println!("Mock Database: Nothing stored for {}", public_address);
+            Ok(None)
+        }
+    }
+}
+
+// Jobs a pool of workers shares through Postgres
+// jobs (id bigserial, chain text, pattern text, case_sensitive bool, priority int, deadline timestamptz,
+//       max_results bigint, found bigint default 0, status text default 'pending', worker_id text,
+//       claimed_at timestamptz, heartbeat_at timestamptz)
+// status is pending, claimed, done or expired
+pub mod jobs_database {
+
+    use super::database::create_client;
+    use crate::jobs::{self, Job};
+    use std::env;
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime};
+    use postgres::{Client, Error};
+
+    pub trait JobQueue {
+        // The pending job of the chains with the highest priority, claimed for the worker
+        fn claim(&self, worker_id: &str, chains: &[&str]) -> Result<Option<Job>, Error>;
+        // false when the worker lost its claim, e.g. after it was released as stale
+        fn heartbeat(&self, job_id: i64, worker_id: &str) -> Result<bool, Error>;
+        // counts a stored result, the job is done once it has max_results. The results found so far,
+        // none when the worker lost its claim
+        fn record_result(&self, job_id: i64, worker_id: &str) -> Result<Option<i64>, Error>;
+        // ends a claimed job, e.g. as expired once its deadline passed
+        fn finish(&self, job_id: i64, worker_id: &str, status: &str) -> Result<(), Error>;
+        // claims without a heartbeat for stale_after go back to pending, jobs past their deadline
+        // expire. The number of released claims
+        fn release_stale(&self, stale_after: Duration) -> Result<u64, Error>;
+    }
+
+    // Actual database
+    pub struct RealDatabase;
+    impl JobQueue for RealDatabase {
+        // SKIP LOCKED lets concurrent workers each take a different job instead of waiting on one
+        fn claim(&self, worker_id: &str, chains: &[&str]) -> Result<Option<Job>, Error> {
+            let mut client: Client = create_client()?;
+            let chains: Vec<String> = chains.iter().map(|chain| chain.to_string()).collect();
+            let row = client.query_opt(
+                "UPDATE jobs SET status = 'claimed', worker_id = $1, claimed_at = now(), heartbeat_at = now()
+                 WHERE id = (
+                     SELECT id FROM jobs
+                     WHERE status = 'pending' AND chain = ANY($2) AND (deadline IS NULL OR deadline > now())
+                     ORDER BY priority DESC, id
+                     LIMIT 1
+                     FOR UPDATE SKIP LOCKED
+                 )
+                 RETURNING id, chain, pattern, case_sensitive, priority, deadline, max_results, found",
+                &[&worker_id, &chains],
+            )?;
+            Ok(row.map(|r| Job {
+                id: r.get(0),
+                chain: r.get(1),
+                pattern: r.get(2),
+                case_sensitive: r.get(3),
+                priority: r.get(4),
+                deadline: r.get(5),
+                max_results: r.get(6),
+                found: r.get(7),
+            }))
+        }
+
+        fn heartbeat(&self, job_id: i64, worker_id: &str) -> Result<bool, Error> {
+            let mut client: Client = create_client()?;
+            let updated = client.execute(
+                "UPDATE jobs SET heartbeat_at = now() WHERE id = $1 AND worker_id = $2 AND status = 'claimed'",
+                &[&job_id, &worker_id],
+            )?;
+            Ok(updated == 1)
+        }
+
+        fn record_result(&self, job_id: i64, worker_id: &str) -> Result<Option<i64>, Error> {
+            let mut client: Client = create_client()?;
+            let row = client.query_opt(
+                "UPDATE jobs SET found = found + 1, heartbeat_at = now(),
+                     status = CASE WHEN found + 1 >= max_results THEN 'done' ELSE status END
+                 WHERE id = $1 AND worker_id = $2 AND status = 'claimed'
+                 RETURNING found",
+                &[&job_id, &worker_id],
+            )?;
+            Ok(row.map(|r| r.get(0)))
+        }
+
+        fn finish(&self, job_id: i64, worker_id: &str, status: &str) -> Result<(), Error> {
+            let mut client: Client = create_client()?;
+            client.execute(
+                "UPDATE jobs SET status = $3 WHERE id = $1 AND worker_id = $2 AND status = 'claimed'",
+                &[&job_id, &worker_id, &status],
+            )?;
+            Ok(())
+        }
+
+        fn release_stale(&self, stale_after: Duration) -> Result<u64, Error> {
+            let mut client: Client = create_client()?;
+            client.execute(
+                "UPDATE jobs SET status = 'expired' WHERE status IN ('pending', 'claimed') AND deadline <= now()",
+                &[],
+            )?;
+            let released = client.execute(
+                "UPDATE jobs SET status = 'pending', worker_id = NULL
+                 WHERE status = 'claimed' AND heartbeat_at < now() - make_interval(secs => $1)",
+                &[&stale_after.as_secs_f64()],
+            )?;
+            Ok(released)
+        }
+    }
+
+    struct MockJob {
+        job: Job,
+        status: String,
+        worker_id: Option<String>,
+        heartbeat_at: SystemTime,
+    }
+
+    // In-memory emulation, shared by the workers of one process
+    pub struct MockDatabase {
+        jobs: Mutex<Vec<MockJob>>,
+    }
+
+    impl MockDatabase {
+        pub fn new(jobs: Vec<Job>) -> Self {
+            let jobs = jobs.into_iter()
+                .map(|job| MockJob { job, status: "pending".to_string(), worker_id: None, heartbeat_at: SystemTime::now() })
+                .collect();
+            MockDatabase { jobs: Mutex::new(jobs) }
+        }
+
+        // the jobs of JOBS_FILE, none without it
+        pub fn from_env() -> Result<Self, String> {
+            let jobs = match env::var("JOBS_FILE") {
+                Ok(path) => {
+                    let content = std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+                    jobs::parse_jobs(&content).map_err(|e| format!("{}: {}", path, e))?
+                }
+                Err(_) => Vec::new(),
+            };
+            Ok(MockDatabase::new(jobs))
+        }
+
+        #[cfg(test)]
+        pub fn status(&self, job_id: i64) -> Option<String> {
+            self.jobs.lock().unwrap().iter().find(|mock| mock.job.id == job_id).map(|mock| mock.status.clone())
+        }
+
+        #[cfg(test)]
+        pub fn age_heartbeat(&self, job_id: i64, by: Duration) {
+            for mock in self.jobs.lock().unwrap().iter_mut().filter(|mock| mock.job.id == job_id) {
+                mock.heartbeat_at -= by;
+            }
+        }
+    }
+
+    impl JobQueue for MockDatabase {
+        fn claim(&self, worker_id: &str, chains: &[&str]) -> Result<Option<Job>, Error> {
+            let now = SystemTime::now();
+            let mut jobs = self.jobs.lock().unwrap();
+            let claimable = jobs.iter_mut()
+                .filter(|mock| mock.status == "pending" && chains.contains(&mock.job.chain.as_str()) && !mock.job.is_expired(now))
+                .min_by_key(|mock| (-mock.job.priority, mock.job.id));
+            Ok(claimable.map(|mock| {
+                mock.status = "claimed".to_string();
+                mock.worker_id = Some(worker_id.to_string());
+                mock.heartbeat_at = now;
+                println!("Mock Database: Job {} claimed by {}", mock.job.id, worker_id);
+                mock.job.clone()
+            }))
+        }
+
+        fn heartbeat(&self, job_id: i64, worker_id: &str) -> Result<bool, Error> {
+            let mut jobs = self.jobs.lock().unwrap();
+            let claimed = jobs.iter_mut().find(|mock| mock.job.id == job_id && mock.status == "claimed" && mock.worker_id.as_deref() == Some(worker_id));
+            Ok(claimed.map(|mock| mock.heartbeat_at = SystemTime::now()).is_some())
+        }
+
+        fn record_result(&self, job_id: i64, worker_id: &str) -> Result<Option<i64>, Error> {
+            let mut jobs = self.jobs.lock().unwrap();
+            let claimed = jobs.iter_mut().find(|mock| mock.job.id == job_id && mock.status == "claimed" && mock.worker_id.as_deref() == Some(worker_id));
+            Ok(claimed.map(|mock| {
+                mock.job.found += 1;
+                mock.heartbeat_at = SystemTime::now();
+                if mock.job.found >= mock.job.max_results {
+                    mock.status = "done".to_string();
+                }
+                mock.job.found
+            }))
+        }
+
+        fn finish(&self, job_id: i64, worker_id: &str, status: &str) -> Result<(), Error> {
+            let mut jobs = self.jobs.lock().unwrap();
+            for mock in jobs.iter_mut().filter(|mock| mock.job.id == job_id && mock.status == "claimed" && mock.worker_id.as_deref() == Some(worker_id)) {
+                mock.status = status.to_string();
+            }
+            Ok(())
+        }
+
+        fn release_stale(&self, stale_after: Duration) -> Result<u64, Error> {
+            let now = SystemTime::now();
+            let mut released = 0;
+            for mock in self.jobs.lock().unwrap().iter_mut() {
+                if (mock.status == "pending" || mock.status == "claimed") && mock.job.is_expired(now) {
+                    mock.status = "expired".to_string();
+                } else if mock.status == "claimed" && now.duration_since(mock.heartbeat_at).unwrap_or_default() > stale_after {
+                    mock.status = "pending".to_string();
+                    mock.worker_id = None;
+                    released += 1;
+                }
+            }
+            Ok(released)
+        }
     }
 }
 
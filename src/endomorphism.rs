@@ -0,0 +1,158 @@
+// secp256k1 has an efficient endomorphism: for P = k·G = (x, y), λ·P = (β·x, y) and −P = (x, −y).
+// One scalar multiplication therefore gives six public keys, k, λk, λ²k and their negations, for the
+// price of two field multiplications. The private key of a derived point is only rebuilt on a hit.
+use crate::keccak_batch;
+use lazy_static::lazy_static;
+use num_bigint::BigUint;
+use secp256k1::{PublicKey, Scalar, SecretKey};
+
+lazy_static! {
+    static ref FIELD_PRIME: BigUint = BigUint::parse_bytes(b"fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f", 16).unwrap();
+    // cube root of unity mod p matching LAMBDA
+    static ref BETA: BigUint = BigUint::parse_bytes(b"7ae96a2b657c07106e64479eac3434e99cf0497512f58995c1396c28719501ee", 16).unwrap();
+}
+
+// cube root of unity mod the curve order n
+const LAMBDA: [u8; 32] = [
+    0x53, 0x63, 0xad, 0x4c, 0xc0, 0x5c, 0x30, 0xe0, 0xa5, 0x26, 0x1c, 0x02, 0x88, 0x12, 0x64, 0x5a,
+    0x12, 0x2e, 0x22, 0xea, 0x20, 0x81, 0x66, 0x78, 0xdf, 0x02, 0x96, 0x7c, 0x1b, 0x23, 0xbd, 0x72,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyTransform {
+    Identity,
+    Lambda,
+    LambdaSquared,
+    Negate,
+    NegateLambda,
+    NegateLambdaSquared,
+}
+
+pub const ALL_TRANSFORMS: [KeyTransform; 6] = [
+    KeyTransform::Identity,
+    KeyTransform::Lambda,
+    KeyTransform::LambdaSquared,
+    KeyTransform::Negate,
+    KeyTransform::NegateLambda,
+    KeyTransform::NegateLambdaSquared,
+];
+
+impl KeyTransform {
+    fn lambda_power(&self) -> usize {
+        match self {
+            KeyTransform::Identity | KeyTransform::Negate => 0,
+            KeyTransform::Lambda | KeyTransform::NegateLambda => 1,
+            KeyTransform::LambdaSquared | KeyTransform::NegateLambdaSquared => 2,
+        }
+    }
+
+    fn negated(&self) -> bool {
+        matches!(self, KeyTransform::Negate | KeyTransform::NegateLambda | KeyTransform::NegateLambdaSquared)
+    }
+
+    // Private key of the point derived with this transform from the public key of secret_key
+    pub fn apply_to_secret(&self, secret_key: &SecretKey) -> SecretKey {
+        let lambda = Scalar::from_be_bytes(LAMBDA).unwrap();
+        let mut secret_key = *secret_key;
+        for _ in 0..self.lambda_power() {
+            // λ is not zero and less than n, so the product of two valid keys is a valid key
+            secret_key = secret_key.mul_tweak(&lambda).unwrap();
+        }
+        if self.negated() {
+            secret_key = secret_key.negate();
+        }
+        secret_key
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DerivedPoint {
+    pub transform: KeyTransform,
+    // x || y, the uncompressed public key without the 0x04 tag
+    pub coordinates: [u8; 64],
+}
+
+fn to_field_bytes(value: &BigUint) -> [u8; 32] {
+    let bytes = value.to_bytes_be();
+    let mut out = [0u8; 32];
+    out[32 - bytes.len()..].copy_from_slice(&bytes);
+    out
+}
+
+fn point(transform: KeyTransform, x: &[u8; 32], y: &[u8; 32]) -> DerivedPoint {
+    let mut coordinates = [0u8; 64];
+    coordinates[..32].copy_from_slice(x);
+    coordinates[32..].copy_from_slice(y);
+    DerivedPoint { transform, coordinates }
+}
+
+// The first `count` (at most six) points of ALL_TRANSFORMS for the given public key
+pub fn derived_points(public_key: &PublicKey, count: usize) -> Vec<DerivedPoint> {
+    let serialized = public_key.serialize_uncompressed();
+    let x = BigUint::from_bytes_be(&serialized[1..33]);
+    let y = BigUint::from_bytes_be(&serialized[33..65]);
+
+    let beta_x = (&x * &*BETA) % &*FIELD_PRIME;
+    let beta_squared_x = (&beta_x * &*BETA) % &*FIELD_PRIME;
+    // y is never zero on secp256k1, so p - y is reduced
+    let negated_y = &*FIELD_PRIME - &y;
+
+    let xs = [to_field_bytes(&x), to_field_bytes(&beta_x), to_field_bytes(&beta_squared_x)];
+    let ys = [to_field_bytes(&y), to_field_bytes(&negated_y)];
+    ALL_TRANSFORMS.iter()
+        .take(count.min(ALL_TRANSFORMS.len()))
+        .map(|transform| {
+            let y = if transform.negated() { &ys[1] } else { &ys[0] };
+            point(*transform, &xs[transform.lambda_power()], y)
+        })
+        .collect()
+}
+
+// eth addresses of all points, hashed LANES at a time
+pub fn addresses(points: &[DerivedPoint]) -> Vec<[u8; 20]> {
+    let coordinates: Vec<[u8; 64]> = points.iter().map(|point| point.coordinates).collect();
+    keccak_batch::keccak256_many(&coordinates).iter()
+        .map(|hash| hash[12..].try_into().unwrap())
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::eth_wallet;
+    use secp256k1::Secp256k1;
+    use tiny_keccak::keccak256;
+
+    #[test]
+    fn derived_points_match_the_transformed_secret_keys() {
+        let secp = Secp256k1::new();
+        for _ in 0..10 {
+            let (secret_key, public_key) = secp.generate_keypair(&mut rand::thread_rng());
+            let points = derived_points(&public_key, 6);
+            assert_eq!(points.len(), 6);
+            for derived in points {
+                let derived_secret = derived.transform.apply_to_secret(&secret_key);
+                let expected = PublicKey::from_secret_key(&secp, &derived_secret);
+                assert_eq!(&expected.serialize_uncompressed()[1..], &derived.coordinates[..], "{:?}", derived.transform);
+                assert_eq!(expected.to_string(), eth_wallet::compressed_public_key_hex(&derived.coordinates));
+            }
+        }
+    }
+
+    #[test]
+    fn identity_point_is_the_eth_address() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+        let points = derived_points(&public_key, 2);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].transform, KeyTransform::Identity);
+        assert_eq!(hex::encode(&keccak256(&points[0].coordinates)[12..]), eth_wallet::Wallet::new(&secret_key, &public_key).address);
+
+        let all = derived_points(&public_key, 6);
+        let batched = addresses(&all);
+        let single: Vec<[u8; 20]> = all.iter().map(|point| keccak256(&point.coordinates)[12..].try_into().unwrap()).collect();
+        assert_eq!(batched, single);
+    }
+}
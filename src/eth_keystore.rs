@@ -0,0 +1,152 @@
+// Web3 Secret Storage (keystore V3) json, the format geth, MetaMask and ethers import.
+// https://ethereum.org/en/developers/docs/data-structures-and-encoding/web3-secret-storage/
+use crate::address::eth_wallet::Wallet;
+use aes::cipher::{KeyIvInit, StreamCipher};
+use rand::RngCore;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::error::Error;
+use tiny_keccak::keccak256;
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+const DERIVED_KEY_LENGTH: usize = 32;
+
+#[derive(Debug, Clone)]
+pub enum Kdf {
+    Scrypt { log_n: u8, r: u32, p: u32 },
+    Pbkdf2 { c: u32 },
+}
+
+impl Kdf {
+    // geth "standard" parameters
+    pub fn scrypt() -> Self {
+        Kdf::Scrypt { log_n: 18, r: 8, p: 1 }
+    }
+
+    pub fn pbkdf2() -> Self {
+        Kdf::Pbkdf2 { c: 262_144 }
+    }
+
+    fn derive_key(&self, password: &str, salt: &[u8]) -> Result<[u8; DERIVED_KEY_LENGTH], Box<dyn Error>> {
+        let mut derived = [0u8; DERIVED_KEY_LENGTH];
+        match self {
+            Kdf::Scrypt { log_n, r, p } => {
+                let params = scrypt::Params::new(*log_n, *r, *p)?;
+                scrypt::scrypt(password.as_bytes(), salt, &params, &mut derived)?;
+            },
+            Kdf::Pbkdf2 { c } => {
+                pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, *c, &mut derived);
+            }
+        }
+        Ok(derived)
+    }
+
+    fn to_json(&self, salt: &[u8]) -> (&'static str, Value) {
+        match self {
+            Kdf::Scrypt { log_n, r, p } => ("scrypt", json!({
+                "dklen": DERIVED_KEY_LENGTH,
+                "n": 1u64 << log_n,
+                "r": r,
+                "p": p,
+                "salt": hex::encode(salt)
+            })),
+            Kdf::Pbkdf2 { c } => ("pbkdf2", json!({
+                "dklen": DERIVED_KEY_LENGTH,
+                "c": c,
+                "prf": "hmac-sha256",
+                "salt": hex::encode(salt)
+            })),
+        }
+    }
+}
+
+fn uuid_v4() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let h = hex::encode(bytes);
+    format!("{}-{}-{}-{}-{}", &h[0..8], &h[8..12], &h[12..16], &h[16..20], &h[20..32])
+}
+
+pub fn encrypt_wallet(wallet: &Wallet, password: &str, kdf: &Kdf) -> Result<Value, Box<dyn Error>> {
+    let mut salt = [0u8; 32];
+    let mut iv = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut iv);
+
+    let derived = kdf.derive_key(password, &salt)?;
+    let mut ciphertext = hex::decode(&wallet.secret_key)?;
+    Aes128Ctr::new(derived[..16].into(), &iv.into()).apply_keystream(&mut ciphertext);
+
+    let mut mac_input = derived[16..32].to_vec();
+    mac_input.extend_from_slice(&ciphertext);
+    let mac = keccak256(&mac_input);
+
+    let (kdf_name, kdf_params) = kdf.to_json(&salt);
+    Ok(json!({
+        "address": wallet.address,
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": hex::encode(iv) },
+            "ciphertext": hex::encode(ciphertext),
+            "kdf": kdf_name,
+            "kdfparams": kdf_params,
+            "mac": hex::encode(mac)
+        },
+        "id": uuid_v4(),
+        "version": 3
+    }))
+}
+
+// secret_key_hex is what the eth table stores, Wallet::secret_key
+pub fn export_secret_key(secret_key_hex: &str, password: &str, kdf: &Kdf, path: &str) -> Result<String, Box<dyn Error>> {
+    let secret_key = SecretKey::from_slice(&hex::decode(secret_key_hex)?)?;
+    let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+    let wallet = Wallet::new(&secret_key, &public_key);
+    let keystore = encrypt_wallet(&wallet, password, kdf)?;
+    std::fs::write(path, serde_json::to_string_pretty(&keystore)?)?;
+    Ok(wallet.address_checksummed().clone())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::{LocalWallet, Signer};
+
+    fn ethers_roundtrip(kdf: Kdf, name: &str) {
+        let secret_key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let public_key = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key);
+        let wallet = Wallet::new(&secret_key, &public_key);
+
+        let path = std::env::temp_dir().join(format!("{}-{}.json", name, uuid_v4()));
+        let keystore = encrypt_wallet(&wallet, "password", &kdf).unwrap();
+        std::fs::write(&path, keystore.to_string()).unwrap();
+
+        let decrypted = LocalWallet::decrypt_keystore(&path, "password").unwrap();
+        assert_eq!(hex::encode(decrypted.signer().to_bytes()), wallet.secret_key);
+        assert_eq!(hex::encode(decrypted.address()), wallet.address);
+        assert!(LocalWallet::decrypt_keystore(&path, "wrong").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn scrypt_keystore_decrypts_with_ethers() {
+        ethers_roundtrip(Kdf::Scrypt { log_n: 10, r: 8, p: 1 }, "scrypt");
+    }
+
+    #[test]
+    fn pbkdf2_keystore_decrypts_with_ethers() {
+        ethers_roundtrip(Kdf::Pbkdf2 { c: 1024 }, "pbkdf2");
+    }
+
+    #[test]
+    fn uuid_is_v4() {
+        let uuid = uuid_v4();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+    }
+}
@@ -0,0 +1,175 @@
+// Search jobs pulled from the jobs table. A job is searched like an order with the same
+// <start>*<end> pattern, by the worker that claimed it, until it has max_results addresses or
+// its deadline passes. Workers heartbeat their claim, claims of workers that stopped
+// heartbeating go back to pending for the others.
+use crate::orders::Order;
+use serde_json::Value;
+use std::env;
+use std::time::{Duration, SystemTime};
+
+// the chains workers search, jobs of others are rejected
+pub const JOB_CHAINS: &[&str] = &["eth"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    pub id: i64,
+    pub chain: String,
+    pub pattern: String,
+    pub case_sensitive: bool,
+    // higher first
+    pub priority: i32,
+    pub deadline: Option<SystemTime>,
+    pub max_results: i64,
+    // results stored so far, also by earlier claims
+    pub found: i64,
+}
+
+impl Job {
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= now)
+    }
+
+    // the order the job is searched as
+    pub fn order(&self) -> Order {
+        Order {
+            id: self.id,
+            customer: format!("job {}", self.id),
+            chain: self.chain.clone(),
+            pattern: self.pattern.clone(),
+            case_sensitive: self.case_sensitive,
+        }
+    }
+}
+
+pub struct JobSettings {
+    // wait between claims while no job is pending
+    pub poll_interval: Duration,
+    pub heartbeat_interval: Duration,
+    // claims without a heartbeat for this long are released
+    pub stale_after: Duration,
+}
+
+impl JobSettings {
+    pub fn new(poll_interval: Duration, heartbeat_interval: Duration, stale_after: Duration) -> Result<Self, String> {
+        if stale_after <= heartbeat_interval {
+            return Err(format!("JOB_STALE_SECONDS ({}) must be longer than JOB_HEARTBEAT_SECONDS ({})", stale_after.as_secs(), heartbeat_interval.as_secs()));
+        }
+        Ok(JobSettings { poll_interval, heartbeat_interval, stale_after })
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        let seconds = |name: &str, default: u64| -> Result<Duration, String> {
+            let seconds: u64 = env::var(name).unwrap_or_else(|_| default.to_string())
+                .parse().map_err(|_| format!("Failed to parse {}", name))?;
+            Ok(Duration::from_secs(seconds.max(1)))
+        };
+        Self::new(seconds("JOB_POLL_SECONDS", 5)?, seconds("JOB_HEARTBEAT_SECONDS", 10)?, seconds("JOB_STALE_SECONDS", 60)?)
+    }
+}
+
+// unique per process and worker, WORKER_NAME tells the machines apart in the jobs table
+pub fn worker_id(task_id: i32) -> String {
+    let name = env::var("WORKER_NAME").unwrap_or_else(|_| "worker".to_string());
+    format!("{}-{}-{}", name, std::process::id(), task_id)
+}
+
+// [{"id": 1, "chain": "eth", "pattern": "c0ffee*", "priority": 5, "deadline": 1767225600, "max_results": 2}]
+// deadline is in unix seconds, case_sensitive, priority, deadline and max_results are optional
+pub fn parse_jobs(content: &str) -> Result<Vec<Job>, String> {
+    let value: Value = serde_json::from_str(content).map_err(|e| format!("Invalid json: {}", e))?;
+    let specs = value.as_array().ok_or("Expected a list of jobs")?;
+    specs.iter().enumerate().map(|(index, spec)| {
+        let string = |name: &str| spec.get(name).and_then(Value::as_str).map(str::to_string).ok_or_else(|| format!("job {}: missing \"{}\"", index, name));
+        let integer = |name: &str, default: i64| match spec.get(name) {
+            None => Ok(default),
+            Some(value) => value.as_i64().ok_or_else(|| format!("job {}: \"{}\" must be an integer", index, name)),
+        };
+        let chain = string("chain")?;
+        if !JOB_CHAINS.contains(&chain.as_str()) {
+            return Err(format!("job {}: chain {} is not searched, jobs can be for {}", index, chain, JOB_CHAINS.join(", ")));
+        }
+        let max_results = integer("max_results", 1)?;
+        if max_results < 1 {
+            return Err(format!("job {}: \"max_results\" must be at least 1", index));
+        }
+        Ok(Job {
+            id: spec.get("id").and_then(Value::as_i64).ok_or_else(|| format!("job {}: missing \"id\"", index))?,
+            chain,
+            pattern: string("pattern")?,
+            case_sensitive: match spec.get("case_sensitive") {
+                None => false,
+                Some(value) => value.as_bool().ok_or_else(|| format!("job {}: \"case_sensitive\" must be true or false", index))?,
+            },
+            priority: integer("priority", 0)? as i32,
+            deadline: match spec.get("deadline") {
+                None => None,
+                Some(_) => Some(SystemTime::UNIX_EPOCH + Duration::from_secs(integer("deadline", 0)?.max(0) as u64)),
+            },
+            max_results,
+            found: 0,
+        })
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::jobs_database::{JobQueue, MockDatabase};
+
+    fn job(id: i64, priority: i32, deadline: Option<SystemTime>) -> Job {
+        Job { id, chain: "eth".to_string(), pattern: "dead*".to_string(), case_sensitive: false, priority, deadline, max_results: 2, found: 0 }
+    }
+
+    #[test]
+    fn workers_claim_the_most_urgent_pending_job() {
+        let past = SystemTime::now() - Duration::from_secs(1);
+        let queue = MockDatabase::new(vec![
+            job(1, 0, None),
+            job(2, 5, Some(past)),
+            job(3, 5, None),
+            Job { chain: "btc".to_string(), ..job(4, 9, None) },
+            job(5, 5, None),
+        ]);
+        let claimed: Vec<Option<i64>> = (0..4).map(|worker| queue.claim(&format!("w{}", worker), &["eth"]).unwrap().map(|job| job.id)).collect();
+        assert_eq!(claimed, [Some(3), Some(5), Some(1), None]);
+
+        queue.release_stale(Duration::from_secs(60)).unwrap();
+        assert_eq!(queue.status(2).as_deref(), Some("expired"));
+        assert_eq!(queue.status(4).as_deref(), Some("pending"));
+    }
+
+    #[test]
+    fn stale_claims_go_back_to_pending() {
+        let queue = MockDatabase::new(vec![job(1, 0, None)]);
+        assert_eq!(queue.claim("w0", &["eth"]).unwrap().unwrap().id, 1);
+        assert!(queue.heartbeat(1, "w0").unwrap());
+        assert_eq!(queue.release_stale(Duration::from_secs(60)).unwrap(), 0);
+
+        queue.age_heartbeat(1, Duration::from_secs(61));
+        assert_eq!(queue.release_stale(Duration::from_secs(60)).unwrap(), 1);
+        assert!(!queue.heartbeat(1, "w0").unwrap());
+        assert_eq!(queue.claim("w1", &["eth"]).unwrap().unwrap().id, 1);
+
+        assert_eq!(queue.record_result(1, "w1").unwrap(), Some(1));
+        assert_eq!(queue.status(1).as_deref(), Some("claimed"));
+        // the released claim records nothing
+        assert_eq!(queue.record_result(1, "w0").unwrap(), None);
+        assert_eq!(queue.record_result(1, "w1").unwrap(), Some(2));
+        assert_eq!(queue.status(1).as_deref(), Some("done"));
+        assert!(!queue.heartbeat(1, "w1").unwrap());
+        assert_eq!(queue.record_result(1, "w1").unwrap(), None);
+    }
+
+    #[test]
+    fn jobs_are_read_from_json() {
+        let jobs = parse_jobs(r#"[{"id": 1, "chain": "eth", "pattern": "c0ffee*", "priority": 5, "deadline": 10, "max_results": 3}]"#).unwrap();
+        assert_eq!(jobs[0].priority, 5);
+        assert_eq!(jobs[0].max_results, 3);
+        assert!(jobs[0].is_expired(SystemTime::now()));
+        assert_eq!(jobs[0].order().pattern, "c0ffee*");
+        assert!(parse_jobs(r#"[{"id": 1, "chain": "eth", "pattern": "c0ffee*", "max_results": 0}]"#).is_err());
+        assert_eq!(parse_jobs(r#"[{"id": 1, "chain": "btc", "pattern": "Bob*"}]"#).err().unwrap(), "job 0: chain btc is not searched, jobs can be for eth");
+        assert!(JobSettings::new(Duration::from_secs(5), Duration::from_secs(10), Duration::from_secs(10)).is_err());
+    }
+}
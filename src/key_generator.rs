@@ -0,0 +1,672 @@
+// Common interface over the wallet modules in address.rs so the search loop can pick a strategy
+// at runtime (KEY_GENERATOR env variable) instead of main.rs being edited for every benchmark.
+use crate::address::{eth_wallet, eth_wallet_simple, eth_wallet_simple_u64, eth_wallet_u64_contained, polka_wallet};
+use crate::address::eth_wallet_u64_contained::{Keyspace, KeyspaceShard};
+use crate::checkpoint::WorkerCheckpoint;
+use crate::endomorphism::{self, KeyTransform};
+use crate::keccak_batch;
+use crate::address::secp_context::with_secp;
+use num_bigint::BigUint;
+use secp256k1::{PublicKey, SecretKey};
+use sp_core::Pair;
+use std::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyScheme {
+    // ethereum style secp256k1 keys, address is the 40 hex chars without 0x
+    Secp256k1,
+    // substrate sr25519 keys, address is ss58
+    Sr25519,
+}
+
+#[derive(Debug, Clone)]
+pub enum CandidateSecret {
+    Hex(String),
+    Key(SecretKey),
+    // derived with the endomorphism, the key is only rebuilt when the candidate is a hit
+    Transformed { base: SecretKey, transform: KeyTransform },
+}
+
+impl CandidateSecret {
+    pub fn to_hex(&self) -> String {
+        match self {
+            CandidateSecret::Hex(secret_key) => secret_key.clone(),
+            CandidateSecret::Key(secret_key) => hex::encode(secret_key.secret_bytes()),
+            CandidateSecret::Transformed { base, transform } => hex::encode(transform.apply_to_secret(base).secret_bytes()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub scheme: KeyScheme,
+    pub secret_key: CandidateSecret,
+    pub public_key: String,
+    pub address: String,
+    pub mnemonic: Option<String>,
+}
+
+impl Candidate {
+    pub fn to_eth_wallet(&self) -> Option<eth_wallet::Wallet> {
+        let mut wallet = self.to_eth_wallet_without_secret()?;
+        wallet.secret_key = self.secret_key.to_hex();
+        Some(wallet)
+    }
+
+    // For checking the rules, secret_key is left empty and has to be filled with secret_key.to_hex() for hits
+    pub fn to_eth_wallet_without_secret(&self) -> Option<eth_wallet::Wallet> {
+        if self.scheme != KeyScheme::Secp256k1 {
+            return None;
+        }
+        Some(eth_wallet::Wallet::from_parts(String::new(), self.public_key.clone(), self.address.clone()))
+    }
+
+    pub fn to_polka_wallet(&self) -> Option<polka_wallet::PolkaWallet> {
+        if self.scheme != KeyScheme::Sr25519 {
+            return None;
+        }
+        Some(polka_wallet::PolkaWallet {
+            secret_key: self.secret_key.to_hex(),
+            public_key: self.public_key.clone(),
+            address: self.address.clone(),
+            mnemonic: self.mnemonic.clone().unwrap_or_default(),
+        })
+    }
+}
+
+// Stack only candidate of the secp256k1 generators, the rules run on the address bytes and the strings
+// of a Candidate are only built for hits
+#[derive(Debug, Clone)]
+pub struct RawCandidate {
+    pub secret_key: CandidateSecret,
+    // x || y of the uncompressed public key
+    pub public_key: [u8; 64],
+    pub address: [u8; 20],
+}
+
+impl RawCandidate {
+    pub fn address_hex_bytes(&self) -> [u8; 40] {
+        eth_wallet::address_hex_bytes(&self.address)
+    }
+
+    pub fn to_candidate(&self) -> Candidate {
+        Candidate {
+            scheme: KeyScheme::Secp256k1,
+            secret_key: self.secret_key.clone(),
+            public_key: eth_wallet::compressed_public_key_hex(&self.public_key),
+            address: hex::encode(self.address),
+            mnemonic: None,
+        }
+    }
+
+    // The wallet of a hit, checksummed is the form the rules already computed, if any
+    pub fn to_eth_wallet(&self, checksummed: Option<&[u8; 40]>) -> eth_wallet::Wallet {
+        let wallet = self.to_candidate().to_eth_wallet().unwrap();
+        if let Some(checksummed) = checksummed {
+            wallet.set_address_checksummed(checksummed);
+        }
+        wallet
+    }
+}
+
+pub trait KeyGenerator {
+    fn name(&self) -> &'static str;
+    fn scheme(&self) -> KeyScheme;
+    fn next_candidate(&mut self) -> Result<Candidate, Box<dyn Error>>;
+
+    // None when keep rejects the raw public key, generators that can check it before the
+    // address is encoded skip the encoding then
+    fn next_candidate_if(&mut self, keep: &dyn Fn(&[u8]) -> bool) -> Result<Option<Candidate>, Box<dyn Error>> {
+        let candidate = self.next_candidate()?;
+        let public_key = hex::decode(&candidate.public_key)?;
+        Ok(keep(&public_key).then_some(candidate))
+    }
+
+    // Allocation free path, only some secp256k1 generators have one
+    fn supports_raw_candidates(&self) -> bool {
+        false
+    }
+
+    fn next_raw_candidate(&mut self) -> Result<RawCandidate, Box<dyn Error>> {
+        Err(format!("{} has no raw candidates", self.name()).into())
+    }
+
+    // Generators that walk a disjoint part of a shared keyspace report what they covered
+    fn shard(&self) -> Option<&KeyspaceShard> {
+        None
+    }
+
+    // Sequential generators can be stopped and continued, random ones have nothing to save
+    fn checkpoint(&self, _worker: &WorkerAssignment) -> Option<WorkerCheckpoint> {
+        None
+    }
+
+    fn resume(&mut self, _checkpoint: &WorkerCheckpoint) -> Result<(), String> {
+        Err(format!("{} can not be resumed", self.name()))
+    }
+}
+
+// Which worker a generator runs in, the keyspace is created once and shared by all workers
+#[derive(Debug, Clone, Copy)]
+pub struct WorkerAssignment {
+    pub worker_index: u64,
+    pub worker_count: u64,
+    pub keyspace: Keyspace,
+}
+
+#[cfg(test)]
+impl WorkerAssignment {
+    pub fn single() -> Self {
+        WorkerAssignment { worker_index: 0, worker_count: 1, keyspace: Keyspace::random() }
+    }
+}
+
+// 24 byte prefix followed by the big endian u64, the layout of the u64 generators' secret keys
+fn prefixed_key_hex(prefix: &[u8; 24], counter: u64) -> String {
+    format!("{}{:016x}", hex::encode(prefix), counter)
+}
+
+fn parse_prefixed_key_hex(base: &str) -> Result<([u8; 24], u64), String> {
+    let bytes = hex::decode(base).map_err(|e| format!("Invalid checkpoint base {}: {}", base, e))?;
+    if bytes.len() != 32 {
+        return Err(format!("Invalid checkpoint base {}, expected 32 bytes", base));
+    }
+    Ok((bytes[..24].try_into().unwrap(), u64::from_be_bytes(bytes[24..].try_into().unwrap())))
+}
+
+// Refills pending with the candidates of up to LANES secret keys, their addresses hashed together with
+// keccak_batch. pending is popped from the back, so the candidates are stored reversed to hand them out
+// in the order of the keys. Stops at the first error, which is only returned when no key was taken
+fn fill_pending(pending: &mut Vec<RawCandidate>, mut next_secret_key: impl FnMut() -> Result<SecretKey, Box<dyn Error>>) -> Result<(), Box<dyn Error>> {
+    let mut keys = Vec::with_capacity(keccak_batch::LANES);
+    let mut public_keys = Vec::with_capacity(keccak_batch::LANES);
+    while keys.len() < keccak_batch::LANES {
+        let secret_key = match next_secret_key() {
+            Ok(secret_key) => secret_key,
+            Err(e) if keys.is_empty() => return Err(e),
+            Err(_) => break,
+        };
+        let public_key = with_secp(|secp| PublicKey::from_secret_key(secp, &secret_key));
+        keys.push(secret_key);
+        public_keys.push(public_key.serialize_uncompressed()[1..].try_into().unwrap());
+    }
+    let hashes = keccak_batch::keccak256_many(&public_keys);
+    pending.extend(keys.into_iter().zip(public_keys.iter()).zip(hashes.iter()).rev()
+        .map(|((secret_key, public_key), hash)| RawCandidate {
+            secret_key: CandidateSecret::Key(secret_key),
+            public_key: *public_key,
+            address: hash[12..].try_into().unwrap(),
+        }));
+    Ok(())
+}
+
+fn secp256k1_candidate(secret_key: String, public_key: String, address: String) -> Candidate {
+    Candidate { scheme: KeyScheme::Secp256k1, secret_key: CandidateSecret::Hex(secret_key), public_key, address, mnemonic: None }
+}
+
+// eth_wallet: a fresh random key every time
+pub struct RandomEthGenerator {
+    pending: Vec<RawCandidate>,
+}
+
+impl KeyGenerator for RandomEthGenerator {
+    fn name(&self) -> &'static str {
+        "eth_random"
+    }
+
+    fn scheme(&self) -> KeyScheme {
+        KeyScheme::Secp256k1
+    }
+
+    fn next_candidate(&mut self) -> Result<Candidate, Box<dyn Error>> {
+        Ok(self.next_raw_candidate()?.to_candidate())
+    }
+
+    fn supports_raw_candidates(&self) -> bool {
+        true
+    }
+
+    fn next_raw_candidate(&mut self) -> Result<RawCandidate, Box<dyn Error>> {
+        if self.pending.is_empty() {
+            fill_pending(&mut self.pending, || Ok(SecretKey::new(&mut rand::thread_rng())))?;
+        }
+        Ok(self.pending.pop().unwrap())
+    }
+}
+
+// eth_wallet_simple: random 128 bit start, incremented by one
+pub struct SequentialEthGenerator {
+    generator: eth_wallet_simple::Generator,
+    start: BigUint,
+    covered: u64,
+}
+
+impl KeyGenerator for SequentialEthGenerator {
+    fn name(&self) -> &'static str {
+        "eth_sequential"
+    }
+
+    fn scheme(&self) -> KeyScheme {
+        KeyScheme::Secp256k1
+    }
+
+    fn next_candidate(&mut self) -> Result<Candidate, Box<dyn Error>> {
+        let wallet = eth_wallet_simple::generate_ecdsa_key_pair_from_private_key(self.generator.current_biguint.clone())?;
+        self.generator.increment();
+        self.covered += 1;
+        Ok(secp256k1_candidate(wallet.secret_key, wallet.public_key, wallet.address))
+    }
+
+    fn checkpoint(&self, worker: &WorkerAssignment) -> Option<WorkerCheckpoint> {
+        Some(WorkerCheckpoint {
+            worker_index: worker.worker_index,
+            worker_count: worker.worker_count,
+            base: eth_wallet_simple::parse_hex64_padded(self.start.clone()),
+            covered: self.covered,
+        })
+    }
+
+    fn resume(&mut self, checkpoint: &WorkerCheckpoint) -> Result<(), String> {
+        let start = BigUint::parse_bytes(checkpoint.base.as_bytes(), 16)
+            .ok_or_else(|| format!("Invalid checkpoint base {}", checkpoint.base))?;
+        self.generator.current_biguint = &start + checkpoint.covered;
+        self.start = start;
+        self.covered = checkpoint.covered;
+        Ok(())
+    }
+}
+
+// eth_wallet_simple_u64: random 24 byte prefix, the last 8 bytes are incremented
+pub struct SequentialU64EthGenerator {
+    generator: eth_wallet_simple_u64::Generator,
+    start: u64,
+    // candidates handed out, the generator is ahead of it by the pending ones
+    covered: u64,
+    pending: Vec<RawCandidate>,
+}
+
+impl KeyGenerator for SequentialU64EthGenerator {
+    fn name(&self) -> &'static str {
+        "eth_sequential_u64"
+    }
+
+    fn scheme(&self) -> KeyScheme {
+        KeyScheme::Secp256k1
+    }
+
+    fn next_candidate(&mut self) -> Result<Candidate, Box<dyn Error>> {
+        Ok(self.next_raw_candidate()?.to_candidate())
+    }
+
+    fn supports_raw_candidates(&self) -> bool {
+        true
+    }
+
+    // same key layout as eth_wallet_simple_u64::generate_ecdsa_key_pair_from_private_key
+    fn next_raw_candidate(&mut self) -> Result<RawCandidate, Box<dyn Error>> {
+        if self.pending.is_empty() {
+            let generator = &mut self.generator;
+            fill_pending(&mut self.pending, || {
+                let secret_key = eth_wallet_u64_contained::secret_key_from_counter(&generator.secret_key_prefix, generator.current_u64)?;
+                generator.increment();
+                Ok(secret_key)
+            })?;
+        }
+        self.covered += 1;
+        Ok(self.pending.pop().unwrap())
+    }
+
+    fn checkpoint(&self, worker: &WorkerAssignment) -> Option<WorkerCheckpoint> {
+        Some(WorkerCheckpoint {
+            worker_index: worker.worker_index,
+            worker_count: worker.worker_count,
+            base: prefixed_key_hex(&self.generator.secret_key_prefix, self.start),
+            covered: self.covered,
+        })
+    }
+
+    fn resume(&mut self, checkpoint: &WorkerCheckpoint) -> Result<(), String> {
+        let (prefix, start) = parse_prefixed_key_hex(&checkpoint.base)?;
+        self.generator.current_u64 = start.checked_add(checkpoint.covered)
+            .ok_or_else(|| format!("Checkpoint {} covered {} keys, more than are left after it", checkpoint.base, checkpoint.covered))?;
+        self.generator.secret_key_prefix = prefix;
+        self.start = start;
+        self.covered = checkpoint.covered;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+// eth_wallet_u64_contained: random 24 byte prefix, every worker walks its own counters of the last 8 bytes
+pub struct ContainedU64EthGenerator {
+    // the shard counts the pending candidates as covered already
+    shard: KeyspaceShard,
+    pending: Vec<RawCandidate>,
+}
+
+impl KeyGenerator for ContainedU64EthGenerator {
+    fn name(&self) -> &'static str {
+        "eth_u64_contained"
+    }
+
+    fn scheme(&self) -> KeyScheme {
+        KeyScheme::Secp256k1
+    }
+
+    fn next_candidate(&mut self) -> Result<Candidate, Box<dyn Error>> {
+        Ok(self.next_raw_candidate()?.to_candidate())
+    }
+
+    fn supports_raw_candidates(&self) -> bool {
+        true
+    }
+
+    fn next_raw_candidate(&mut self) -> Result<RawCandidate, Box<dyn Error>> {
+        if self.pending.is_empty() {
+            let shard = &mut self.shard;
+            fill_pending(&mut self.pending, || shard.next_secret_key())?;
+        }
+        Ok(self.pending.pop().unwrap())
+    }
+
+    fn shard(&self) -> Option<&KeyspaceShard> {
+        Some(&self.shard)
+    }
+
+    fn checkpoint(&self, _worker: &WorkerAssignment) -> Option<WorkerCheckpoint> {
+        Some(WorkerCheckpoint {
+            worker_index: self.shard.worker_index,
+            worker_count: self.shard.worker_count,
+            base: prefixed_key_hex(&self.shard.keyspace.prefix, self.shard.keyspace.start),
+            covered: self.shard.covered - self.pending.len() as u64,
+        })
+    }
+
+    // the shard layout depends on the worker count, so only a checkpoint of the same slot fits
+    fn resume(&mut self, checkpoint: &WorkerCheckpoint) -> Result<(), String> {
+        if checkpoint.worker_index != self.shard.worker_index || checkpoint.worker_count != self.shard.worker_count {
+            return Err(format!("Checkpoint of shard {}/{} does not fit shard {}/{}", checkpoint.worker_index, checkpoint.worker_count, self.shard.worker_index, self.shard.worker_count));
+        }
+        let (prefix, start) = parse_prefixed_key_hex(&checkpoint.base)?;
+        self.shard.keyspace = Keyspace { prefix, start };
+        self.shard.covered = checkpoint.covered;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+// eth_wallet with the endomorphism: up to six candidates from every random key
+pub struct EndomorphismEthGenerator {
+    keys_per_point: usize,
+    pending: Vec<RawCandidate>,
+}
+
+impl KeyGenerator for EndomorphismEthGenerator {
+    fn name(&self) -> &'static str {
+        "eth_endomorphism"
+    }
+
+    fn scheme(&self) -> KeyScheme {
+        KeyScheme::Secp256k1
+    }
+
+    fn next_candidate(&mut self) -> Result<Candidate, Box<dyn Error>> {
+        Ok(self.next_raw_candidate()?.to_candidate())
+    }
+
+    fn supports_raw_candidates(&self) -> bool {
+        true
+    }
+
+    fn next_raw_candidate(&mut self) -> Result<RawCandidate, Box<dyn Error>> {
+        if self.pending.is_empty() {
+            let (base, public_key) = with_secp(|secp| secp.generate_keypair(&mut rand::thread_rng()));
+            let points = endomorphism::derived_points(&public_key, self.keys_per_point);
+            let addresses = endomorphism::addresses(&points);
+            // popped from the back, so reversed to hand them out in ALL_TRANSFORMS order
+            self.pending.extend(points.iter().zip(addresses.iter()).rev()
+                .map(|(point, address)| RawCandidate {
+                    secret_key: CandidateSecret::Transformed { base, transform: point.transform },
+                    public_key: point.coordinates,
+                    address: *address,
+                }));
+        }
+        Ok(self.pending.pop().unwrap())
+    }
+}
+
+// polka_wallet: random mnemonic, needs POLKADOT_PASSWORD
+pub struct RandomPolkadotGenerator;
+
+impl KeyGenerator for RandomPolkadotGenerator {
+    fn name(&self) -> &'static str {
+        "polkadot_random"
+    }
+
+    fn scheme(&self) -> KeyScheme {
+        KeyScheme::Sr25519
+    }
+
+    fn next_candidate(&mut self) -> Result<Candidate, Box<dyn Error>> {
+        Ok(polkadot_candidate(polka_wallet::generate_random_wallet()))
+    }
+
+    fn next_candidate_if(&mut self, keep: &dyn Fn(&[u8]) -> bool) -> Result<Option<Candidate>, Box<dyn Error>> {
+        let (pair, mnemonic) = polka_wallet::generate_random_pair();
+        if !keep(pair.public().as_ref()) {
+            return Ok(None);
+        }
+        Ok(Some(polkadot_candidate(polka_wallet::PolkaWallet::new(&pair, &mnemonic))))
+    }
+}
+
+fn polkadot_candidate(wallet: polka_wallet::PolkaWallet) -> Candidate {
+    Candidate {
+        scheme: KeyScheme::Sr25519,
+        secret_key: CandidateSecret::Hex(wallet.secret_key),
+        public_key: wallet.public_key,
+        address: wallet.address,
+        mnemonic: Some(wallet.mnemonic),
+    }
+}
+
+pub const GENERATOR_NAMES: [&str; 6] = ["eth_random", "eth_sequential", "eth_sequential_u64", "eth_u64_contained", "eth_endomorphism", "polkadot_random"];
+
+pub fn generator_by_name(name: &str, worker: &WorkerAssignment) -> Result<Box<dyn KeyGenerator>, String> {
+    match name {
+        "eth_random" => Ok(Box::new(RandomEthGenerator { pending: Vec::new() })),
+        "eth_sequential" => {
+            let generator = eth_wallet_simple::Generator::new();
+            let start = generator.current_biguint.clone();
+            Ok(Box::new(SequentialEthGenerator { generator, start, covered: 0 }))
+        },
+        "eth_sequential_u64" => {
+            let generator = eth_wallet_simple_u64::Generator::new();
+            let start = generator.current_u64;
+            Ok(Box::new(SequentialU64EthGenerator { generator, start, covered: 0, pending: Vec::new() }))
+        },
+        "eth_u64_contained" => Ok(Box::new(ContainedU64EthGenerator {
+            shard: worker.keyspace.shard(worker.worker_index, worker.worker_count),
+            pending: Vec::new(),
+        })),
+        "eth_endomorphism" => {
+            // ENDOMORPHISM_KEYS between 1 and 6, how many of the derived keys are used per point
+            let keys_per_point = std::env::var("ENDOMORPHISM_KEYS")
+                .unwrap_or_else(|_| endomorphism::ALL_TRANSFORMS.len().to_string())
+                .parse::<usize>()
+                .ok()
+                .filter(|n| (1..=endomorphism::ALL_TRANSFORMS.len()).contains(n))
+                .ok_or("ENDOMORPHISM_KEYS must be between 1 and 6")?;
+            Ok(Box::new(EndomorphismEthGenerator { keys_per_point, pending: Vec::new() }))
+        },
+        "polkadot_random" => Ok(Box::new(RandomPolkadotGenerator)),
+        _ => Err(format!("Unknown key generator {}, expected one of {:?}", name, GENERATOR_NAMES)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secp256k1::Secp256k1;
+
+    #[test]
+    fn every_name_resolves() {
+        for name in GENERATOR_NAMES {
+            assert_eq!(generator_by_name(name, &WorkerAssignment::single()).unwrap().name(), name);
+        }
+        assert!(generator_by_name("eth_gpu", &WorkerAssignment::single()).is_err());
+    }
+
+    #[test]
+    fn eth_candidates_match_their_secret_key() {
+        let secp = Secp256k1::new();
+        for name in GENERATOR_NAMES.iter().filter(|name| name.starts_with("eth")) {
+            let mut generator = generator_by_name(name, &WorkerAssignment::single()).unwrap();
+            assert_eq!(generator.scheme(), KeyScheme::Secp256k1);
+            for _ in 0..20 {
+                let candidate = generator.next_candidate().unwrap();
+                let secret_key = SecretKey::from_slice(&hex::decode(candidate.secret_key.to_hex()).unwrap()).unwrap();
+                let expected = eth_wallet::Wallet::new(&secret_key, &PublicKey::from_secret_key(&secp, &secret_key));
+                let wallet = candidate.to_eth_wallet().unwrap();
+                assert_eq!(wallet.address, expected.address, "{}", name);
+                assert_eq!(wallet.address_checksummed(), expected.address_checksummed(), "{}", name);
+                assert!(candidate.to_polka_wallet().is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn sequential_generators_step_by_one() {
+        for name in ["eth_sequential", "eth_sequential_u64"] {
+            let mut generator = generator_by_name(name, &WorkerAssignment::single()).unwrap();
+            let first = generator.next_candidate().unwrap();
+            let second = generator.next_candidate().unwrap();
+            let first_low = u64::from_str_radix(&first.secret_key.to_hex()[48..], 16).unwrap();
+            let second_low = u64::from_str_radix(&second.secret_key.to_hex()[48..], 16).unwrap();
+            assert_eq!(second_low, first_low.wrapping_add(1), "{}", name);
+        }
+    }
+
+    #[test]
+    fn batched_generators_keep_the_key_order() {
+        let worker = WorkerAssignment { worker_index: 1, worker_count: 3, keyspace: Keyspace::random() };
+        for (name, step) in [("eth_sequential_u64", 1), ("eth_u64_contained", 3)] {
+            let mut generator = generator_by_name(name, &worker).unwrap();
+            let counters: Vec<u64> = (0..2 * keccak_batch::LANES + 1)
+                .map(|_| u64::from_str_radix(&generator.next_raw_candidate().unwrap().secret_key.to_hex()[48..], 16).unwrap())
+                .collect();
+            for pair in counters.windows(2) {
+                assert_eq!(pair[1], pair[0].wrapping_add(step), "{}", name);
+            }
+            assert_eq!(generator.checkpoint(&worker).unwrap().covered, counters.len() as u64, "{}", name);
+        }
+
+        // a shard with two keys left hands out both before it reports the exhaustion
+        let mut generator = generator_by_name("eth_u64_contained", &worker).unwrap();
+        let checkpoint = generator.checkpoint(&worker).unwrap();
+        generator.resume(&WorkerCheckpoint { covered: u64::MAX / 3 - 2, ..checkpoint }).unwrap();
+        assert!(generator.next_raw_candidate().is_ok());
+        assert_eq!(generator.checkpoint(&worker).unwrap().covered, u64::MAX / 3 - 1);
+        assert!(generator.next_raw_candidate().is_ok());
+        assert!(generator.next_raw_candidate().unwrap_err().is::<eth_wallet_u64_contained::ShardExhausted>());
+        assert_eq!(generator.checkpoint(&worker).unwrap().covered, u64::MAX / 3);
+    }
+
+    #[test]
+    fn resumed_generators_continue_after_the_checkpoint() {
+        let worker = WorkerAssignment { worker_index: 1, worker_count: 2, keyspace: Keyspace::random() };
+        for name in ["eth_sequential", "eth_sequential_u64", "eth_u64_contained"] {
+            let mut generator = generator_by_name(name, &worker).unwrap();
+            for _ in 0..5 {
+                generator.next_candidate().unwrap();
+            }
+            let checkpoint = generator.checkpoint(&worker).unwrap();
+            assert_eq!(checkpoint.covered, 5);
+            let expected = generator.next_candidate().unwrap();
+
+            // a fresh process starts somewhere random
+            let other_worker = WorkerAssignment { keyspace: Keyspace::random(), ..worker };
+            let mut resumed = generator_by_name(name, &other_worker).unwrap();
+            resumed.resume(&checkpoint).unwrap();
+            assert_eq!(resumed.next_candidate().unwrap().secret_key.to_hex(), expected.secret_key.to_hex(), "{}", name);
+            assert_eq!(resumed.checkpoint(&worker).unwrap().covered, 6);
+        }
+
+        // a counter past the last key of the u64 space
+        let mut sequential = generator_by_name("eth_sequential_u64", &worker).unwrap();
+        let past_the_end = WorkerCheckpoint { worker_index: 1, worker_count: 2, base: format!("{}{:016x}", "01".repeat(24), u64::MAX - 1), covered: 2 };
+        assert!(sequential.resume(&past_the_end).err().unwrap().contains("covered 2 keys"));
+        assert!(sequential.resume(&WorkerCheckpoint { covered: 1, ..past_the_end }).is_ok());
+
+        let mut random = generator_by_name("eth_random", &worker).unwrap();
+        assert!(random.checkpoint(&worker).is_none());
+        assert!(random.resume(&WorkerCheckpoint { worker_index: 1, worker_count: 2, base: "01".repeat(32), covered: 0 }).is_err());
+    }
+
+    #[test]
+    fn endomorphism_hands_out_six_keys_per_point() {
+        let mut generator = generator_by_name("eth_endomorphism", &WorkerAssignment::single()).unwrap();
+        let candidates: Vec<Candidate> = (0..12).map(|_| generator.next_candidate().unwrap()).collect();
+        let bases: Vec<SecretKey> = candidates.iter().map(|candidate| match candidate.secret_key {
+            CandidateSecret::Transformed { base, .. } => base,
+            _ => panic!("endomorphism candidates defer the secret key"),
+        }).collect();
+        assert!(bases[..6].iter().all(|base| *base == bases[0]));
+        assert!(bases[6..].iter().all(|base| *base == bases[6]));
+        assert_ne!(bases[0], bases[6]);
+
+        let unresolved = candidates[1].to_eth_wallet_without_secret().unwrap();
+        assert!(unresolved.secret_key.is_empty());
+        assert_eq!(candidates[1].to_eth_wallet().unwrap().address, unresolved.address);
+    }
+
+    #[test]
+    fn raw_candidates_match_the_string_candidates() {
+        let secp = Secp256k1::new();
+        for name in ["eth_random", "eth_sequential_u64", "eth_u64_contained", "eth_endomorphism"] {
+            let mut generator = generator_by_name(name, &WorkerAssignment::single()).unwrap();
+            assert!(generator.supports_raw_candidates(), "{}", name);
+            for _ in 0..10 {
+                let raw = generator.next_raw_candidate().unwrap();
+                let candidate = raw.to_candidate();
+                assert_eq!(&raw.address_hex_bytes()[..], candidate.address.as_bytes());
+
+                let secret_key = SecretKey::from_slice(&hex::decode(candidate.secret_key.to_hex()).unwrap()).unwrap();
+                let public_key = PublicKey::from_secret_key(&secp, &secret_key);
+                assert_eq!(candidate.public_key, public_key.to_string(), "{}", name);
+                assert_eq!(candidate.address, eth_wallet::Wallet::new(&secret_key, &public_key).address, "{}", name);
+            }
+        }
+
+        let mut sequential = generator_by_name("eth_sequential", &WorkerAssignment::single()).unwrap();
+        assert!(!sequential.supports_raw_candidates());
+        assert!(sequential.next_raw_candidate().is_err());
+    }
+
+    #[test]
+    fn raw_wallets_keep_the_checksummed_address() {
+        let raw = generator_by_name("eth_random", &WorkerAssignment::single()).unwrap().next_raw_candidate().unwrap();
+        assert!(raw.to_eth_wallet(None).known_address_checksummed().is_none());
+
+        let expected = raw.to_candidate().to_eth_wallet().unwrap().address_checksummed().clone();
+        let mut checksummed = [0u8; 40];
+        checksummed.copy_from_slice(expected.as_bytes());
+        assert_eq!(raw.to_eth_wallet(Some(&checksummed)).known_address_checksummed(), Some(&expected));
+    }
+
+    #[test]
+    fn polkadot_candidates_are_filtered_by_their_public_key() {
+        if std::env::var("POLKADOT_PASSWORD").is_err() {
+            std::env::set_var("POLKADOT_PASSWORD", "");
+        }
+        let mut generator = generator_by_name("polkadot_random", &WorkerAssignment::single()).unwrap();
+        assert!(generator.next_candidate_if(&|_| false).unwrap().is_none());
+
+        let candidate = generator.next_candidate_if(&|public_key| public_key.len() == 32).unwrap().unwrap();
+        assert!(candidate.to_eth_wallet().is_none());
+        let wallet = candidate.to_polka_wallet().unwrap();
+        assert_eq!(wallet.address, candidate.address);
+        assert_eq!(wallet.mnemonic.split_whitespace().count(), 12);
+    }
+}
@@ -1,24 +1,55 @@
 #[warn(unused_imports)]
 #[warn(dead_code)]
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
 use std::env;
+use std::thread;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
 use crate::vanity_generator::VanityResult;
 use crate::vanity_generator::PolkadotVanityResult;
-use crate::address_utils::address_utils::calculate_proximity_coefficient;
-use crate::address_utils::address_utils::calculate_entropy;
 use rayon::prelude::*;
 use dotenv::dotenv;
 
 mod address;
+mod address_manager;
 mod vanity_generator;
 mod database;
 mod address_utils;
 mod percentile_heap;
+mod base58_prefix;
+mod prefix_feasibility;
+mod polkadot_keystore;
+mod eth_keystore;
+mod watch_only;
+mod key_generator;
+mod checkpoint;
+mod endomorphism;
+mod keccak_batch;
+mod rule_config;
+mod rule_expr;
+mod rule_script;
+mod word_matcher;
+mod orders;
+mod jobs;
+mod swappable;
 
 
 fn main() {
     dotenv().ok();
 
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 1 && args[1] == "export-polkadot" {
+        export_polkadot(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "export-eth" {
+        export_eth(&args[2..]);
+        return;
+    }
+    if args.len() > 1 && args[1] == "watch-only" {
+        run_watch_only(&args[2..]);
+        return;
+    }
 
     let process_count = env::var("PROCESS_COUNT")
     .unwrap_or_else(|_| "1".to_string())
@@ -29,26 +60,308 @@ fn main() {
     });
     println!("Starting {} processes", process_count);
 
+    let search_mode = env::var("SEARCH_MODE").unwrap_or_else(|_| "polkadot".to_string());
+    println!("Search mode: {}", search_mode);
+    // every other mode searches polkadot addresses
+    let chain = if matches!(search_mode.as_str(), "eth" | "eth_mnemonic" | "jobs") { "eth" } else { "polkadot" };
+    match rule_config::active_rule_set(chain) {
+        Ok(rule_set) => {
+            println!("Rule profile {}: {} {} rules, version {}", rule_set.profile, rule_set.entries.len(), rule_set.chain, rule_set.version);
+            for entry in rule_set.entries.iter() {
+                if let Some(probability) = entry.rule.probability() {
+                    println!("Rule {}: probability {:.3e}, expected attempts {:.3e}", entry.id, probability, 1.0 / probability);
+                } else if let Some(bound) = entry.rule.probability_below() {
+                    println!("Rule {}: probability below {:.3e}, expected attempts above {:.3e}", entry.id, bound, 1.0 / bound);
+                }
+            }
+            if chain == "polkadot" {
+                print_polkadot_start_reports(&rule_set);
+                if polkadot_prefix_only() {
+                    if let Err(e) = vanity_generator::PolkadotPrefixRules::new(&rule_set) {
+                        println!("{}", e);
+                        return;
+                    }
+                }
+            }
+        }
+        Err(e) => {
+            println!("Invalid rules: {}", e);
+            return;
+        }
+    }
+    // the built-in rules never change
+    if let (Some(dir), Some(interval)) = (rule_config::rules_dir(), rule_config::reload_interval()) {
+        println!("Checking {} for rule changes every {} seconds", dir.display(), interval.as_secs());
+        let active: &'static rule_config::ActiveRuleSet = if chain == "eth" { &vanity_generator::ETH_RULES } else { &vanity_generator::POLKADOT_RULES };
+        rule_config::watch(active, interval);
+    }
+    if search_mode == "eth" {
+        match eth_db().read_open_orders("eth") {
+            Ok(open_orders) => {
+                orders::print_rejected(&orders::ETH_ORDERS.replace(open_orders));
+                println!("Searching for {} open eth orders", orders::ETH_ORDERS.current().orders.len());
+            }
+            Err(e) => println!("Searching without orders, reading them failed: {}", e),
+        }
+        if let Some(interval) = orders::reload_interval() {
+            let db = eth_db();
+            orders::watch(&orders::ETH_ORDERS, interval, move |chain| db.read_open_orders(chain));
+        }
+    }
+    // jobs mode takes its patterns from the jobs table instead of the env
+    let job_queue = if search_mode == "jobs" {
+        let settings = match jobs::JobSettings::from_env() {
+            Ok(settings) => settings,
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        };
+        match job_queue() {
+            Some(queue) => Some((queue, settings)),
+            None => return,
+        }
+    } else {
+        None
+    };
+    if search_mode == "eth" {
+        let profile = search_profile();
+        let coverage = checkpoint::CheckpointStore::from_env().coverage(&profile);
+        println!("Profile {}: {} keys covered in {} worker checkpoints", profile, coverage.covered, coverage.checkpoints);
+    }
+
+    // created once so that sharded key generators split the same keyspace between the workers
+    let keyspace = address::eth_wallet_u64_contained::Keyspace::random();
     (0..process_count).into_par_iter().for_each(|task_id| {
-        // run_vanity(task_id);
-        run_vanity_polkadot(task_id);
+        let worker = key_generator::WorkerAssignment {
+            worker_index: task_id as u64,
+            worker_count: process_count as u64,
+            keyspace,
+        };
+        match search_mode.as_str() {
+            "eth" => run_vanity(task_id, &worker),
+            "eth_mnemonic" => run_vanity_mnemonic(task_id),
+            "jobs" => {
+                let (queue, settings) = job_queue.as_ref().unwrap();
+                run_jobs(task_id, &worker, queue.as_ref(), settings)
+            },
+            _ => run_vanity_polkadot(task_id, &worker),
+        }
     });
 }
 
 
 
-fn run_vanity_polkadot(task_id: i32) {
-
-    println!("Process {}: Vanity Generaor started!", task_id);
-
+fn polkadot_db() -> Box<dyn database::polkadot_database::DatabaseHandler> {
     let environment = std::env::var("ENVIRONMENT").unwrap_or_else(|_| "localdev".to_string());
-    let db: Box<dyn database::polkadot_database::DatabaseHandler> = if environment == "prod" {
+    if environment == "prod" {
         println!("Using prod database");
         Box::new(database::polkadot_database::RealDatabase)
     } else {
         println!("Using mock database");
         Box::new(database::polkadot_database::MockDatabase)
+    }
+}
+
+// watch-only <xpub> <eth|btc> [start_index]
+// Scans the non hardened children of the xpub with PROCESS_COUNT workers and prints the first matching index
+fn run_watch_only(args: &[String]) {
+    if args.len() < 2 {
+        println!("Usage: watch-only <xpub> <eth|btc> [start_index]");
+        return;
+    }
+    let xpub: watch_only::ExtendedPubKey = match args[0].parse() {
+        Ok(xpub) => xpub,
+        Err(e) => {
+            println!("Invalid xpub: {}", e);
+            return;
+        }
+    };
+    let chain: watch_only::Chain = match args[1].parse() {
+        Ok(chain) => chain,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let rule_chain = match chain {
+        watch_only::Chain::Eth => "eth",
+        watch_only::Chain::Btc => "btc",
+    };
+    if let Err(e) = rule_config::active_rule_set(rule_chain) {
+        println!("Invalid rules: {}", e);
+        return;
+    }
+    let start_index: u32 = match args.get(2).map(|s| s.parse()) {
+        None => 0,
+        Some(Ok(start_index)) if start_index < watch_only::HARDENED_OFFSET => start_index,
+        Some(_) => {
+            println!("Invalid start_index {}, expected a number below {}", args[2], watch_only::HARDENED_OFFSET);
+            return;
+        }
     };
+    let process_count: u32 = env::var("PROCESS_COUNT")
+        .unwrap_or_else(|_| "1".to_string())
+        .parse()
+        .unwrap_or(1)
+        .max(1);
+    println!("Scanning {:?} children of xpub at depth {} from index {} with {} processes", chain, xpub.depth, start_index, process_count);
+
+    let start = Instant::now();
+    let best = std::sync::atomic::AtomicU32::new(u32::MAX);
+    let hit = (0..process_count).into_par_iter()
+        .filter_map(|task_id| {
+            let rule = |address: &String| watch_only::matched_rule(chain, address);
+            watch_only::scan(&xpub, chain, start_index.saturating_add(task_id), watch_only::HARDENED_OFFSET, process_count, &best, rule)
+        })
+        .min_by_key(|hit| hit.index);
+
+    match hit {
+        Some(hit) => println!("Found {} in {:.2} seconds", hit, start.elapsed().as_secs_f64()),
+        None => println!("No matching index below {}", watch_only::HARDENED_OFFSET),
+    }
+}
+
+fn keystore_password() -> Option<String> {
+    // typed without echo
+    let password = env::var("KEYSTORE_PASSWORD").unwrap_or_else(|_| {
+        rpassword::prompt_password("Keystore password: ").expect("Failed to read password")
+    });
+    if password.is_empty() {
+        println!("Refusing to export with an empty password");
+        return None;
+    }
+    Some(password)
+}
+
+fn eth_db() -> Box<dyn database::database::DatabaseHandler + Send> {
+    let environment = std::env::var("ENVIRONMENT").unwrap_or_else(|_| "localdev".to_string());
+    if environment == "prod" {
+        println!("Using prod database");
+        Box::new(database::database::RealDatabase)
+    } else {
+        println!("Using mock database");
+        Box::new(database::database::MockDatabase)
+    }
+}
+
+// shared by all workers of the process, the mock queue only lives in memory
+fn job_queue() -> Option<Box<dyn database::jobs_database::JobQueue + Send + Sync>> {
+    let environment = std::env::var("ENVIRONMENT").unwrap_or_else(|_| "localdev".to_string());
+    if environment == "prod" {
+        println!("Using prod job queue");
+        return Some(Box::new(database::jobs_database::RealDatabase));
+    }
+    println!("Using mock job queue");
+    match database::jobs_database::MockDatabase::from_env() {
+        Ok(queue) => Some(Box::new(queue)),
+        Err(e) => {
+            println!("Invalid jobs: {}", e);
+            None
+        }
+    }
+}
+
+// export-eth <public_address> <output_file> [scrypt|pbkdf2]
+// password is taken from KEYSTORE_PASSWORD or read from stdin
+fn export_eth(args: &[String]) {
+    if args.len() < 2 {
+        println!("Usage: export-eth <public_address> <output_file> [scrypt|pbkdf2]");
+        return;
+    }
+    let kdf = match args.get(2).map(|s| s.as_str()) {
+        None | Some("scrypt") => eth_keystore::Kdf::scrypt(),
+        Some("pbkdf2") => eth_keystore::Kdf::pbkdf2(),
+        Some(other) => {
+            println!("Unknown kdf {}, expected scrypt or pbkdf2", other);
+            return;
+        }
+    };
+
+    let secret_key = match eth_db().read_private_key(&args[0]) {
+        Ok(Some(secret_key)) => secret_key,
+        Ok(None) => {
+            println!("No wallet found for {}", args[0]);
+            return;
+        },
+        Err(e) => {
+            println!("Error reading from DB: {}", e);
+            return;
+        }
+    };
+
+    let password = match keystore_password() {
+        Some(password) => password,
+        None => return,
+    };
+
+    match eth_keystore::export_secret_key(&secret_key, &password, &kdf, &args[1]) {
+        Ok(address) => println!("Exported 0x{} to {}", address, args[1]),
+        Err(e) => println!("Error exporting {}: {}", args[0], e),
+    }
+}
+
+// export-polkadot <public_address> <output_file> [genesis_hash] [name]
+// password is taken from KEYSTORE_PASSWORD or read from stdin
+fn export_polkadot(args: &[String]) {
+    if args.len() < 2 {
+        println!("Usage: export-polkadot <public_address> <output_file> [genesis_hash] [name]");
+        return;
+    }
+    let options = polkadot_keystore::KeystoreOptions {
+        genesis_hash: args.get(2).cloned(),
+        name: args.get(3).cloned(),
+    };
+
+    let secret_key = match polkadot_db().read_private_key(&args[0]) {
+        Ok(Some(secret_key)) => secret_key,
+        Ok(None) => {
+            println!("No wallet found for {}", args[0]);
+            return;
+        },
+        Err(e) => {
+            println!("Error reading from DB: {}", e);
+            return;
+        }
+    };
+
+    let password = match keystore_password() {
+        Some(password) => password,
+        None => return,
+    };
+
+    match polkadot_keystore::export_secret_key(&secret_key, &password, &options, &args[1]) {
+        Ok(address) => println!("Exported {} to {}", address, args[1]),
+        Err(e) => println!("Error exporting {}: {}", args[0], e),
+    }
+}
+
+// how likely the starts of the start rules are, the ss58 network prefix fixes the first chars
+fn print_polkadot_start_reports(rule_set: &vanity_generator::RuleSet) {
+    let layout = address::polka_wallet::ss58_payload_layout();
+    for entry in rule_set.entries.iter() {
+        if let Some((prefixes, case_sensitive)) = entry.rule.start_prefixes(base58_prefix::BASE58_ALPHABET) {
+            let prefixes: Vec<&str> = prefixes.iter().map(|prefix| prefix.as_str()).collect();
+            for report in prefix_feasibility::check_prefixes(&layout, &prefixes, case_sensitive).iter().filter(|report| report.is_possible()) {
+                println!("Rule {}: start {}", entry.id, report);
+            }
+        }
+    }
+}
+
+// only check the start rules, skipping the ss58 encoding for keys that can not match them
+fn polkadot_prefix_only() -> bool {
+    env::var("POLKADOT_PREFIX_ONLY").map(|v| v == "true").unwrap_or(false)
+}
+
+fn run_vanity_polkadot(task_id: i32, worker: &key_generator::WorkerAssignment) {
+
+    println!("Process {}: Vanity Generaor started!", task_id);
+
+    let Some(mut generator) = polkadot_generator(task_id, worker) else {
+        return;
+    };
+    let db = polkadot_db();
 
     let mut loop_counter = 0;
     let mut total_adresses_searched = 0;
@@ -62,6 +375,12 @@ fn run_vanity_polkadot(task_id: i32) {
         }); 
     println!("Process {}: Efficiency count set to: {}", task_id, efficiency_count);
 
+    let mut rules = vanity_generator::POLKADOT_RULES.cache();
+    let prefix_only = polkadot_prefix_only();
+    println!("Process {}: Prefix only search: {}", task_id, prefix_only);
+    // built from the current rules, again after a reload changed them
+    let mut prefix_rules: Option<vanity_generator::PolkadotPrefixRules> = None;
+
     let mut wallet_creation_time: i64 = 0;
     let mut vanity_check_time: i64 = 0;
     let mut before_wallet: Instant = Instant::now();
@@ -71,12 +390,33 @@ fn run_vanity_polkadot(task_id: i32) {
         while loop_counter < efficiency_count {
 
             before_wallet = Instant::now();
-            let wallet = address::polka_wallet::generate_random_wallet();
+            let rule_set = rules.get();
+            if prefix_only && prefix_rules.as_ref().is_none_or(|prefix_rules| prefix_rules.version != rule_set.version) {
+                match vanity_generator::PolkadotPrefixRules::new(rule_set) {
+                    Ok(new_rules) => prefix_rules = Some(new_rules),
+                    Err(e) => {
+                        println!("Process {}: {}, stopping", task_id, e);
+                        return;
+                    }
+                }
+            }
+            let candidate = match &prefix_rules {
+                Some(prefix_rules) => generator.next_candidate_if(&|public_key| prefix_rules.could_match(public_key)),
+                None => generator.next_candidate().map(Some),
+            };
             after_wallet = Instant::now();
-            
-            let vanity_result: PolkadotVanityResult = vanity_generator::does_polkadot_address_meet_criteria(&wallet);
+            let wallet = match candidate {
+                Ok(candidate) => candidate.map(|candidate| candidate.to_polka_wallet().unwrap()),
+                Err(e) => {
+                    println!("Process {}: Key generator error: {}", task_id, e);
+                    return;
+                }
+            };
+            let vanity_result: Option<PolkadotVanityResult> = wallet.map(|wallet| {
+                vanity_generator::does_polkadot_address_meet_criteria(&wallet, rule_set, prefix_rules.as_ref())
+            });
             after_vanity = Instant::now();
-            if vanity_result.met_criteria {
+            if let Some(vanity_result) = vanity_result.filter(|r| r.met_criteria) {
                 println!("Process {}: {} - {:?} - Entropy {:.2}, Proximity {:.2}", task_id, vanity_result.wallet.address, vanity_result.matched_rule, &vanity_result.entropy_coefficient, &vanity_result.proximity_coefficient);
                 match db.write_eth_wallet(&vanity_result) {
                     Ok(_) => {
@@ -103,18 +443,69 @@ fn run_vanity_polkadot(task_id: i32) {
 }
 
 
-fn run_vanity(task_id: i32) {
+fn key_generator_name() -> String {
+    env::var("KEY_GENERATOR").unwrap_or_else(|_| "eth_random".to_string())
+}
+
+// checkpoints and coverage are kept per profile, by default one profile per key generator
+fn search_profile() -> String {
+    env::var("SEARCH_PROFILE").unwrap_or_else(|_| key_generator_name())
+}
+
+fn polkadot_generator(task_id: i32, worker: &key_generator::WorkerAssignment) -> Option<Box<dyn key_generator::KeyGenerator>> {
+    let name = env::var("KEY_GENERATOR").unwrap_or_else(|_| "polkadot_random".to_string());
+    match key_generator::generator_by_name(&name, worker) {
+        Ok(generator) if generator.scheme() == key_generator::KeyScheme::Sr25519 => Some(generator),
+        Ok(generator) => {
+            println!("Process {}: Key generator {} does not produce polkadot keys", task_id, generator.name());
+            None
+        },
+        Err(e) => {
+            println!("Process {}: {}", task_id, e);
+            None
+        }
+    }
+}
+
+fn eth_generator(task_id: i32, worker: &key_generator::WorkerAssignment) -> Option<Box<dyn key_generator::KeyGenerator>> {
+    let generator = match key_generator::generator_by_name(&key_generator_name(), worker) {
+        Ok(generator) if generator.scheme() == key_generator::KeyScheme::Secp256k1 => generator,
+        Ok(generator) => {
+            println!("Process {}: Key generator {} does not produce eth keys", task_id, generator.name());
+            return None;
+        },
+        Err(e) => {
+            println!("Process {}: {}", task_id, e);
+            return None;
+        }
+    };
+    println!("Process {}: Key generator: {}, raw candidates: {}", task_id, generator.name(), generator.supports_raw_candidates());
+    Some(generator)
+}
+
+fn run_vanity(task_id: i32, worker: &key_generator::WorkerAssignment) {
 
     println!("Process {}: Vanity Generaor started!", task_id);
 
-    let environment = std::env::var("ENVIRONMENT").unwrap_or_else(|_| "localdev".to_string());
-    let db: Box<dyn database::database::DatabaseHandler> = if environment == "prod" {
-        println!("Using prod database");
-        Box::new(database::database::RealDatabase)
-    } else {
-        println!("Using mock database");
-        Box::new(database::database::MockDatabase)
+    let db = eth_db();
+
+    let mut generator = match eth_generator(task_id, worker) {
+        Some(generator) => generator,
+        None => return,
     };
+    let raw_candidates = generator.supports_raw_candidates();
+
+    let profile = search_profile();
+    let checkpoints = checkpoint::CheckpointStore::from_env();
+    if let Some(saved) = checkpoints.load(&profile, worker.worker_index, worker.worker_count) {
+        match generator.resume(&saved) {
+            Ok(_) => println!("Process {}: Resumed {} from {} after {} keys", task_id, profile, saved.base, saved.covered),
+            Err(e) => println!("Process {}: Not resuming {}: {}", task_id, profile, e),
+        }
+    }
+
+    let mut rules = vanity_generator::ETH_RULES.cache();
+    let mut open_orders = orders::ETH_ORDERS.cache();
 
     let mut loop_counter = 0;
     let mut total_adresses_searched = 0;
@@ -133,20 +524,80 @@ fn run_vanity(task_id: i32) {
     let mut before_wallet: Instant = Instant::now();
     let mut after_wallet: Instant = Instant::now();
     let mut after_vanity: Instant = Instant::now();
+    let mut exhausted = false;
     loop {
         while loop_counter < efficiency_count {
 
             before_wallet = Instant::now();
-            let wallet = address::eth_wallet::generate_random_wallet();
-            after_wallet = Instant::now();
-            
-            let vanity_result: VanityResult = vanity_generator::does_address_meet_criteria(&wallet);
+            let rule_set = rules.get();
+            let order_book = open_orders.get();
+            let vanity_result: Option<VanityResult> = if raw_candidates {
+                let raw = match generator.next_raw_candidate() {
+                    Ok(raw) => raw,
+                    Err(e) if is_exhausted(e.as_ref()) => {
+                        println!("Process {}: {}, stopping", task_id, e);
+                        exhausted = true;
+                        break;
+                    },
+                    Err(e) => {
+                        println!("Process {}: Error generating key: {}", task_id, e);
+                        continue;
+                    }
+                };
+                let address_hex = raw.address_hex_bytes();
+                after_wallet = Instant::now();
+
+                // the Wallet and its strings only exist for hits, the checksum only for rules that need it
+                let address = vanity_generator::EthAddressBytes::new(&address_hex);
+                let order_id = order_book.find_eth(&address).map(|order| order.id);
+                (order_id.is_some() || address.any_match(rule_set)).then(|| {
+                    let mut vanity_result = vanity_generator::does_address_meet_rules(&raw.to_eth_wallet(address.computed_checksummed()), rule_set);
+                    vanity_result.order_id = order_id;
+                    vanity_result
+                })
+            } else {
+                let candidate = match generator.next_candidate() {
+                    Ok(candidate) => candidate,
+                    Err(e) if is_exhausted(e.as_ref()) => {
+                        println!("Process {}: {}, stopping", task_id, e);
+                        exhausted = true;
+                        break;
+                    },
+                    Err(e) => {
+                        println!("Process {}: Error generating key: {}", task_id, e);
+                        continue;
+                    }
+                };
+                let wallet = candidate.to_eth_wallet_without_secret().unwrap();
+                after_wallet = Instant::now();
+
+                let address = vanity_generator::EthAddressBytes::new(wallet.address.as_bytes());
+                let order_id = order_book.find_eth(&address).map(|order| order.id);
+                if let Some(checksummed) = address.computed_checksummed() {
+                    wallet.set_address_checksummed(checksummed);
+                }
+                Some(vanity_generator::does_address_meet_rules(&wallet, rule_set))
+                    .map(|mut vanity_result| {
+                        vanity_result.order_id = order_id;
+                        vanity_result
+                    })
+                    .filter(|vanity_result| vanity_result.met_criteria || vanity_result.order_id.is_some())
+                    .map(|mut vanity_result| {
+                        vanity_result.wallet.secret_key = candidate.secret_key.to_hex();
+                        vanity_result
+                    })
+            };
             after_vanity = Instant::now();
-            if vanity_result.met_criteria {
-                println!("Process {}: {} - {:?} - Entropy {:.2}, Proximity {:.2}, CS Entropy {:.2}, CS Proximity {:.2}", task_id, vanity_result.wallet.address_checksummed, vanity_result.matched_rule, &vanity_result.entropy_coefficient, &vanity_result.proximity_coefficient, &vanity_result.entropy_coefficient_checksummed, &vanity_result.proximity_coefficient_checksummed);
+            if let Some(vanity_result) = vanity_result {
+                if let Some(order_id) = vanity_result.order_id {
+                    write_order_hit(task_id, db.as_ref(), order_id, &vanity_result);
+                    loop_counter += 1;
+                    continue;
+                }
+                println!("Process {}: {} - {:?} - Entropy {:.2}, Proximity {:.2}, CS Entropy {:.2}, CS Proximity {:.2}", task_id, vanity_result.wallet.address_checksummed(), vanity_result.matched_rule, &vanity_result.entropy_coefficient, &vanity_result.proximity_coefficient, &vanity_result.entropy_coefficient_checksummed, &vanity_result.proximity_coefficient_checksummed);
                 match db.write_eth_wallet(&vanity_result) {
                     Ok(_) => {
-                        println!("Process {}: Wrote to DB {}", task_id, vanity_result.wallet.address_checksummed);
+                        println!("Process {}: Wrote to DB {}", task_id, vanity_result.wallet.address_checksummed());
                     },
                     Err(e) => {
                         println!("Process {}: Error writing to DB: {}", task_id, e);
@@ -161,8 +612,268 @@ fn run_vanity(task_id: i32) {
         wallet_creation_time = 0;
         vanity_check_time = 0;
 
-        total_adresses_searched += efficiency_count;
-        println!("Process {}: Total searched {} addresses. Loops per second: {}", task_id, total_adresses_searched, efficiency_count as f64 / start.elapsed().as_secs_f64());
+        total_adresses_searched += loop_counter;
+        println!("Process {}: Total searched {} addresses. Loops per second: {}", task_id, total_adresses_searched, loop_counter as f64 / start.elapsed().as_secs_f64());
+        if let Some(shard) = generator.shard() {
+            println!("Process {}: Shard {}/{} covered {} keys, next counter {:?}", task_id, shard.worker_index, shard.worker_count, shard.covered, shard.next_counter());
+        }
+        if let Some(position) = generator.checkpoint(worker) {
+            if let Err(e) = checkpoints.save(&profile, &position) {
+                println!("Process {}: Error writing checkpoint: {}", task_id, e);
+            }
+        }
+        if exhausted {
+            return;
+        }
+        loop_counter = 0;
+        start = Instant::now();
+    }
+}
+
+
+// Key errors only skip the candidate, except an exhausted shard which has no keys left to search
+fn is_exhausted(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.is::<address::eth_wallet_u64_contained::ShardExhausted>()
+}
+
+// Stores the wallet and closes the order together, the order only leaves the search once both
+// are written. A failed write leaves the order open, so a later hit can still fulfil it.
+fn write_order_hit(task_id: i32, db: &dyn database::database::DatabaseHandler, order_id: i64, vanity_result: &VanityResult) {
+    println!("Process {}: {} - order {} - {:?}", task_id, vanity_result.wallet.address_checksummed(), order_id, vanity_result.matched_rule);
+    match db.write_order_wallet(vanity_result, order_id) {
+        Ok(true) => {
+            orders::ETH_ORDERS.remove(order_id);
+            println!("Process {}: Order {} fulfilled by {}", task_id, order_id, vanity_result.wallet.address_checksummed());
+        },
+        Ok(false) => {
+            orders::ETH_ORDERS.remove(order_id);
+            println!("Process {}: Order {} was already fulfilled", task_id, order_id);
+        },
+        Err(e) => {
+            println!("Process {}: Error writing order {} to DB, it stays open: {}", task_id, order_id, e);
+        }
+    }
+}
+
+// candidates between two checks of the deadline and the claim of a job, the heartbeat has its own timer
+const JOB_BATCH: usize = 10_000;
+
+// Claims jobs one after the other, releasing the stale claims of other workers before each claim
+fn run_jobs(task_id: i32, worker: &key_generator::WorkerAssignment, queue: &(dyn database::jobs_database::JobQueue + Sync), settings: &jobs::JobSettings) {
+    let worker_id = jobs::worker_id(task_id);
+    println!("Process {}: Job worker {} started!", task_id, worker_id);
+
+    let db = eth_db();
+    let mut generator = match eth_generator(task_id, worker) {
+        Some(generator) => generator,
+        None => return,
+    };
+
+    loop {
+        match queue.release_stale(settings.stale_after) {
+            Ok(0) => {},
+            Ok(released) => println!("Process {}: Released {} stale job claims", task_id, released),
+            Err(e) => println!("Process {}: Error releasing stale job claims: {}", task_id, e),
+        }
+        match queue.claim(&worker_id, jobs::JOB_CHAINS) {
+            Ok(Some(job)) => {
+                if run_job(task_id, &worker_id, &job, generator.as_mut(), queue, db.as_ref(), settings) == JobEnd::Exhausted {
+                    return;
+                }
+            },
+            Ok(None) => thread::sleep(settings.poll_interval),
+            Err(e) => {
+                println!("Process {}: Error claiming a job: {}", task_id, e);
+                thread::sleep(settings.poll_interval);
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum JobEnd {
+    // the worker can go on with the next job
+    Stopped,
+    // the key generator has no keys left, the job stays claimed until its heartbeats go stale
+    Exhausted,
+}
+
+// Searches one claimed job until it has max_results, expires or the claim is lost, heartbeating
+// the claim from a second thread meanwhile
+fn run_job(task_id: i32, worker_id: &str, job: &jobs::Job, generator: &mut dyn key_generator::KeyGenerator, queue: &(dyn database::jobs_database::JobQueue + Sync), db: &dyn database::database::DatabaseHandler, settings: &jobs::JobSettings) -> JobEnd {
+    let (book, rejected) = orders::OrderBook::new(&job.chain, vec![job.order()]);
+    if let Some((_, e)) = rejected.first() {
+        println!("Process {}: Job {} is invalid: {}", task_id, job.id, e);
+        if let Err(e) = queue.finish(job.id, worker_id, "invalid") {
+            println!("Process {}: Error finishing job {}: {}", task_id, job.id, e);
+        }
+        return JobEnd::Stopped;
+    }
+    println!("Process {}: Searching job {} for {} (priority {}, {} of {} found)", task_id, job.id, job.pattern, job.priority, job.found, job.max_results);
+
+    let lost = AtomicBool::new(false);
+    let (stop, stopped) = mpsc::channel::<()>();
+    thread::scope(|scope| {
+        let lost = &lost;
+        scope.spawn(move || send_heartbeats(task_id, worker_id, job.id, queue, settings.heartbeat_interval, stopped, lost));
+        // dropped on every return of the search, which stops the heartbeats
+        let _stop = stop;
+
+        let raw_candidates = generator.supports_raw_candidates();
+        let mut found = job.found;
+        let mut searched: u64 = 0;
+        let start = Instant::now();
+        loop {
+            for _ in 0..JOB_BATCH {
+                let wallet = if raw_candidates {
+                    let raw = match generator.next_raw_candidate() {
+                        Ok(raw) => raw,
+                        Err(e) if is_exhausted(e.as_ref()) => {
+                            println!("Process {}: {}, stopping job {}", task_id, e, job.id);
+                            return JobEnd::Exhausted;
+                        },
+                        Err(e) => {
+                            println!("Process {}: Error generating key: {}", task_id, e);
+                            continue;
+                        }
+                    };
+                    let address_hex = raw.address_hex_bytes();
+                    let address = vanity_generator::EthAddressBytes::new(&address_hex);
+                    book.find_eth(&address).map(|_| raw.to_eth_wallet(address.computed_checksummed()))
+                } else {
+                    let candidate = match generator.next_candidate() {
+                        Ok(candidate) => candidate,
+                        Err(e) if is_exhausted(e.as_ref()) => {
+                            println!("Process {}: {}, stopping job {}", task_id, e, job.id);
+                            return JobEnd::Exhausted;
+                        },
+                        Err(e) => {
+                            println!("Process {}: Error generating key: {}", task_id, e);
+                            continue;
+                        }
+                    };
+                    let wallet = candidate.to_eth_wallet_without_secret().unwrap();
+                    let address = vanity_generator::EthAddressBytes::new(wallet.address.as_bytes());
+                    let found = book.find_eth(&address).is_some();
+                    if let Some(checksummed) = address.computed_checksummed() {
+                        wallet.set_address_checksummed(checksummed);
+                    }
+                    found.then_some(wallet).map(|mut wallet| {
+                        wallet.secret_key = candidate.secret_key.to_hex();
+                        wallet
+                    })
+                };
+                searched += 1;
+                let wallet = match wallet {
+                    Some(wallet) => wallet,
+                    None => continue,
+                };
+
+                // counted before the wallet is stored, so that a worker that lost its claim can not
+                // store results past max_results of a job another worker searches now
+                match queue.record_result(job.id, worker_id) {
+                    Ok(Some(count)) => found = count,
+                    Ok(None) => {
+                        println!("Process {}: Lost the claim on job {}, it is searched elsewhere now", task_id, job.id);
+                        return JobEnd::Stopped;
+                    },
+                    // whether the claim is still ours is unknown, the job goes back to pending once its heartbeats stop
+                    Err(e) => {
+                        println!("Process {}: Error recording a result of job {}, stopping the job: {}", task_id, job.id, e);
+                        return JobEnd::Stopped;
+                    },
+                }
+                let mut vanity_result = vanity_generator::does_address_meet_rules(&wallet, &vanity_generator::ETH_RULES.current());
+                vanity_result.job_id = Some(job.id);
+                if let Err(e) = db.write_eth_wallet(&vanity_result) {
+                    println!("Process {}: Error writing result {} of job {} to DB: {}", task_id, found, job.id, e);
+                }
+                println!("Process {}: Job {} result {} of {}: {}", task_id, job.id, found, job.max_results, wallet.address_checksummed());
+                if found >= job.max_results {
+                    println!("Process {}: Job {} done after {} addresses in {:.2} seconds", task_id, job.id, searched, start.elapsed().as_secs_f64());
+                    return JobEnd::Stopped;
+                }
+            }
+
+            if job.is_expired(SystemTime::now()) {
+                println!("Process {}: Job {} expired with {} of {} results", task_id, job.id, found, job.max_results);
+                if let Err(e) = queue.finish(job.id, worker_id, "expired") {
+                    println!("Process {}: Error finishing job {}: {}", task_id, job.id, e);
+                }
+                return JobEnd::Stopped;
+            }
+            if lost.load(Ordering::Relaxed) {
+                println!("Process {}: Lost the claim on job {}, it is searched elsewhere now", task_id, job.id);
+                return JobEnd::Stopped;
+            }
+        }
+    })
+}
+
+// Until the search stops and drops its sender, or the claim is lost
+fn send_heartbeats(task_id: i32, worker_id: &str, job_id: i64, queue: &dyn database::jobs_database::JobQueue, interval: Duration, stopped: mpsc::Receiver<()>, lost: &AtomicBool) {
+    while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+        match queue.heartbeat(job_id, worker_id) {
+            Ok(true) => {},
+            Ok(false) => {
+                lost.store(true, Ordering::Relaxed);
+                return;
+            },
+            // the claim may still be ours, tried again on the next beat
+            Err(e) => println!("Process {}: Error sending the heartbeat of job {}: {}", task_id, job_id, e),
+        }
+    }
+}
+
+fn run_vanity_mnemonic(task_id: i32) {
+
+    println!("Process {}: Mnemonic Vanity Generaor started!", task_id);
+
+    let db = eth_db();
+    let config = match address_manager::HdSearchConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            println!("Process {}: {}", task_id, e);
+            return;
+        }
+    };
+    println!("Process {}: Checking {} indices of {} per mnemonic", task_id, config.index_count, config.base_path);
+
+    let mut loop_counter = 0;
+    let mut total_adresses_searched = 0;
+    let mut start = Instant::now();
+    let efficiency_count = env::var("EFFICIENCY_COUNT")
+        .unwrap_or_else(|_| "1000000".to_string())
+        .parse()
+        .unwrap_or_else(|_| {
+            println!("Failed to parse EFFICIENCY_COUNT, defaulting to 1000000");
+            1000000
+        }); 
+    println!("Process {}: Efficiency count set to: {}", task_id, efficiency_count);
+
+    let secp = secp256k1::Secp256k1::new();
+    loop {
+        while loop_counter < efficiency_count {
+            let mnemonic = address_manager::generate_mnemonic(&config);
+            let candidates = address_manager::derive_eth_wallets(&mnemonic, &config, &secp);
+
+            for mnemonic_result in vanity_generator::does_mnemonic_meet_criteria(mnemonic.phrase(), &candidates, &config) {
+                let vanity_result = &mnemonic_result.result;
+                println!("Process {}: {} - {:?} - Index {} - Entropy {:.2}, Proximity {:.2}", task_id, vanity_result.wallet.address_checksummed(), vanity_result.matched_rule, mnemonic_result.index, &vanity_result.entropy_coefficient, &vanity_result.proximity_coefficient);
+                match db.write_mnemonic_wallet(&mnemonic_result) {
+                    Ok(_) => {
+                        println!("Process {}: Wrote to DB {}", task_id, vanity_result.wallet.address_checksummed());
+                    },
+                    Err(e) => {
+                        println!("Process {}: Error writing to DB: {}", task_id, e);
+                    }
+                }
+            }
+            loop_counter += candidates.len();
+        }
+
+        total_adresses_searched += loop_counter;
+        println!("Process {}: Total searched {} addresses. Loops per second: {}", task_id, total_adresses_searched, loop_counter as f64 / start.elapsed().as_secs_f64());
         loop_counter = 0;
         start = Instant::now();
     }
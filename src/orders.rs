@@ -0,0 +1,324 @@
+// Customer orders searched next to the rules. Each order wants one address of its chain with a
+// pattern of <start>*<end>, either side may be empty. The words of all open orders of a chain
+// are compiled into one WordMatcher, so every candidate is checked against all orders in one pass.
+// A hit goes to the oldest open order it satisfies, which is then fulfilled and leaves the book.
+use crate::rule_config;
+use crate::vanity_generator::EthAddressBytes;
+use crate::word_matcher::{Anchor, WordMatcher};
+use lazy_static::lazy_static;
+use serde_json::Value;
+use std::collections::HashSet;
+use crate::swappable::{Cached, Swappable};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// the chains workers search orders for, orders of others are rejected
+pub const ORDER_CHAINS: &[&str] = &["eth"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub id: i64,
+    pub customer: String,
+    pub chain: String,
+    pub pattern: String,
+    // eth orders with a case sensitive pattern match the checksummed address
+    pub case_sensitive: bool,
+}
+
+impl Order {
+    // (start, end) of the pattern
+    pub fn words(&self) -> Result<(&str, &str), String> {
+        let (start, end) = self.pattern.split_once('*').ok_or_else(|| format!("pattern {} has no '*' between start and end", self.pattern))?;
+        if end.contains('*') {
+            return Err(format!("pattern {} has more than one '*'", self.pattern));
+        }
+        if start.is_empty() && end.is_empty() {
+            return Err("pattern has no start and no end".to_string());
+        }
+        Ok((start, end))
+    }
+
+    fn check(&self, chain: &str) -> Result<(), String> {
+        let (start, end) = self.words()?;
+        for word in [start, end].into_iter().filter(|word| !word.is_empty()) {
+            rule_config::check_pattern_word(chain, word, self.case_sensitive)?;
+        }
+        if start.len() + end.len() > rule_config::min_address_length(chain)? {
+            return Err(format!("pattern {} is longer than a {} address", self.pattern, chain));
+        }
+        Ok(())
+    }
+
+    // address is checked case insensitively, cased for case sensitive orders
+    fn is_met_by(&self, address_no_prefix: &[u8], cased: &[u8]) -> bool {
+        let (start, end) = self.words().expect("orders are checked when the book is built");
+        let address = if self.case_sensitive { cased } else { address_no_prefix };
+        let (start, end) = (start.as_bytes(), end.as_bytes());
+        if address.len() < start.len() + end.len() {
+            return false;
+        }
+        if self.case_sensitive {
+            address.starts_with(start) && address.ends_with(end)
+        } else {
+            address[..start.len()].eq_ignore_ascii_case(start) && address[address.len() - end.len()..].eq_ignore_ascii_case(end)
+        }
+    }
+}
+
+pub struct OrderBook {
+    // oldest first
+    pub orders: Vec<Order>,
+    // target id -> index into orders
+    targets: Vec<usize>,
+    matcher: WordMatcher,
+}
+
+impl OrderBook {
+    // Keeps the valid open orders of the chain, the invalid ones are returned as (id, error)
+    pub fn new(chain: &str, orders: Vec<Order>) -> (Self, Vec<(i64, String)>) {
+        let mut rejected = Vec::new();
+        let mut valid: Vec<Order> = Vec::new();
+        for order in orders.into_iter().filter(|order| order.chain == chain) {
+            match order.check(chain) {
+                Ok(()) => valid.push(order),
+                Err(e) => rejected.push((order.id, e)),
+            }
+        }
+        valid.sort_by_key(|order| order.id);
+
+        let mut words: Vec<(Anchor, &str)> = Vec::new();
+        let mut targets = Vec::new();
+        for (index, order) in valid.iter().enumerate() {
+            let (start, end) = order.words().unwrap();
+            for (anchor, word) in [(Anchor::Start, start), (Anchor::End, end)] {
+                if !word.is_empty() {
+                    words.push((anchor, word));
+                    targets.push(index);
+                }
+            }
+        }
+        // case sensitive orders are checked exactly once one of their words matched
+        let matcher = WordMatcher::new(&words, false);
+        (OrderBook { orders: valid, targets, matcher }, rejected)
+    }
+
+    pub fn empty(chain: &str) -> Self {
+        OrderBook::new(chain, Vec::new()).0
+    }
+
+    // the oldest order the address satisfies, cased is only called for case sensitive orders
+    fn find_with<'a>(&self, address_no_prefix: &'a [u8], cased: impl Fn() -> &'a [u8]) -> Option<&Order> {
+        let mut candidates: Vec<usize> = Vec::new();
+        self.matcher.for_each_match(address_no_prefix, |id| candidates.push(self.targets[id]));
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates.into_iter()
+            .map(|index| &self.orders[index])
+            .find(|order| order.is_met_by(address_no_prefix, if order.case_sensitive { cased() } else { address_no_prefix }))
+    }
+
+    // for chains without a checksum the address is also the cased form
+    #[cfg(test)]
+    pub fn find(&self, address_no_prefix: &[u8]) -> Option<&Order> {
+        self.find_with(address_no_prefix, || address_no_prefix)
+    }
+
+    pub fn find_eth(&self, address: &EthAddressBytes) -> Option<&Order> {
+        self.find_with(address.address_no_prefix(), || &address.checksummed()[..])
+    }
+}
+
+// The open orders the workers search for, swapped as a whole like the ActiveRuleSet
+pub struct ActiveOrders {
+    chain: String,
+    book: Swappable<OrderBook>,
+    // fulfilled here, kept out even if a reload still lists them as open
+    removed: Mutex<HashSet<i64>>,
+}
+
+impl ActiveOrders {
+    pub fn new(chain: &str) -> Self {
+        ActiveOrders {
+            chain: chain.to_string(),
+            book: Swappable::new(OrderBook::empty(chain)),
+            removed: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn current(&self) -> Arc<OrderBook> {
+        self.book.current()
+    }
+
+    pub fn cache(&self) -> Cached<'_, OrderBook> {
+        self.book.cache()
+    }
+
+    // Replaces the book when the open order ids changed, returns the rejected orders
+    pub fn replace(&self, orders: Vec<Order>) -> Vec<(i64, String)> {
+        let mut removed = self.removed.lock().unwrap();
+        // orders that are no longer open can not come back, only the ones still listed are kept out
+        removed.retain(|id| orders.iter().any(|order| order.id == *id));
+        let orders = orders.into_iter().filter(|order| !removed.contains(&order.id)).collect();
+        let (book, rejected) = OrderBook::new(&self.chain, orders);
+        let ids = |book: &OrderBook| book.orders.iter().map(|order| order.id).collect::<Vec<i64>>();
+        self.book.update(|current| (ids(&book) != ids(current)).then_some(book));
+        rejected
+    }
+
+    // Takes a fulfilled order out of the live matcher, false if it was not in the book
+    pub fn remove(&self, order_id: i64) -> bool {
+        self.removed.lock().unwrap().insert(order_id);
+        self.book.update(|current| {
+            current.orders.iter().any(|order| order.id == order_id).then(|| {
+                let orders = current.orders.iter().filter(|order| order.id != order_id).cloned().collect();
+                OrderBook::new(&self.chain, orders).0
+            })
+        })
+    }
+}
+
+lazy_static! {
+    pub static ref ETH_ORDERS: ActiveOrders = ActiveOrders::new("eth");
+}
+
+// ORDERS_RELOAD_SECONDS, 0 turns reloading off
+pub fn reload_interval() -> Option<Duration> {
+    let seconds: u64 = std::env::var("ORDERS_RELOAD_SECONDS").ok().and_then(|s| s.parse().ok()).unwrap_or(30);
+    if seconds == 0 { None } else { Some(Duration::from_secs(seconds)) }
+}
+
+pub fn print_rejected(rejected: &[(i64, String)]) {
+    for (id, e) in rejected {
+        println!("Skipping order {}: {}", id, e);
+    }
+}
+
+// Reads the open orders every interval, new orders join the search and cancelled ones leave it
+pub fn watch<F>(active: &'static ActiveOrders, interval: Duration, read_open_orders: F)
+where
+    F: Fn(&str) -> Result<Vec<Order>, String> + Send + 'static,
+{
+    thread::spawn(move || {
+        // an invalid order is only reported once, not on every reload
+        let mut reported: Vec<(i64, String)> = Vec::new();
+        loop {
+            thread::sleep(interval);
+            match read_open_orders(&active.chain) {
+                Ok(orders) => {
+                    let rejected = active.replace(orders);
+                    print_rejected(&rejected.iter().filter(|order| !reported.contains(order)).cloned().collect::<Vec<_>>());
+                    reported = rejected;
+                }
+                Err(e) => println!("Keeping the open {} orders, reading them failed: {}", active.chain, e),
+            }
+        }
+    });
+}
+
+// [{"id": 1, "customer": "acme", "chain": "eth", "pattern": "c0ffee*", "case_sensitive": false}]
+pub fn parse_orders(content: &str) -> Result<Vec<Order>, String> {
+    let value: Value = serde_json::from_str(content).map_err(|e| format!("Invalid json: {}", e))?;
+    let specs = value.as_array().ok_or("Expected a list of orders")?;
+    specs.iter().enumerate().map(|(index, spec)| {
+        let field = |name: &str| spec.get(name).and_then(Value::as_str).map(str::to_string).ok_or_else(|| format!("order {}: missing \"{}\"", index, name));
+        let chain = field("chain")?;
+        if !ORDER_CHAINS.contains(&chain.as_str()) {
+            return Err(format!("order {}: chain {} is not searched, orders can be for {}", index, chain, ORDER_CHAINS.join(", ")));
+        }
+        Ok(Order {
+            id: spec.get("id").and_then(Value::as_i64).ok_or_else(|| format!("order {}: missing \"id\"", index))?,
+            customer: field("customer")?,
+            chain,
+            pattern: field("pattern")?,
+            case_sensitive: match spec.get("case_sensitive") {
+                None => false,
+                Some(value) => value.as_bool().ok_or_else(|| format!("order {}: \"case_sensitive\" must be true or false", index))?,
+            },
+        })
+    }).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: i64, chain: &str, pattern: &str, case_sensitive: bool) -> Order {
+        Order { id, customer: format!("customer{}", id), chain: chain.to_string(), pattern: pattern.to_string(), case_sensitive }
+    }
+
+    #[test]
+    fn hits_go_to_the_oldest_matching_order() {
+        let (book, rejected) = OrderBook::new("eth", vec![
+            order(7, "eth", "dead*", false),
+            order(3, "eth", "*beef", false),
+            order(5, "eth", "dead*beef", false),
+            order(9, "btc", "Bob*", true),
+        ]);
+        assert!(rejected.is_empty());
+        assert_eq!(book.orders.iter().map(|order| order.id).collect::<Vec<i64>>(), [3, 5, 7]);
+        assert_eq!(book.find(b"dead00000000000000000000000000000000beef").unwrap().id, 3);
+        assert_eq!(book.find(b"DEAD000000000000000000000000000000000000").unwrap().id, 7);
+        assert_eq!(book.find(b"0000000000000000000000000000000000000000"), None);
+
+        let (btc, _) = OrderBook::new("btc", vec![order(9, "btc", "Bob*", true), order(10, "btc", "*xyz", false)]);
+        assert_eq!(btc.find(b"Bob1111111111111111111111111").unwrap().id, 9);
+        assert_eq!(btc.find(b"bob1111111111111111111111111"), None);
+        assert_eq!(btc.find(b"111111111111111111111111XyZ").unwrap().id, 10);
+    }
+
+    #[test]
+    fn case_sensitive_eth_orders_match_the_checksummed_address() {
+        let wallet = crate::address::eth_wallet::generate_random_wallet();
+        let checksummed = wallet.address_checksummed().to_string();
+        let (book, _) = OrderBook::new("eth", vec![order(1, "eth", &format!("{}*", &checksummed[..6]), true)]);
+        assert_eq!(book.find_eth(&EthAddressBytes::new(wallet.address.as_bytes())).unwrap().id, 1);
+
+        let flipped: String = checksummed[..6].chars().map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() }).collect();
+        if flipped != checksummed[..6] {
+            let (book, _) = OrderBook::new("eth", vec![order(1, "eth", &format!("{}*", flipped), true)]);
+            assert_eq!(book.find_eth(&EthAddressBytes::new(wallet.address.as_bytes())), None);
+        }
+    }
+
+    #[test]
+    fn invalid_orders_are_rejected() {
+        let (book, rejected) = OrderBook::new("eth", vec![
+            order(1, "eth", "dead", false),
+            order(2, "eth", "*", false),
+            order(3, "eth", "xyz*", false),
+            order(4, "eth", "a*b*c", false),
+            order(5, "eth", &format!("{}*{}", "a".repeat(30), "b".repeat(11)), false),
+            order(6, "eth", "c0ffee*", false),
+        ]);
+        assert_eq!(rejected.iter().map(|(id, _)| *id).collect::<Vec<i64>>(), [1, 2, 3, 4, 5]);
+        assert_eq!(book.orders.len(), 1);
+        assert!(parse_orders(r#"[{"id": 1, "customer": "acme", "chain": "eth"}]"#).unwrap_err().contains("missing \"pattern\""));
+        assert_eq!(parse_orders(r#"[{"id": 1, "customer": "acme", "chain": "btc", "pattern": "Bob*"}]"#).unwrap_err(), "order 0: chain btc is not searched, orders can be for eth");
+        assert_eq!(parse_orders(r#"[{"id": 1, "customer": "acme", "chain": "eth", "pattern": "c0ffee*"}]"#).unwrap(), vec![Order {
+            id: 1, customer: "acme".to_string(), chain: "eth".to_string(), pattern: "c0ffee*".to_string(), case_sensitive: false,
+        }]);
+    }
+
+    #[test]
+    fn fulfilled_orders_leave_every_cache() {
+        let active = ActiveOrders::new("eth");
+        let mut cache = active.cache();
+        assert!(cache.get().orders.is_empty());
+        active.replace(vec![order(1, "eth", "dead*", false), order(2, "eth", "*beef", false)]);
+        let address = b"dead00000000000000000000000000000000beef";
+        assert_eq!(cache.get().find(address).unwrap().id, 1);
+        assert!(active.remove(1));
+        assert!(!active.remove(1));
+        assert_eq!(cache.get().find(address).unwrap().id, 2);
+        assert!(active.remove(2));
+        assert_eq!(cache.get().find(address), None);
+        active.replace(vec![order(1, "eth", "dead*", false), order(3, "eth", "dead*", false)]);
+        assert_eq!(cache.get().find(address).unwrap().id, 3);
+        // once the database no longer lists them, the removed ids are forgotten
+        assert_eq!(active.removed.lock().unwrap().iter().copied().collect::<Vec<i64>>(), [1]);
+        active.replace(vec![order(3, "eth", "dead*", false)]);
+        assert!(active.removed.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+// polkadot-js compatible json for sr25519 accounts, the format the browser extension imports.
+// encoded = salt(32) || N || p || r (u32 le each) || nonce(24) || secretbox(pkcs8 key)
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use crypto_secretbox::aead::{Aead, KeyInit};
+use crypto_secretbox::{Key, Nonce, XSalsa20Poly1305};
+use rand::RngCore;
+use serde_json::{json, Value};
+use sp_core::crypto::Ss58Codec;
+use sp_core::{sr25519, Pair};
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PKCS8_HEADER: [u8; 16] = [48, 83, 2, 1, 1, 48, 5, 6, 3, 43, 101, 112, 4, 34, 4, 32];
+const PKCS8_DIVIDER: [u8; 5] = [161, 35, 3, 33, 0];
+
+// polkadot-js refuses any other scrypt parameters
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_P: u32 = 1;
+const SCRYPT_R: u32 = 8;
+const SALT_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 24;
+const SECRET_KEY_LENGTH: usize = 64;
+const PUBLIC_KEY_LENGTH: usize = 32;
+
+#[derive(Debug, Default, Clone)]
+pub struct KeystoreOptions {
+    pub name: Option<String>,
+    // restricts the account to one chain in the extension, e.g. 0x91b171bb158e2d3848fa23a9f1c25182fb8e20313b2c1eb49219da7a70ce90c3 for Polkadot
+    pub genesis_hash: Option<String>,
+}
+
+fn scrypt_key(password: &str, salt: &[u8]) -> Result<[u8; 32], Box<dyn Error>> {
+    let params = scrypt::Params::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)?;
+    let mut derived = [0u8; 64];
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut derived)?;
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&derived[..32]);
+    Ok(key)
+}
+
+fn pkcs8_encode(pair: &sr25519::Pair) -> Vec<u8> {
+    // polkadot-js keeps the secret in the ed25519 compatible form schnorrkel calls half ed25519
+    let keypair: &schnorrkel::Keypair = pair.as_ref();
+    let half_ed25519 = keypair.to_half_ed25519_bytes();
+    let mut encoded = PKCS8_HEADER.to_vec();
+    encoded.extend_from_slice(&half_ed25519[..SECRET_KEY_LENGTH]);
+    encoded.extend_from_slice(&PKCS8_DIVIDER);
+    encoded.extend_from_slice(&half_ed25519[SECRET_KEY_LENGTH..]);
+    encoded
+}
+
+fn pkcs8_decode(encoded: &[u8]) -> Result<sr25519::Pair, Box<dyn Error>> {
+    let secret_start = PKCS8_HEADER.len();
+    let divider_start = secret_start + SECRET_KEY_LENGTH;
+    let public_start = divider_start + PKCS8_DIVIDER.len();
+    if encoded.len() != public_start + PUBLIC_KEY_LENGTH
+        || encoded[..secret_start] != PKCS8_HEADER
+        || encoded[divider_start..public_start] != PKCS8_DIVIDER {
+        return Err("Invalid pkcs8 content".into());
+    }
+    let secret = schnorrkel::SecretKey::from_ed25519_bytes(&encoded[secret_start..divider_start])
+        .map_err(|e| format!("Invalid secret key: {:?}", e))?;
+    let pair = sr25519::Pair::from(secret.to_keypair());
+    if pair.public().0[..] != encoded[public_start..] {
+        return Err("Public key does not match the secret key".into());
+    }
+    Ok(pair)
+}
+
+pub fn encrypt_pair(pair: &sr25519::Pair, password: &str, options: &KeystoreOptions) -> Result<Value, Box<dyn Error>> {
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let key = scrypt_key(password, &salt)?;
+    let cipher = XSalsa20Poly1305::new(&Key::from(key));
+    let encrypted = cipher.encrypt(&Nonce::from(nonce), pkcs8_encode(pair).as_slice())
+        .map_err(|_| "Encryption failed")?;
+
+    let mut encoded = salt.to_vec();
+    encoded.extend_from_slice(&(1u32 << SCRYPT_LOG_N).to_le_bytes());
+    encoded.extend_from_slice(&SCRYPT_P.to_le_bytes());
+    encoded.extend_from_slice(&SCRYPT_R.to_le_bytes());
+    encoded.extend_from_slice(&nonce);
+    encoded.extend_from_slice(&encrypted);
+
+    let when_created = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    let mut meta = json!({ "whenCreated": when_created });
+    if let Some(name) = &options.name {
+        meta["name"] = json!(name);
+    }
+    if let Some(genesis_hash) = &options.genesis_hash {
+        meta["genesisHash"] = json!(genesis_hash);
+    }
+
+    Ok(json!({
+        "encoded": BASE64.encode(encoded),
+        "encoding": {
+            "content": ["pkcs8", "sr25519"],
+            "type": ["scrypt", "xsalsa20-poly1305"],
+            "version": "3"
+        },
+        "address": pair.public().to_ss58check(),
+        "meta": meta
+    }))
+}
+
+pub fn decrypt_pair(keystore: &Value, password: &str) -> Result<sr25519::Pair, Box<dyn Error>> {
+    let encoding_type = &keystore["encoding"]["type"];
+    if encoding_type != &json!(["scrypt", "xsalsa20-poly1305"]) {
+        return Err(format!("Unsupported encoding {}", encoding_type).into());
+    }
+    let encoded = BASE64.decode(keystore["encoded"].as_str().ok_or("Missing encoded field")?)?;
+    let header_length = SALT_LENGTH + 12;
+    if encoded.len() < header_length + NONCE_LENGTH {
+        return Err("Encoded field is too short".into());
+    }
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(encoded[offset..offset + 4].try_into().unwrap());
+    if read_u32(SALT_LENGTH) != 1 << SCRYPT_LOG_N || read_u32(SALT_LENGTH + 4) != SCRYPT_P || read_u32(SALT_LENGTH + 8) != SCRYPT_R {
+        return Err("Unsupported scrypt parameters".into());
+    }
+
+    let key = scrypt_key(password, &encoded[..SALT_LENGTH])?;
+    let cipher = XSalsa20Poly1305::new(&Key::from(key));
+    let nonce: [u8; NONCE_LENGTH] = encoded[header_length..header_length + NONCE_LENGTH].try_into()?;
+    let decrypted = cipher.decrypt(&Nonce::from(nonce), &encoded[header_length + NONCE_LENGTH..])
+        .map_err(|_| "Wrong password or corrupted keystore")?;
+    pkcs8_decode(&decrypted)
+}
+
+// secret_key_hex is what the polkadot table stores, PolkaWallet::secret_key. The keystore is
+// decrypted again before it is written, a file that does not give back the key is never written
+pub fn export_secret_key(secret_key_hex: &str, password: &str, options: &KeystoreOptions, path: &str) -> Result<String, Box<dyn Error>> {
+    let pair = sr25519::Pair::from_seed_slice(&hex::decode(secret_key_hex)?)
+        .map_err(|e| format!("Invalid secret key: {:?}", e))?;
+    let keystore = encrypt_pair(&pair, password, options)?;
+    if decrypt_pair(&keystore, password)?.to_raw_vec() != pair.to_raw_vec() {
+        return Err("The keystore does not decrypt to the secret key".into());
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&keystore)?)?;
+    Ok(pair.public().to_ss58check())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keystore_roundtrip() {
+        let pair = sr25519::Pair::from_seed(&[3u8; 32]);
+        let options = KeystoreOptions {
+            name: Some("vanity".to_string()),
+            genesis_hash: Some("0x91b171bb158e2d3848fa23a9f1c25182fb8e20313b2c1eb49219da7a70ce90c3".to_string()),
+        };
+        let keystore = encrypt_pair(&pair, "password", &options).unwrap();
+
+        assert_eq!(keystore["address"], json!(pair.public().to_ss58check()));
+        assert_eq!(keystore["encoding"]["content"], json!(["pkcs8", "sr25519"]));
+        assert_eq!(keystore["meta"]["name"], json!("vanity"));
+        assert_eq!(keystore["meta"]["genesisHash"], json!(options.genesis_hash.unwrap()));
+
+        let decrypted = decrypt_pair(&keystore, "password").unwrap();
+        assert_eq!(decrypted.public(), pair.public());
+        assert_eq!(decrypted.to_raw_vec(), pair.to_raw_vec());
+        assert!(decrypt_pair(&keystore, "wrong").is_err());
+    }
+
+    #[test]
+    fn pkcs8_layout() {
+        let pair = sr25519::Pair::from_seed(&[9u8; 32]);
+        let encoded = pkcs8_encode(&pair);
+        assert_eq!(encoded.len(), 117);
+        assert_eq!(&encoded[85..], pair.public().as_ref() as &[u8]);
+        assert_eq!(pkcs8_decode(&encoded).unwrap().public(), pair.public());
+    }
+
+    #[test]
+    fn exported_keystore_decrypts_to_the_secret_key() {
+        let path = std::env::temp_dir().join(format!("vanity-keystore-{}.json", rand::random::<u64>()));
+        let secret_key = hex::encode([7u8; 32]);
+        let address = export_secret_key(&secret_key, "password", &KeystoreOptions::default(), path.to_str().unwrap()).unwrap();
+        let keystore: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(keystore["address"], json!(address));
+        assert_eq!(decrypt_pair(&keystore, "password").unwrap().public(), sr25519::Pair::from_seed(&[7u8; 32]).public());
+        assert!(export_secret_key("00", "password", &KeystoreOptions::default(), path.to_str().unwrap()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
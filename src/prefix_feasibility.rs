@@ -0,0 +1,118 @@
+use crate::base58_prefix::{self, PayloadLayout, BASE58_ALPHABET};
+use num_bigint::BigUint;
+use num_traits::{One, ToPrimitive};
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum Feasibility {
+    Impossible(String),
+    // probability that a random key produces the pattern, expected attempts = 1 / probability
+    Possible(f64),
+}
+
+#[derive(Debug)]
+pub struct PatternReport {
+    pub pattern: String,
+    pub feasibility: Feasibility,
+}
+
+impl PatternReport {
+    pub fn is_possible(&self) -> bool {
+        matches!(self.feasibility, Feasibility::Possible(_))
+    }
+}
+
+impl fmt::Display for PatternReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.feasibility {
+            Feasibility::Impossible(reason) => write!(f, "{}: impossible, {}", self.pattern, reason),
+            Feasibility::Possible(probability) => write!(
+                f,
+                "{}: probability {:.3e}, expected attempts {:.3e}",
+                self.pattern,
+                probability,
+                1.0 / probability
+            ),
+        }
+    }
+}
+
+// Chars an encoded payload of this layout can start with, e.g. only '5' for ss58 prefix 42
+pub fn possible_leading_chars(layout: &PayloadLayout) -> Vec<char> {
+    BASE58_ALPHABET.iter()
+        .map(|&c| c as char)
+        .filter(|c| !base58_prefix::prefix_ranges(layout, &c.to_string()).is_empty())
+        .collect()
+}
+
+pub fn check_prefix(layout: &PayloadLayout, pattern: &str, case_sensitive: bool) -> PatternReport {
+    let report = |feasibility| PatternReport { pattern: pattern.to_string(), feasibility };
+
+    let not_in_alphabet: Vec<char> = pattern.chars()
+        .filter(|&c| {
+            let options = if case_sensitive { vec![c] } else { vec![c.to_ascii_lowercase(), c.to_ascii_uppercase()] };
+            !options.iter().any(|&o| o.is_ascii() && base58_prefix::char_index(o as u8).is_some())
+        })
+        .collect();
+    if !not_in_alphabet.is_empty() {
+        return report(Feasibility::Impossible(format!("{:?} not in the Base58 alphabet", not_in_alphabet)));
+    }
+
+    let ranges = base58_prefix::word_ranges(layout, pattern, case_sensitive);
+    if ranges.is_empty() {
+        let leading: String = possible_leading_chars(layout).into_iter().collect();
+        return report(Feasibility::Impossible(format!("addresses can only start with one of \"{}\"", leading)));
+    }
+
+    let matching: BigUint = ranges.iter().map(|(lo, hi)| hi - lo + BigUint::one()).sum();
+    let total = layout.max_value() - layout.min_value() + BigUint::one();
+    let probability = matching.to_f64().unwrap() / total.to_f64().unwrap();
+    report(Feasibility::Possible(probability))
+}
+
+pub fn check_prefixes(layout: &PayloadLayout, patterns: &[&str], case_sensitive: bool) -> Vec<PatternReport> {
+    patterns.iter().map(|pattern| check_prefix(layout, pattern, case_sensitive)).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ss58_generic() -> PayloadLayout {
+        PayloadLayout::new(vec![42], 32, 2)
+    }
+
+    #[test]
+    fn leading_chars_of_generic_substrate() {
+        assert_eq!(possible_leading_chars(&ss58_generic()), vec!['5']);
+    }
+
+    #[test]
+    fn impossible_patterns() {
+        let layout = ss58_generic();
+        assert!(!check_prefix(&layout, "dev", false).is_possible());
+        assert!(!check_prefix(&layout, "0x5", true).is_possible());
+        assert!(!check_prefix(&layout, "5l", true).is_possible());
+        match check_prefix(&layout, "012345", false).feasibility {
+            Feasibility::Impossible(reason) => assert!(reason.contains("alphabet")),
+            _ => panic!("'0' can not be produced"),
+        }
+    }
+
+    #[test]
+    fn difficulty_of_possible_patterns() {
+        let layout = ss58_generic();
+        assert_eq!(check_prefix(&layout, "5", true).feasibility, Feasibility::Possible(1.0));
+        let case_sensitive = match check_prefix(&layout, "5Dev", true).feasibility {
+            Feasibility::Possible(p) => p,
+            _ => panic!("5Dev is possible"),
+        };
+        let case_insensitive = match check_prefix(&layout, "5dev", false).feasibility {
+            Feasibility::Possible(p) => p,
+            _ => panic!("5dev is possible"),
+        };
+        assert!(case_sensitive > 0.0 && case_sensitive < 1e-3);
+        assert!(case_insensitive > case_sensitive);
+    }
+}
@@ -0,0 +1,909 @@
+// Rule sets per chain from <RULES_DIR>/<chain>.json, a map of profile name to a list of rules:
+// { "default": [ { "id": "zero_bytes", "type": "zero_bytes", "min": 5 }, ... ], "strict": [ ... ] }
+// A rule can also be an expression, see rule_expr: { "id": "coffee", "expr": "start(\"c0ffee\") & zero_bytes(>=3)" }
+// or just the expression string, which is then also its id.
+// Rules of type script are Rhai scripts, see rule_script: { "id": "special", "type": "script", "source": "fn matches(..) { .. }" }
+// The profile is picked with RULE_PROFILE. Without RULES_DIR the profiles shipped in rules/ are
+// used, a configured RULES_DIR must have the file of the chain.
+// A running search picks up changes of the file through ActiveRuleSet::reload, see watch.
+use crate::address::polka_wallet;
+use crate::base58_prefix::{self, PayloadLayout};
+use crate::prefix_feasibility;
+use crate::rule_expr::{self, Arg, Comparison, Expr};
+use crate::rule_script::{self, ScriptRule};
+use crate::swappable::{Cached, Swappable};
+use crate::vanity_generator::{
+    AllOf, AnyOf, AtLeast, BookendRule, BoxedRule, ChecksummedRule, CharEntropyRule, ContainsConsecutiveCharsCounterRule, ContainsWordRule, EndRule,
+    MaskRule, MetamaskStartEndRule, Not, PalindromeRule, PolkadotJsStartEndRule, PrefixSuffixRule, ProximityCoefficientRule, RegexRule, RuleEntry, RuleSet, StartRule,
+    StartsConsecutiveCharsCounterRule, TargetsRule, ZeroBytesRule, regex_literals,
+};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+const ETH_DEFAULT_RULES: &str = include_str!("../rules/eth.json");
+const BTC_DEFAULT_RULES: &str = include_str!("../rules/btc.json");
+const POLKADOT_DEFAULT_RULES: &str = include_str!("../rules/polkadot.json");
+
+struct ChainRules {
+    name: &'static str,
+    // shortest address without prefix, rules must not look past it
+    min_address_length: usize,
+    // longest address without prefix, the usual length
+    max_address_length: usize,
+    // chars of an address, lowercase for eth
+    alphabet: &'static str,
+    // the alphabet regex rules declare, checksummed_<name> for checksummed rules
+    alphabet_name: &'static str,
+    is_hex: bool,
+    has_checksum: bool,
+    // chars a case sensitive word may contain
+    is_address_char: fn(char) -> bool,
+    // exact chance of an address starting with a word, where the chars are not independent
+    prefix_probability: Option<fn(&str, bool) -> f64>,
+    // payload of addresses whose first chars are fixed by a version, starts it can not produce are rejected
+    start_layout: Option<fn() -> PayloadLayout>,
+    defaults: &'static str,
+}
+
+const CHAINS: [ChainRules; 3] = [
+    ChainRules {
+        name: "eth",
+        min_address_length: 40,
+        max_address_length: 40,
+        alphabet: "0123456789abcdef",
+        alphabet_name: "hex",
+        is_hex: true,
+        has_checksum: true,
+        is_address_char: |c| c.is_ascii_hexdigit(),
+        prefix_probability: None,
+        start_layout: None,
+        defaults: ETH_DEFAULT_RULES,
+    },
+    // P2PKH without the leading '1'
+    ChainRules {
+        name: "btc",
+        min_address_length: 25,
+        max_address_length: 33,
+        alphabet: "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz",
+        alphabet_name: "base58",
+        is_hex: false,
+        has_checksum: false,
+        is_address_char: |c| c.is_ascii_alphanumeric() && !"0OIl".contains(c),
+        // version 0, hash160 and 4 checksum bytes, the rules see what follows the '1' of the version
+        prefix_probability: Some(|word, case_sensitive| {
+            base58_prefix::prefix_probability(&PayloadLayout::new(vec![0x00], 20, 4), &format!("1{}", word), case_sensitive)
+        }),
+        start_layout: None,
+        defaults: BTC_DEFAULT_RULES,
+    },
+    // the whole ss58 address, its first chars come from the network prefix, e.g. '5' for 42.
+    // Lengths of one byte prefixes
+    ChainRules {
+        name: "polkadot",
+        min_address_length: 47,
+        max_address_length: 48,
+        alphabet: "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz",
+        alphabet_name: "base58",
+        is_hex: false,
+        has_checksum: false,
+        is_address_char: |c| c.is_ascii_alphanumeric() && !"0OIl".contains(c),
+        prefix_probability: Some(|word, case_sensitive| {
+            base58_prefix::prefix_probability(&polka_wallet::ss58_payload_layout(), word, case_sensitive)
+        }),
+        start_layout: Some(polka_wallet::ss58_payload_layout),
+        defaults: POLKADOT_DEFAULT_RULES,
+    },
+];
+
+// (char, probability) of an address char. Each letter of a checksummed eth address is uppercase
+// for half of the addresses.
+fn alphabet(chain: &ChainRules, checksummed: bool) -> Vec<(u8, f64)> {
+    let probability = 1.0 / chain.alphabet.len() as f64;
+    chain.alphabet.bytes()
+        .flat_map(|c| match checksummed && c.is_ascii_alphabetic() {
+            true => vec![(c, probability / 2.0), (c.to_ascii_uppercase(), probability / 2.0)],
+            false => vec![(c, probability)],
+        })
+        .collect()
+}
+
+fn chain_rules(chain: &str) -> Result<&'static ChainRules, String> {
+    CHAINS.iter().find(|rules| rules.name == chain).ok_or_else(|| format!("No rule sets for chain {}", chain))
+}
+
+// None when RULES_DIR is not set
+pub fn rules_dir() -> Option<PathBuf> {
+    std::env::var_os("RULES_DIR").map(PathBuf::from)
+}
+
+pub fn active_profile() -> String {
+    std::env::var("RULE_PROFILE").unwrap_or_else(|_| DEFAULT_PROFILE.to_string())
+}
+
+// The RULE_PROFILE rule set of the chain from RULES_DIR
+pub fn active_rule_set(chain: &str) -> Result<RuleSet, String> {
+    load_rule_set(rules_dir().as_deref(), chain, &active_profile())
+}
+
+// The built-in rules without a dir. A file that can not be read is an error, never a fallback to them
+pub fn load_rule_set(dir: Option<&Path>, chain: &str, profile: &str) -> Result<RuleSet, String> {
+    let (source, content) = match dir {
+        Some(dir) => {
+            let path = dir.join(format!("{}.json", chain));
+            let content = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            (path.display().to_string(), content)
+        }
+        None => (format!("built-in {} rules", chain), chain_rules(chain)?.defaults.to_string()),
+    };
+    let mut profiles = parse_rule_sets(chain, &content).map_err(|e| format!("{}: {}", source, e))?;
+    match profiles.iter().position(|rule_set| rule_set.profile == profile) {
+        Some(index) => Ok(profiles.swap_remove(index)),
+        None => {
+            let names: Vec<&str> = profiles.iter().map(|rule_set| rule_set.profile.as_str()).collect();
+            Err(format!("{}: no profile {}, available: {}", source, profile, names.join(", ")))
+        }
+    }
+}
+
+// <profile>-<hash of its rules>, only changes when the rules of the profile change
+fn version(profile: &str, rules: &[Value]) -> String {
+    let hash = Sha256::digest(serde_json::to_string(rules).unwrap().as_bytes());
+    format!("{}-{}", profile, &hex::encode(hash)[..12])
+}
+
+// Every profile is checked, also the ones not in use, so a broken file fails at startup
+pub fn parse_rule_sets(chain: &str, content: &str) -> Result<Vec<RuleSet>, String> {
+    let chain_rules = chain_rules(chain)?;
+    let value: Value = serde_json::from_str(content).map_err(|e| format!("Invalid json: {}", e))?;
+    let profiles = value.as_object().ok_or("Expected an object of profile name to rule list")?;
+    if profiles.is_empty() {
+        return Err("No profiles".to_string());
+    }
+
+    let mut rule_sets = Vec::new();
+    for (profile, rules) in profiles {
+        let rules = rules.as_array().ok_or_else(|| format!("profile {}: expected a list of rules", profile))?;
+        if rules.is_empty() {
+            return Err(format!("profile {}: no rules", profile));
+        }
+        let mut entries: Vec<RuleEntry> = Vec::new();
+        for (index, spec) in rules.iter().enumerate() {
+            let entry = parse_rule(chain_rules, spec).map_err(|e| format!("profile {}, rule {} {}", profile, index, e))?;
+            if entries.iter().any(|other| other.id == entry.id) {
+                return Err(format!("profile {}, rule {} ({}): duplicate id", profile, index, entry.id));
+            }
+            entries.push(entry);
+        }
+        rule_sets.push(RuleSet { chain: chain.to_string(), profile: profile.clone(), version: version(profile, rules), entries });
+    }
+    Ok(rule_sets)
+}
+
+// The rule set the workers search with, a reload swaps the whole set at once
+pub struct ActiveRuleSet {
+    dir: Option<PathBuf>,
+    chain: String,
+    profile: String,
+    rule_set: Swappable<RuleSet>,
+}
+
+impl ActiveRuleSet {
+    pub fn load(dir: Option<&Path>, chain: &str, profile: &str) -> Result<Self, String> {
+        Ok(ActiveRuleSet {
+            dir: dir.map(Path::to_path_buf),
+            chain: chain.to_string(),
+            profile: profile.to_string(),
+            rule_set: Swappable::new(load_rule_set(dir, chain, profile)?),
+        })
+    }
+
+    pub fn from_env(chain: &str) -> Result<Self, String> {
+        ActiveRuleSet::load(rules_dir().as_deref(), chain, &active_profile())
+    }
+
+    pub fn current(&self) -> Arc<RuleSet> {
+        self.rule_set.current()
+    }
+
+    pub fn cache(&self) -> Cached<'_, RuleSet> {
+        self.rule_set.cache()
+    }
+
+    // Reads the file again. The new version when it changed, a file that is invalid or can not be
+    // read, e.g. while an editor replaces it, keeps the current rules
+    pub fn reload(&self) -> Result<Option<String>, String> {
+        let rule_set = load_rule_set(self.dir.as_deref(), &self.chain, &self.profile)?;
+        let version = rule_set.version.clone();
+        let changed = self.rule_set.update(|current| (current.version != rule_set.version).then_some(rule_set));
+        Ok(changed.then_some(version))
+    }
+}
+
+// RULES_RELOAD_SECONDS, 0 turns reloading off
+pub fn reload_interval() -> Option<Duration> {
+    let seconds: u64 = std::env::var("RULES_RELOAD_SECONDS").ok().and_then(|s| s.parse().ok()).unwrap_or(30);
+    if seconds == 0 { None } else { Some(Duration::from_secs(seconds)) }
+}
+
+// Checks the rules file every interval and swaps in changed rules for all workers
+pub fn watch(active: &'static ActiveRuleSet, interval: Duration) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        match active.reload() {
+            Ok(Some(version)) => println!("Reloaded {} rules, now {}", active.chain, version),
+            Ok(None) => {}
+            Err(e) => println!("Keeping {} rules {}, the new ones can not be loaded: {}", active.chain, active.current().version, e),
+        }
+    });
+}
+
+// one rule spec, errors start with the id of the rule
+fn parse_rule(chain: &ChainRules, spec: &Value) -> Result<RuleEntry, String> {
+    if let Some(expr) = spec.as_str() {
+        let rule = parse_expr(chain, expr).map_err(|e| format!("({}): {}", expr, e))?;
+        return Ok(RuleEntry { id: expr.to_string(), rule });
+    }
+    let spec = spec.as_object().ok_or("(?): expected an object or an expression")?;
+    if let Some(expr) = spec.get("expr") {
+        let expr = expr.as_str().ok_or("(?): \"expr\" must be a string")?;
+        let id = spec.get("id").and_then(Value::as_str).unwrap_or(expr).to_string();
+        if let Some(unknown) = spec.keys().find(|key| *key != "id" && *key != "expr") {
+            return Err(format!("({}): unknown field \"{}\" next to \"expr\"", id, unknown));
+        }
+        let rule = parse_expr(chain, expr).map_err(|e| format!("({}): {}", id, e))?;
+        return Ok(RuleEntry { id, rule });
+    }
+    let rule_type = spec.get("type").and_then(Value::as_str).ok_or("(?): missing \"type\"")?;
+    let id = match spec.get("id") {
+        None => rule_type.to_string(),
+        Some(id) => id.as_str().filter(|id| !id.is_empty()).ok_or("(?): \"id\" must be a non empty string")?.to_string(),
+    };
+    let rule = parse_rule_body(chain, rule_type, spec).map_err(|e| format!("({}): {}", id, e))?;
+    Ok(RuleEntry { id, rule })
+}
+
+// words of checksummed rules may use both cases, all others only see the lowercase address
+fn check_word(chain: &ChainRules, word: &str, case_sensitive: bool, checksummed: bool) -> Result<(), String> {
+    let is_possible = |c: char| if case_sensitive {
+        (chain.is_address_char)(c)
+    } else {
+        (chain.is_address_char)(c.to_ascii_lowercase()) || (chain.is_address_char)(c.to_ascii_uppercase())
+    };
+    if word.is_empty() || word.len() > chain.min_address_length || !word.chars().all(is_possible) {
+        return Err(format!("\"{}\" can not appear in {} addresses", word, chain.name));
+    }
+    if chain.has_checksum && !checksummed && case_sensitive && word.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(format!("\"{}\" has uppercase chars but the rule is not checksummed", word));
+    }
+    Ok(())
+}
+
+// A word checked outside the rule files, e.g. of an order pattern. Case sensitive words of
+// chains with a checksum are matched against the checksummed address.
+pub fn check_pattern_word(chain: &str, word: &str, case_sensitive: bool) -> Result<(), String> {
+    let chain = chain_rules(chain)?;
+    check_word(chain, word, case_sensitive, chain.has_checksum)
+}
+
+// shortest address without prefix, a start and an end word must fit into it side by side
+pub fn min_address_length(chain: &str) -> Result<usize, String> {
+    Ok(chain_rules(chain)?.min_address_length)
+}
+
+fn parse_rule_body(chain: &ChainRules, rule_type: &str, spec: &Map<String, Value>) -> Result<BoxedRule, String> {
+    let checksummed = match spec.get("checksummed") {
+        None => false,
+        Some(value) => value.as_bool().ok_or("\"checksummed\" must be true or false")?,
+    };
+    if checksummed && !chain.has_checksum {
+        return Err(format!("{} addresses have no checksummed form", chain.name));
+    }
+    let check_word = |word: &str, case_sensitive: bool| check_word(chain, word, case_sensitive, checksummed);
+    // e.g. "dev" for ss58 addresses, which all start with '5'
+    let check_start = |word: &str, case_sensitive: bool| match chain.start_layout {
+        Some(layout) => {
+            let report = prefix_feasibility::check_prefix(&layout(), word, case_sensitive);
+            if report.is_possible() { Ok(()) } else { Err(report.to_string()) }
+        }
+        None => Ok(()),
+    };
+
+    let fields: &[&str] = match rule_type {
+        "zero_bytes" | "start_consecutive" | "consecutive" | "palindrome" | "bookend" => &["min"],
+        "metamask" | "polkadot_js" => &[],
+        "start_word" | "end_word" => &["words", "case_sensitive"],
+        "contains_word" => &["words", "case_sensitive", "min_position", "max_position"],
+        "prefix_suffix" => &["starts", "ends", "case_sensitive", "overlap"],
+        "targets" => &["starts", "ends", "case_sensitive"],
+        "mask" => &["pattern", "case_sensitive"],
+        "regex" => &["pattern", "alphabet", "samples"],
+        "entropy" => &["max"],
+        "proximity" => &["min", "max_distance"],
+        "script" => &["source", "budget_ms"],
+        _ => return Err(format!("unknown type {}", rule_type)),
+    };
+    if let Some(unknown) = spec.keys().find(|key| !["id", "type", "checksummed"].contains(&key.as_str()) && !fields.contains(&key.as_str())) {
+        return Err(format!("unknown field \"{}\" for type {}", unknown, rule_type));
+    }
+
+    let rule: BoxedRule = match rule_type {
+        "zero_bytes" => {
+            if !chain.is_hex {
+                return Err(format!("zero_bytes needs a hex address, {} addresses are not", chain.name));
+            }
+            Box::new(ZeroBytesRule::new(integer(spec, "min", 1, chain.min_address_length / 2)?))
+        }
+        "start_consecutive" => Box::new(StartsConsecutiveCharsCounterRule::new(integer(spec, "min", 2, chain.min_address_length)?)),
+        "consecutive" => Box::new(ContainsConsecutiveCharsCounterRule::new(integer(spec, "min", 2, chain.min_address_length)?)),
+        "metamask" => Box::new(MetamaskStartEndRule::new()),
+        "polkadot_js" => Box::new(PolkadotJsStartEndRule::new()),
+        // mirrored chars at each end, so at most half of the shortest address
+        "palindrome" => Box::new(PalindromeRule::new(integer(spec, "min", 2, chain.min_address_length / 2)?)),
+        "bookend" => Box::new(BookendRule::new(integer(spec, "min", 2, chain.min_address_length / 2)?)),
+        "start_word" | "end_word" | "contains_word" => {
+            let words = words(spec, "words")?;
+            let case_sensitive = case_sensitive(spec)?;
+            for word in &words {
+                check_word(word, case_sensitive)?;
+                if rule_type == "start_word" {
+                    check_start(word, case_sensitive)?;
+                }
+            }
+            match rule_type {
+                "start_word" => Box::new(StartRule::new(&words, case_sensitive)),
+                "end_word" => Box::new(EndRule::new(&words, case_sensitive)),
+                _ => {
+                    let rule = ContainsWordRule::new(&words, case_sensitive);
+                    if spec.contains_key("min_position") || spec.contains_key("max_position") {
+                        let last = chain.min_address_length - 1;
+                        let min = if spec.contains_key("min_position") { integer(spec, "min_position", 0, last)? } else { 0 };
+                        let max = if spec.contains_key("max_position") { integer(spec, "max_position", min, last)? } else { last };
+                        Box::new(rule.at_positions(min, max))
+                    } else {
+                        Box::new(rule)
+                    }
+                }
+            }
+        }
+        "prefix_suffix" => {
+            let starts = words(spec, "starts")?;
+            let ends = words(spec, "ends")?;
+            let case_sensitive = case_sensitive(spec)?;
+            for word in starts.iter().chain(ends.iter()) {
+                check_word(word, case_sensitive)?;
+            }
+            for word in &starts {
+                check_start(word, case_sensitive)?;
+            }
+            let overlap = match spec.get("overlap") {
+                None => false,
+                Some(value) => value.as_bool().ok_or("\"overlap\" must be true or false")?,
+            };
+            let rule = PrefixSuffixRule::new(&starts, &ends, case_sensitive);
+            Box::new(if overlap { rule.allowing_overlap() } else { rule })
+        }
+        "targets" => {
+            // either list may be left out
+            let list = |field: &str| if spec.contains_key(field) { words(spec, field) } else { Ok(Vec::new()) };
+            let (starts, ends) = (list("starts")?, list("ends")?);
+            if starts.is_empty() && ends.is_empty() {
+                return Err("\"starts\" or \"ends\" must list targets".to_string());
+            }
+            let case_sensitive = case_sensitive(spec)?;
+            for word in starts.iter().chain(ends.iter()) {
+                check_word(word, case_sensitive)?;
+            }
+            for word in &starts {
+                check_start(word, case_sensitive)?;
+            }
+            Box::new(TargetsRule::new(&starts, &ends, case_sensitive))
+        }
+        "mask" => {
+            let pattern = spec.get("pattern").and_then(Value::as_str).ok_or("\"pattern\" must be a string")?;
+            let case_sensitive = case_sensitive(spec)?;
+            let mask = MaskRule::new(pattern, case_sensitive)?;
+            let literals = mask.literals();
+            if !literals.is_empty() {
+                check_word(&literals, case_sensitive).map_err(|e| format!("mask {}: {}", pattern, e))?;
+            }
+            let length = mask.fixed_length();
+            if length > chain.max_address_length || (!mask.has_run() && length < chain.min_address_length) {
+                let expected = if chain.min_address_length == chain.max_address_length {
+                    chain.max_address_length.to_string()
+                } else {
+                    format!("{} to {}", chain.min_address_length, chain.max_address_length)
+                };
+                return Err(format!("mask {} matches {} chars, {} addresses have {}, use * for the rest", pattern, length, chain.name, expected));
+            }
+            // exact for hex and base58 starts, else at the usual length as if the chars were independent
+            let (probability, approximate) = match (chain.prefix_probability, mask.literal_prefix()) {
+                (Some(prefix_probability), Some(prefix)) => (prefix_probability(prefix, case_sensitive), false),
+                _ => (mask.match_probability(&alphabet(chain, checksummed), chain.max_address_length), !chain.is_hex || checksummed),
+            };
+            Box::new(mask.with_probability(probability, approximate))
+        }
+        "regex" => {
+            let pattern = spec.get("pattern").and_then(Value::as_str).ok_or("\"pattern\" must be a string")?;
+            // declared, so the author states which chars the pattern sees
+            let expected = if checksummed { format!("checksummed_{}", chain.alphabet_name) } else { chain.alphabet_name.to_string() };
+            match spec.get("alphabet").and_then(Value::as_str) {
+                Some(declared) if declared == expected => {}
+                Some(declared) => return Err(format!("alphabet {} does not fit, the rule sees {} addresses as {}", declared, chain.name, expected)),
+                None => return Err(format!("\"alphabet\" must be declared, {} for this rule", expected)),
+            }
+            let alphabet = alphabet(chain, checksummed);
+            let case_insensitive = pattern.contains("(?i");
+            let outside = regex_literals(pattern).into_iter()
+                .find(|&c| !alphabet.iter().any(|&(a, _)| if case_insensitive { c.eq_ignore_ascii_case(&(a as char)) } else { c == a as char }));
+            if let Some(c) = outside {
+                return Err(format!("regex {}: {} is not in the {} alphabet, the rule can never match it", pattern, c, expected));
+            }
+            let samples = match spec.get("samples") {
+                None => 10_000,
+                Some(_) => integer(spec, "samples", 100, 1_000_000)?,
+            };
+            Box::new(RegexRule::new(pattern)?.with_sampled_probability(&alphabet, chain.max_address_length, samples)?)
+        }
+        "entropy" => Box::new(CharEntropyRule::new(number(spec, "max")?)),
+        "proximity" => Box::new(ProximityCoefficientRule::new(number(spec, "min")?, integer(spec, "max_distance", 1, chain.min_address_length)?)),
+        "script" => {
+            // a list of lines reads better in json than one long string
+            let source = match spec.get("source") {
+                Some(Value::String(source)) => source.clone(),
+                Some(Value::Array(_)) => words(spec, "source")?.join("\n"),
+                _ => return Err("\"source\" must be a string or a list of lines".to_string()),
+            };
+            let budget_ms = match spec.get("budget_ms") {
+                None => rule_script::DEFAULT_BUDGET_MS as usize,
+                Some(_) => integer(spec, "budget_ms", 1, 1000)?,
+            };
+            Box::new(ScriptRule::new(&source, chain.is_hex, chain.min_address_length, Duration::from_millis(budget_ms as u64))?)
+        }
+        _ => unreachable!(),
+    };
+    Ok(if checksummed { Box::new(ChecksummedRule(rule)) } else { rule })
+}
+
+fn parse_expr(chain: &ChainRules, expr: &str) -> Result<BoxedRule, String> {
+    build_expr(chain, &rule_expr::parse(expr)?, false)
+}
+
+// Leaves become the same specs as in the json form, so they are validated the same way
+fn build_expr(chain: &ChainRules, expr: &Expr, checksummed: bool) -> Result<BoxedRule, String> {
+    let build_all = |exprs: &[Expr]| -> Result<Vec<BoxedRule>, String> {
+        let rules = exprs.iter().map(|expr| build_expr(chain, expr, checksummed)).collect::<Result<Vec<_>, _>>()?;
+        if rules.iter().any(|rule| rule.needs_checksum()) != rules.iter().all(|rule| rule.needs_checksum()) {
+            return Err("checksummed and lowercase rules can not be combined, wrap the whole expression in checksummed(..)".to_string());
+        }
+        Ok(rules)
+    };
+    match expr {
+        Expr::All(exprs) => Ok(Box::new(AllOf(build_all(exprs)?))),
+        Expr::Any(exprs) => Ok(Box::new(AnyOf(build_all(exprs)?))),
+        Expr::AtLeast(count, exprs) => Ok(Box::new(AtLeast(*count, build_all(exprs)?))),
+        Expr::Not(expr) => Ok(Box::new(Not(build_expr(chain, expr, checksummed)?))),
+        Expr::Checksummed(expr) => {
+            if !chain.has_checksum {
+                return Err(format!("{} addresses have no checksummed form", chain.name));
+            }
+            build_expr(chain, expr, true)
+        }
+        Expr::Rule { name, args } => {
+            let (rule_type, mut spec) = expr_spec(name, args)?;
+            if checksummed {
+                spec.insert("checksummed".to_string(), json!(true));
+            }
+            parse_rule_body(chain, rule_type, &spec).map_err(|e| format!("{}: {}", name, e))
+        }
+    }
+}
+
+// rule type and json spec of a rule call
+fn expr_spec(name: &str, args: &[Arg]) -> Result<(&'static str, Map<String, Value>), String> {
+    let strings = || -> Option<Vec<&str>> {
+        args.iter().map(|arg| match arg { Arg::Str(s) => Some(s.as_str()), _ => None }).collect()
+    };
+    // integral numbers as json integers, the counts are checked with as_u64
+    let number = |value: f64| if value.fract() == 0.0 && value >= 0.0 { json!(value as u64) } else { json!(value) };
+    let usage = |example: &str| format!("expected {}", example);
+    // the count rules compare inclusively, > n is >= n + 1
+    let at_least_count = |arg: &Arg| match arg {
+        Arg::Compare(Comparison::AtLeast, min) => Some(*min),
+        Arg::Compare(Comparison::Greater, min) if min.fract() == 0.0 => Some(min + 1.0),
+        _ => None,
+    };
+
+    let (rule_type, spec) = match (name, args) {
+        ("start" | "istart" | "end" | "iend" | "contains" | "icontains", _) => {
+            let words = strings().filter(|words| !words.is_empty()).ok_or_else(|| usage(&format!("{}(\"word\", ..)", name)))?;
+            let rule_type = match name.trim_start_matches('i') { "start" => "start_word", "end" => "end_word", _ => "contains_word" };
+            (rule_type, json!({ "words": words, "case_sensitive": !name.starts_with('i') }))
+        }
+        ("start_end", _) => {
+            let words = strings().filter(|words| words.len() >= 2).ok_or_else(|| usage("start_end(\"start\", \"end\", ..)"))?;
+            ("prefix_suffix", json!({ "starts": [words[0]], "ends": &words[1..], "case_sensitive": true, "overlap": true }))
+        }
+        ("zero_bytes" | "consecutive" | "start_consecutive" | "palindrome" | "bookend", [arg]) if at_least_count(arg).is_some() => {
+            let rule_type = match name {
+                "zero_bytes" => "zero_bytes",
+                "consecutive" => "consecutive",
+                "palindrome" => "palindrome",
+                "bookend" => "bookend",
+                _ => "start_consecutive",
+            };
+            (rule_type, json!({ "min": number(at_least_count(arg).unwrap()) }))
+        }
+        ("zero_bytes" | "consecutive" | "start_consecutive" | "palindrome" | "bookend", _) => return Err(usage(&format!("{}(>=n)", name))),
+        ("mask" | "imask", [Arg::Str(pattern)]) => ("mask", json!({ "pattern": pattern, "case_sensitive": name == "mask" })),
+        ("mask" | "imask", _) => return Err(usage(&format!("{}(\"pattern\")", name))),
+        ("metamask", []) => ("metamask", json!({})),
+        ("metamask", _) => return Err(usage("metamask()")),
+        ("polkadot_js", []) => ("polkadot_js", json!({})),
+        ("polkadot_js", _) => return Err(usage("polkadot_js()")),
+        ("entropy", [Arg::Compare(Comparison::AtMost, max)]) => ("entropy", json!({ "max": number(*max) })),
+        ("entropy", _) => return Err(usage("entropy(<=x)")),
+        ("proximity", [Arg::Compare(Comparison::AtLeast, min), Arg::Number(max_distance)]) => {
+            ("proximity", json!({ "min": number(*min), "max_distance": number(*max_distance) }))
+        }
+        ("proximity", _) => return Err(usage("proximity(>=x, max_distance)")),
+        _ => return Err(format!("unknown rule {}", name)),
+    };
+    match spec {
+        Value::Object(spec) => Ok((rule_type, spec)),
+        _ => unreachable!(),
+    }
+}
+
+fn case_sensitive(spec: &Map<String, Value>) -> Result<bool, String> {
+    match spec.get("case_sensitive") {
+        None => Ok(true),
+        Some(value) => value.as_bool().ok_or_else(|| "\"case_sensitive\" must be true or false".to_string()),
+    }
+}
+
+fn integer(spec: &Map<String, Value>, field: &str, min: usize, max: usize) -> Result<usize, String> {
+    spec.get(field)
+        .and_then(Value::as_u64)
+        .map(|value| value as usize)
+        .filter(|value| (min..=max).contains(value))
+        .ok_or_else(|| format!("\"{}\" must be an integer from {} to {}", field, min, max))
+}
+
+fn number(spec: &Map<String, Value>, field: &str) -> Result<f64, String> {
+    spec.get(field)
+        .and_then(Value::as_f64)
+        .filter(|value| *value > 0.0)
+        .ok_or_else(|| format!("\"{}\" must be a positive number", field))
+}
+
+fn words<'a>(spec: &'a Map<String, Value>, field: &str) -> Result<Vec<&'a str>, String> {
+    let error = || format!("\"{}\" must be a non empty list of strings", field);
+    let list = spec.get(field).and_then(Value::as_array).filter(|list| !list.is_empty()).ok_or_else(error)?;
+    list.iter().map(|word| word.as_str().ok_or_else(error)).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vanity_generator::{EthAddressBytes, Rule};
+
+    // ids of the rules that match, eth addresses go through EthAddressBytes for the checksummed rules
+    fn matched_ids(rule_set: &RuleSet, address: &str) -> Vec<String> {
+        let matches = if rule_set.chain == "eth" {
+            EthAddressBytes::new(address.as_bytes()).find_matches(rule_set)
+        } else {
+            rule_set.find_matches(address.as_bytes())
+        };
+        matches.into_iter().map(|rule_match| rule_match.rule_id).collect()
+    }
+
+    fn parse_error(chain: &str, content: &str) -> String {
+        parse_rule_sets(chain, content).err().unwrap()
+    }
+
+    #[test]
+    fn shipped_profiles_are_valid() {
+        for chain in CHAINS.iter() {
+            let rule_sets = parse_rule_sets(chain.name, chain.defaults).unwrap();
+            assert!(rule_sets.iter().any(|rule_set| rule_set.profile == DEFAULT_PROFILE), "{}", chain.name);
+        }
+    }
+
+    #[test]
+    fn default_eth_profile_has_the_built_in_rules() {
+        let rule_set = load_rule_set(None, "eth", DEFAULT_PROFILE).unwrap();
+        let ids: Vec<&str> = rule_set.entries.iter().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(ids, ["zero_bytes", "dev", "start_consecutive", "metamask", "consecutive", "start_word", "entropy", "proximity", "checksummed_entropy", "checksummed_proximity"]);
+        // what they match is checked against the string rules in vanity_generator
+    }
+
+    #[test]
+    fn errors_name_the_bad_entry() {
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "zero_bytes", "min": 5}, {"id": "words", "type": "start_word", "words": ["c0ffee", "cafe!"]}]}"#),
+            "profile default, rule 1 (words): \"cafe!\" can not appear in eth addresses");
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "start_word", "words": ["DEAD"]}]}"#),
+            "profile default, rule 0 (start_word): \"DEAD\" has uppercase chars but the rule is not checksummed");
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "consecutive", "min": 41}]}"#),
+            "profile default, rule 0 (consecutive): \"min\" must be an integer from 2 to 40");
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "entropy", "max": 2.8, "mx": 3}]}"#),
+            "profile default, rule 0 (entropy): unknown field \"mx\" for type entropy");
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "metamask"}, {"type": "metamask"}]}"#),
+            "profile default, rule 1 (metamask): duplicate id");
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "metamask"}], "strict": [{"type": "reverse"}]}"#),
+            "profile strict, rule 0 (reverse): unknown type reverse");
+        assert!(parse_error("btc", r#"{"default": [{"type": "entropy", "max": 3, "checksummed": true}]}"#).contains("no checksummed form"));
+        assert!(parse_rule_sets("btc", r#"{"default": [{"type": "start_word", "words": ["0x"]}]}"#).is_err());
+    }
+
+    #[test]
+    fn polkadot_starts_must_be_producible() {
+        // all generic substrate addresses start with '5'
+        assert_eq!(parse_error("polkadot", r#"{"default": [{"type": "start_word", "words": ["5coin", "dev"], "case_sensitive": false}]}"#),
+            "profile default, rule 0 (start_word): dev: impossible, addresses can only start with one of \"5\"");
+        assert!(parse_error("polkadot", r#"{"default": [{"type": "targets", "starts": ["5G", "5a"]}]}"#).contains("5a: impossible"));
+        assert!(parse_rule_sets("polkadot", r#"{"default": [{"type": "prefix_suffix", "starts": ["5G"], "ends": ["dev"]}, "polkadot_js()"]}"#).is_ok());
+        // ends are not fixed by the network prefix
+        assert!(parse_rule_sets("polkadot", r#"{"default": ["end(\"dev\")"]}"#).is_ok());
+
+        let rule_set = load_rule_set(None, "polkadot", DEFAULT_PROFILE).unwrap();
+        assert_eq!(matched_ids(&rule_set, "5CoinGGGGGGa1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8g9"), ["consecutive", "start_word"]);
+    }
+
+    #[test]
+    fn expressions_build_combined_rules() {
+        let content = r#"{"default": [
+            {"id": "coffee_zeros", "expr": "start(\"c0ffee\") & zero_bytes(>=3)"},
+            "entropy(<=3) & !start(\"c0ffee\")",
+            {"id": "two_of", "expr": "at_least(2, start_end(\"ab\", \"cd\"), consecutive(>7), metamask())"}
+        ]}"#;
+        let rule_set = parse_rule_sets("eth", content).unwrap().pop().unwrap();
+        assert_eq!(rule_set.entries[1].id, "entropy(<=3) & !start(\"c0ffee\")");
+        assert_eq!(matched_ids(&rule_set, "c0ffee000000a1b2c3d4e5f6a7b8c9d0e1f2a3b4"), ["coffee_zeros"]);
+        assert!(matched_ids(&rule_set, "c0ffee00a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6").is_empty());
+        assert_eq!(matched_ids(&rule_set, "1111111111111111111111122222222222222222"), ["entropy(<=3) & !start(\"c0ffee\")", "two_of"]);
+        assert_eq!(matched_ids(&rule_set, "ab000000001a2b3c4d5e6f7a8b9c0d1e2f3a4bcd"), ["two_of"]);
+        assert!(matched_ids(&rule_set, "ab1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8fcd").is_empty());
+
+        let matches = EthAddressBytes::new(b"c0ffee000000a1b2c3d4e5f6a7b8c9d0e1f2a3b4").find_matches(&rule_set);
+        assert_eq!(matches[0].matched.as_deref(), Some("c0ffee"));
+        assert_eq!(matches[0].parameters, json!({ "matched": 2, "of": 2 }));
+    }
+
+    #[test]
+    fn expression_errors_name_the_rule() {
+        let error = |expr: &str| parse_error("eth", &json!({ "default": [{ "id": "x", "expr": expr }] }).to_string());
+        assert_eq!(error("start(\"c0ffee\") & zero_bytes(>=21)"), "profile default, rule 0 (x): zero_bytes: \"min\" must be an integer from 1 to 20");
+        assert_eq!(error("start(\"c0ffee\") & zero_bytes(3)"), "profile default, rule 0 (x): expected zero_bytes(>=n)");
+        assert_eq!(error("reverse(>=8)"), "profile default, rule 0 (x): unknown rule reverse");
+        assert_eq!(error("entropy(<3)"), "profile default, rule 0 (x): expected entropy(<=x)");
+        assert_eq!(error("start(\"c0ffee\") &"), "profile default, rule 0 (x): at 17: expected a rule, found the end");
+        assert!(error("metamask() & checksummed(entropy(<=3))").contains("can not be combined"));
+        assert!(parse_rule_sets("eth", r#"{"default": ["checksummed(start(\"DEAD\") | entropy(<=3))"]}"#).is_ok());
+        assert!(parse_error("btc", r#"{"default": ["checksummed(metamask())"]}"#).contains("no checksummed form"));
+    }
+
+    #[test]
+    fn word_rules() {
+        let content = r#"{"default": [
+            {"id": "end", "type": "end_word", "words": ["beef", "F00D"], "case_sensitive": false},
+            {"id": "middle", "type": "contains_word", "words": ["cafe"], "min_position": 10, "max_position": 20},
+            {"id": "bookend", "type": "prefix_suffix", "starts": ["c0ffee"], "ends": ["beef"]},
+            "contains(\"dead\") & end(\"0000\")"
+        ]}"#;
+        let rule_set = parse_rule_sets("eth", content).unwrap().pop().unwrap();
+        assert_eq!(matched_ids(&rule_set, "c0ffee32af5e170c3ec77a1233883c8a7e8abeef"), ["end", "bookend"]);
+        assert_eq!(matched_ids(&rule_set, "1234567890cafe170c3ec77a1233883c8a7ef00d"), ["end", "middle"]);
+        assert!(matched_ids(&rule_set, "cafe567890a1e170c3ec77a1233883c8a7e8a001").is_empty());
+        assert_eq!(matched_ids(&rule_set, "12345678dead170c3ec77a1233883c8a7e8a0000"), ["contains(\"dead\") & end(\"0000\")"]);
+
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "contains_word", "words": ["cafe"], "min_position": 10, "max_position": 5}]}"#),
+            "profile default, rule 0 (contains_word): \"max_position\" must be an integer from 10 to 39");
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "prefix_suffix", "starts": ["c0ffee"], "ends": ["BEEF"]}]}"#),
+            "profile default, rule 0 (prefix_suffix): \"BEEF\" has uppercase chars but the rule is not checksummed");
+        assert_eq!(parse_error("eth", r#"{"default": ["iend()"]}"#), "profile default, rule 0 (iend()): expected iend(\"word\", ..)");
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "prefix_suffix", "starts": ["c0ffee"], "ends": ["beef"], "overlap": 1}]}"#),
+            "profile default, rule 0 (prefix_suffix): \"overlap\" must be true or false");
+    }
+
+    #[test]
+    fn symmetry_rules() {
+        let content = r#"{"default": [
+            {"id": "mirrored", "type": "palindrome", "min": 4},
+            {"id": "bookend", "type": "bookend", "min": 4, "checksummed": true},
+            "palindrome(>=3) & bookend(>=2)"
+        ]}"#;
+        let rule_set = parse_rule_sets("eth", content).unwrap().pop().unwrap();
+        assert_eq!(matched_ids(&rule_set, "dcba5532af5e170c3ec77a1233883c8a7e8aabcd"), ["mirrored"]);
+        // digits look the same checksummed
+        assert_eq!(matched_ids(&rule_set, "12345532af5e170c3ec77a1233883c8a7e8a1234"), ["bookend"]);
+        assert_eq!(matched_ids(&rule_set, "12215532af5e170c3ec77a1233883c8a7e8a1221"), ["mirrored", "bookend", "palindrome(>=3) & bookend(>=2)"]);
+        assert!(matched_ids(&rule_set, "12335532af5e170c3ec77a1233883c8a7e8a1243").is_empty());
+        let rule_match = EthAddressBytes::new(b"dcba5532af5e170c3ec77a1233883c8a7e8aabcd").find_match(&rule_set.entries[0]).unwrap();
+        assert_eq!((rule_match.matched.as_deref(), rule_match.score), (Some("dcba"), 4.0));
+
+        let btc = parse_rule_sets("btc", r#"{"default": ["palindrome(>=3)", "bookend(>=3)"]}"#).unwrap().pop().unwrap();
+        assert_eq!(matched_ids(&btc, "Bob1234567xyz7654321boB"), ["palindrome(>=3)"]);
+        assert_eq!(matched_ids(&btc, "Bob1234567xyz7654321Bob"), ["bookend(>=3)"]);
+        assert!(matched_ids(&btc, "Bob1234567xyz7654321bob").is_empty());
+
+        assert_eq!(parse_error("btc", r#"{"default": [{"type": "bookend", "min": 13}]}"#), "profile default, rule 0 (bookend): \"min\" must be an integer from 2 to 12");
+        assert_eq!(parse_error("btc", r#"{"default": ["palindrome()"]}"#), "profile default, rule 0 (palindrome()): expected palindrome(>=n)");
+    }
+
+    #[test]
+    fn mask_rules() {
+        let content = r#"{"default": [
+            {"id": "bookend", "type": "mask", "pattern": "dead????????????????????????????????beef"},
+            {"id": "checksummed", "type": "mask", "pattern": "DE*", "checksummed": true},
+            "mask(\"*c0ffee*\")"
+        ]}"#;
+        let rule_set = parse_rule_sets("eth", content).unwrap().pop().unwrap();
+        assert_eq!(rule_set.entries[0].rule.probability(), Some(16f64.powi(-8)));
+        // D and E each uppercase for half of the addresses
+        assert_eq!(rule_set.entries[1].rule.probability(), Some(32f64.powi(-2)));
+        let contains = rule_set.entries[2].rule.probability().unwrap();
+        assert!(contains > 34.0 * 16f64.powi(-6) * 0.99 && contains < 35.0 * 16f64.powi(-6), "{}", contains);
+
+        let rule_match = EthAddressBytes::new(b"dead5532af5e170c3ec77a1233883c8a7e8abeef").find_matches(&rule_set).pop().unwrap();
+        assert_eq!(rule_match.rule_id, "bookend");
+        assert_eq!(rule_match.parameters["probability"], json!(16f64.powi(-8)));
+        // letters of checksummed addresses are uppercase with about ½
+        let rule_match = rule_set.entries[1].find_match(b"DE5532aF5e170c3Ec77a1233883c8a7e8aBeef00").unwrap();
+        assert_eq!(rule_match.parameters["approximate_probability"], json!(32f64.powi(-2)));
+
+        // the first char of a 33 char btc address covers only about 25 of the 58
+        let btc = parse_rule_sets("btc", r#"{"default": ["mask(\"2*\")", "mask(\"z*\")", "mask(\"*Bob\")"]}"#).unwrap().pop().unwrap();
+        let (two, z) = (btc.entries[0].rule.probability().unwrap(), btc.entries[1].rule.probability().unwrap());
+        assert!(two > 1.0 / 58.0 && z < two / 10.0, "{} {}", two, z);
+        let rule_match = btc.find_matches(b"2BCDEFGHJKLMNPQRSTUVWXYZabcdeBob").pop().unwrap();
+        assert!(rule_match.parameters.get("approximate_probability").is_some());
+        assert!(btc.find_matches(b"2BCDEFGHJKLMNPQRSTUVWXYZabcdefgh")[0].parameters.get("probability").is_some());
+
+        assert_eq!(parse_error("eth", r#"{"default": ["mask(\"dead??beef\")"]}"#),
+            "profile default, rule 0 (mask(\"dead??beef\")): mask: mask dead??beef matches 10 chars, eth addresses have 40, use * for the rest");
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "mask", "pattern": "dead*BEEF"}]}"#),
+            "profile default, rule 0 (mask): mask dead*BEEF: \"deadBEEF\" has uppercase chars but the rule is not checksummed");
+        assert!(parse_error("eth", r#"{"default": [{"type": "mask", "pattern": "1l*"}]}"#).contains("can not appear"));
+        assert!(parse_rule_sets("btc", r#"{"default": [{"type": "mask", "pattern": "0*"}]}"#).is_err());
+        assert!(parse_rule_sets("btc", r#"{"default": [{"type": "mask", "pattern": "Bob*"}]}"#).is_ok());
+    }
+
+    #[test]
+    fn regex_rules() {
+        let content = r#"{"default": [
+            {"id": "dead_digits", "type": "regex", "pattern": "^dead[0-9]+", "alphabet": "hex", "samples": 1000},
+            {"id": "checksummed_dead", "type": "regex", "pattern": "^DEAD", "alphabet": "checksummed_hex", "checksummed": true}
+        ]}"#;
+        let rule_set = parse_rule_sets("eth", content).unwrap().pop().unwrap();
+        let rule_match = EthAddressBytes::new(b"dead1234af5e170c3ec77a1233883c8a7e8a827f").find_matches(&rule_set).remove(0);
+        assert_eq!((rule_match.rule_id.as_str(), rule_match.matched.as_deref()), ("dead_digits", Some("dead1234")));
+        assert_eq!(rule_match.parameters["samples"], json!(1000));
+
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "regex", "pattern": "^dead"}]}"#),
+            "profile default, rule 0 (regex): \"alphabet\" must be declared, hex for this rule");
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "regex", "pattern": "^dead", "alphabet": "base58"}]}"#),
+            "profile default, rule 0 (regex): alphabet base58 does not fit, the rule sees eth addresses as hex");
+        assert!(parse_error("eth", r#"{"default": [{"type": "regex", "pattern": "(0+)*1", "alphabet": "hex"}]}"#).contains("backtrack"));
+        assert!(parse_rule_sets("btc", r#"{"default": [{"type": "regex", "pattern": "^[Bb]ob", "alphabet": "base58"}]}"#).is_ok());
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "regex", "pattern": "^z", "alphabet": "hex"}]}"#),
+            "profile default, rule 0 (regex): regex ^z: z is not in the hex alphabet, the rule can never match it");
+        assert!(parse_error("eth", r#"{"default": [{"type": "regex", "pattern": "^DEAD", "alphabet": "hex"}]}"#).contains("D is not in the hex alphabet"));
+        assert!(parse_rule_sets("eth", r#"{"default": [{"type": "regex", "pattern": "(?i)^DEAD", "alphabet": "hex"}]}"#).is_ok());
+        assert!(parse_rule_sets("btc", r#"{"default": [{"type": "regex", "pattern": "^b0b", "alphabet": "base58"}]}"#).is_err());
+    }
+
+    #[test]
+    fn target_rules_report_every_target() {
+        let content = r#"{"default": [
+            {"id": "orders", "type": "targets", "starts": ["dead", "deadbeef", "c0ffee"], "ends": ["beef", "f00d"]},
+            {"id": "only_ends", "type": "targets", "ends": ["Cafe"], "case_sensitive": false}
+        ]}"#;
+        let rule_set = parse_rule_sets("eth", content).unwrap().pop().unwrap();
+        let matches = EthAddressBytes::new(b"deadbeef32af5e170c3ec77a1233883c8a7ebeef").find_matches(&rule_set);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].matched.as_deref(), Some("dead+deadbeef+beef"));
+        assert_eq!(matches[0].parameters["targets"], json!([{ "start": "dead" }, { "start": "deadbeef" }, { "end": "beef" }]));
+        assert_eq!(matches[0].score, 3.0);
+        let matches = EthAddressBytes::new(b"00000000000000000000000000000000000cafe").find_matches(&rule_set);
+        assert_eq!(matches[0].rule_id, "only_ends");
+
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "targets"}]}"#),
+            "profile default, rule 0 (targets): \"starts\" or \"ends\" must list targets");
+    }
+
+    #[test]
+    fn script_rules_match_like_the_typed_rules() {
+        let rule_set = load_rule_set(None, "eth", "scripted").unwrap();
+        let dev = &rule_set.entries[0];
+        let default = load_rule_set(None, "eth", DEFAULT_PROFILE).unwrap();
+        let typed = default.entries.iter().find(|entry| entry.id == "dev").unwrap();
+        assert!(dev.rule.needs_checksum());
+        let random = (0..50).map(|_| crate::address::eth_wallet::generate_random_wallet().address);
+        for address in ["de00000000000000000000000000000000000001".to_string()].into_iter().chain(random) {
+            let checksummed = crate::address::eth_wallet::checksummed(&address);
+            assert_eq!(dev.rule.apply(&checksummed), typed.rule.apply(&checksummed), "{}", checksummed);
+        }
+
+        assert!(parse_error("eth", r#"{"default": [{"type": "script", "source": "fn matches(address, bytes, metrics) { address.len() }"}]}"#)
+            .starts_with("profile default, rule 0 (script): script on 0000000000000000000000000000000000000000:"));
+        assert_eq!(parse_error("eth", r#"{"default": [{"type": "script", "source": "fn matches(a, b, c) { true }", "budget_ms": 0}]}"#),
+            "profile default, rule 0 (script): \"budget_ms\" must be an integer from 1 to 1000");
+    }
+
+    #[test]
+    fn reload_swaps_the_rules_of_all_caches() {
+        let dir = std::env::temp_dir().join(format!("vanity-reload-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("eth.json");
+        fs::write(&path, r#"{"default": ["start(\"beef\")"], "other": ["metamask()"]}"#).unwrap();
+        let active = ActiveRuleSet::load(Some(&dir), "eth", DEFAULT_PROFILE).unwrap();
+        let mut first = active.cache();
+        let mut second = active.cache();
+        let version = first.get().version.clone();
+        assert!(version.starts_with("default-"));
+        let beef = b"beef000000000000000000000000000000000000";
+        assert!(EthAddressBytes::new(beef).any_match(first.get()));
+
+        // another profile changed, same version
+        fs::write(&path, r#"{"default": ["start(\"beef\")"], "other": ["consecutive(>=9)"]}"#).unwrap();
+        assert_eq!(active.reload(), Ok(None));
+
+        fs::write(&path, r#"{"default": ["start(\"c0ffee\")"]}"#).unwrap();
+        let reloaded = active.reload().unwrap().unwrap();
+        assert_ne!(reloaded, version);
+        assert_eq!(first.get().version, reloaded);
+        assert!(!EthAddressBytes::new(beef).any_match(first.get()));
+        assert_eq!(second.get().version, reloaded);
+
+        // an invalid file keeps the rules
+        fs::write(&path, r#"{"default": ["start(\"c0ffee\""]}"#).unwrap();
+        assert!(active.reload().is_err());
+        assert_eq!(first.get().version, reloaded);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_deleted_rules_file_keeps_the_rules() {
+        let dir = std::env::temp_dir().join(format!("vanity-deleted-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("eth.json");
+        fs::write(&path, r#"{"default": ["start(\"beef\")"]}"#).unwrap();
+        let active = ActiveRuleSet::load(Some(&dir), "eth", DEFAULT_PROFILE).unwrap();
+        let mut cache = active.cache();
+        let version = cache.get().version.clone();
+
+        // also the gap while an editor saves by rename
+        fs::remove_file(&path).unwrap();
+        assert!(active.reload().err().unwrap().starts_with(&path.display().to_string()));
+        assert_eq!(active.current().version, version);
+        assert_eq!(cache.get().version, version);
+        assert!(EthAddressBytes::new(b"beef000000000000000000000000000000000000").any_match(cache.get()));
+        assert!(!EthAddressBytes::new(b"0000000000000000000000000000000000000000").any_match(cache.get()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn profiles_are_loaded_from_the_rules_dir() {
+        let dir = std::env::temp_dir().join(format!("vanity-rules-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("eth.json"), r#"{"default": [{"type": "metamask"}], "words": [{"type": "start_word", "words": ["beef"]}]}"#).unwrap();
+
+        let rule_set = load_rule_set(Some(&dir), "eth", "words").unwrap();
+        assert_eq!(rule_set.profile, "words");
+        assert!(rule_set.any_match(b"beef000000000000000000000000000000000000"));
+        assert!(!rule_set.any_match(b"c0ffee0000000000000000000000000000000000"));
+        assert!(load_rule_set(Some(&dir), "eth", "strict").err().unwrap().ends_with("no profile strict, available: default, words"));
+        // no btc.json in a configured dir, the shipped profiles are not used in its place
+        assert!(load_rule_set(Some(&dir), "btc", DEFAULT_PROFILE).err().unwrap().starts_with(&dir.join("btc.json").display().to_string()));
+        assert!(load_rule_set(Some(&dir.join("typo")), "eth", DEFAULT_PROFILE).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
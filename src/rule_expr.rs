@@ -0,0 +1,292 @@
+// Rule expressions for profiles, e.g. start("dead") & zero_bytes(>=3) | consecutive(>=8)
+//   expr    := and ('|' and)*
+//   and     := unary ('&' unary)*
+//   unary   := '!' unary | '(' expr ')' | call
+//   call    := name '(' [arg (',' arg)*] ')'
+//   arg     := "string" | op number | number | expr
+// & binds tighter than |. all(..), any(..), not(..), at_least(n, ..) and checksummed(..) are the
+// combinators, every other name is a rule type of rule_config, built by rule_config::build_expr.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Comparison {
+    AtLeast,
+    Greater,
+    AtMost,
+    Less,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Str(String),
+    Compare(Comparison, f64),
+    Number(f64),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Rule { name: String, args: Vec<Arg> },
+    All(Vec<Expr>),
+    Any(Vec<Expr>),
+    Not(Box<Expr>),
+    AtLeast(usize, Vec<Expr>),
+    Checksummed(Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Str(String),
+    Number(f64),
+    Op(&'static str),
+}
+
+// (position, token)
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, String> {
+    const OPS: [&str; 10] = [">=", "<=", "&", "|", "!", "(", ")", ",", ">", "<"];
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' {
+            let end = chars[i + 1..].iter().position(|&c| c == '"').ok_or_else(|| format!("at {}: unterminated string", i))?;
+            tokens.push((i, Token::Str(chars[i + 1..i + 1 + end].iter().collect())));
+            i += end + 2;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text.parse().map_err(|_| format!("at {}: invalid number {}", start, text))?;
+            tokens.push((start, Token::Number(number)));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((start, Token::Name(chars[start..i].iter().collect())));
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            let op = OPS.iter().find(|op| rest.starts_with(*op)).ok_or_else(|| format!("at {}: unexpected '{}'", i, c))?;
+            tokens.push((i, Token::Op(op)));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.next).map(|(position, _)| *position).unwrap_or(self.end)
+    }
+
+    fn error<T>(&self, expected: &str) -> Result<T, String> {
+        match self.peek() {
+            Some(token) => Err(format!("at {}: expected {}, found {:?}", self.position(), expected, token)),
+            None => Err(format!("at {}: expected {}, found the end", self.position(), expected)),
+        }
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(found)) if *found == op) {
+            self.next += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.eat(op) { Ok(()) } else { self.error(&format!("'{}'", op)) }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut any = vec![self.and()?];
+        while self.eat("|") {
+            any.push(self.and()?);
+        }
+        Ok(if any.len() == 1 { any.pop().unwrap() } else { Expr::Any(any) })
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut all = vec![self.unary()?];
+        while self.eat("&") {
+            all.push(self.unary()?);
+        }
+        Ok(if all.len() == 1 { all.pop().unwrap() } else { Expr::All(all) })
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.expr()?;
+            self.expect(")")?;
+            return Ok(expr);
+        }
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr, String> {
+        let position = self.position();
+        let name = match self.peek() {
+            Some(Token::Name(name)) => name.clone(),
+            _ => return self.error("a rule"),
+        };
+        self.next += 1;
+        self.expect("(")?;
+        let mut args = Vec::new();
+        if !self.eat(")") {
+            loop {
+                args.push(self.arg()?);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        combinator(&name, args).map_err(|e| format!("at {}: {}", position, e))
+    }
+
+    fn arg(&mut self) -> Result<Arg, String> {
+        let arg = match self.peek() {
+            Some(Token::Str(s)) => Arg::Str(s.clone()),
+            Some(Token::Number(number)) => Arg::Number(*number),
+            Some(Token::Op(op)) if [">=", "<=", ">", "<"].contains(op) => {
+                let comparison = match *op {
+                    ">=" => Comparison::AtLeast,
+                    ">" => Comparison::Greater,
+                    "<=" => Comparison::AtMost,
+                    _ => Comparison::Less,
+                };
+                self.next += 1;
+                match self.peek() {
+                    Some(Token::Number(number)) => Arg::Compare(comparison, *number),
+                    _ => return self.error("a number"),
+                }
+            }
+            _ => return Ok(Arg::Expr(self.expr()?)),
+        };
+        self.next += 1;
+        Ok(arg)
+    }
+}
+
+fn exprs(name: &str, args: Vec<Arg>) -> Result<Vec<Expr>, String> {
+    args.into_iter()
+        .map(|arg| match arg {
+            Arg::Expr(expr) => Ok(expr),
+            _ => Err(format!("{} takes rules", name)),
+        })
+        .collect()
+}
+
+fn combinator(name: &str, mut args: Vec<Arg>) -> Result<Expr, String> {
+    match name {
+        "all" | "any" if args.is_empty() => Err(format!("{} needs at least one rule", name)),
+        "all" => Ok(Expr::All(exprs(name, args)?)),
+        "any" => Ok(Expr::Any(exprs(name, args)?)),
+        "not" | "checksummed" => {
+            let mut inner = exprs(name, args)?;
+            if inner.len() != 1 {
+                return Err(format!("{} takes one rule", name));
+            }
+            let inner = Box::new(inner.pop().unwrap());
+            Ok(if name == "not" { Expr::Not(inner) } else { Expr::Checksummed(inner) })
+        }
+        "at_least" => {
+            let count = match args.first() {
+                Some(Arg::Number(count)) if count.fract() == 0.0 && *count >= 1.0 => *count as usize,
+                _ => return Err("at_least takes a count and rules, e.g. at_least(2, metamask(), zero_bytes(>=3))".to_string()),
+            };
+            let rules = exprs(name, args.split_off(1))?;
+            if count > rules.len() {
+                return Err(format!("at_least({}) of only {} rules", count, rules.len()));
+            }
+            Ok(Expr::AtLeast(count, rules))
+        }
+        _ => Ok(Expr::Rule { name: name.to_string(), args }),
+    }
+}
+
+pub fn parse(input: &str) -> Result<Expr, String> {
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Err("empty expression".to_string());
+    }
+    let mut parser = Parser { tokens, next: 0, end: input.chars().count() };
+    let expr = parser.expr()?;
+    if parser.peek().is_some() {
+        return parser.error("'&', '|' or the end");
+    }
+    Ok(expr)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, args: Vec<Arg>) -> Expr {
+        Expr::Rule { name: name.to_string(), args }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expr = parse(r#"start("dead") & zero_bytes(>=3) | consecutive(>=8)"#).unwrap();
+        assert_eq!(expr, Expr::Any(vec![
+            Expr::All(vec![
+                rule("start", vec![Arg::Str("dead".to_string())]),
+                rule("zero_bytes", vec![Arg::Compare(Comparison::AtLeast, 3.0)]),
+            ]),
+            rule("consecutive", vec![Arg::Compare(Comparison::AtLeast, 8.0)]),
+        ]));
+        assert_eq!(parse(r#"start("dead") & (zero_bytes(>=3) | consecutive(>8))"#).unwrap(), Expr::All(vec![
+            rule("start", vec![Arg::Str("dead".to_string())]),
+            Expr::Any(vec![
+                rule("zero_bytes", vec![Arg::Compare(Comparison::AtLeast, 3.0)]),
+                rule("consecutive", vec![Arg::Compare(Comparison::Greater, 8.0)]),
+            ]),
+        ]));
+    }
+
+    #[test]
+    fn combinators() {
+        assert_eq!(parse(r#"!start("c0ffee") & entropy(<=3)"#).unwrap(), Expr::All(vec![
+            Expr::Not(Box::new(rule("start", vec![Arg::Str("c0ffee".to_string())]))),
+            rule("entropy", vec![Arg::Compare(Comparison::AtMost, 3.0)]),
+        ]));
+        assert_eq!(parse("at_least(2, metamask(), zero_bytes(>=3), not(consecutive(>=5)))").unwrap(), Expr::AtLeast(2, vec![
+            rule("metamask", vec![]),
+            rule("zero_bytes", vec![Arg::Compare(Comparison::AtLeast, 3.0)]),
+            Expr::Not(Box::new(rule("consecutive", vec![Arg::Compare(Comparison::AtLeast, 5.0)]))),
+        ]));
+        assert_eq!(parse("checksummed(entropy(<=3))").unwrap(), Expr::Checksummed(Box::new(rule("entropy", vec![Arg::Compare(Comparison::AtMost, 3.0)]))));
+    }
+
+    #[test]
+    fn errors_point_at_the_position() {
+        assert_eq!(parse(r#"start("dead") &"#).err().unwrap(), "at 15: expected a rule, found the end");
+        assert_eq!(parse(r#"start("dead" zero_bytes(>=3)"#).err().unwrap(), "at 13: expected ',', found Name(\"zero_bytes\")");
+        assert_eq!(parse(r#"start("dead) "#).err().unwrap(), "at 6: unterminated string");
+        assert_eq!(parse("metamask() metamask()").err().unwrap(), "at 11: expected '&', '|' or the end, found Name(\"metamask\")");
+        assert_eq!(parse("at_least(3, metamask(), consecutive(>=5))").err().unwrap(), "at 0: at_least(3) of only 2 rules");
+        assert_eq!(parse("not(\"x\")").err().unwrap(), "at 0: not takes rules");
+        assert_eq!(parse("metamask() # x").err().unwrap(), "at 11: unexpected '#'");
+        assert!(parse("  ").is_err());
+    }
+}
@@ -0,0 +1,228 @@
+// Rules written in Rhai, for one-off checks that should not need a rebuild. A script defines
+//   fn matches(address, bytes, metrics)   -> bool, required
+//   fn score(address, bytes, metrics)     -> number, optional, the score of the RuleMatch
+// address is the address without prefix (checksummed for checksummed rules), bytes the decoded
+// bytes of hex addresses and the ascii bytes otherwise, metrics a map of
+// entropy, proximity, longest_run, leading_run and, for hex addresses, zero_bytes.
+// Scripts can not import modules, eval or print, and each call has to finish within its budget.
+// A script over its budget is disabled until the rules change, other failures are counted
+// and printed the 1st, 10th, 100th.. time.
+use crate::address_utils::address_utils;
+use crate::vanity_generator::{self, FailureCount, Rule, RuleMatch};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Blob, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde_json::json;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// hard limit next to the time budget, also bounds a script when the clock is not checked yet
+const MAX_OPERATIONS: u64 = 1_000_000;
+// the clock is read every this many operations
+const CLOCK_INTERVAL: u64 = 256;
+pub const DEFAULT_BUDGET_MS: u64 = 10;
+
+thread_local! {
+    // deadline of the script call running on this thread
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+pub struct ScriptRule {
+    engine: Engine,
+    ast: AST,
+    has_score: bool,
+    is_hex: bool,
+    budget: Duration,
+    failures: FailureCount,
+    disabled: AtomicBool,
+}
+
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(1024);
+    engine.set_max_array_size(1024);
+    engine.set_max_map_size(64);
+    engine.on_progress(|operations| {
+        let over_budget = operations % CLOCK_INTERVAL == 0
+            && DEADLINE.with(|deadline| deadline.get()).is_some_and(|deadline| Instant::now() > deadline);
+        over_budget.then_some(Dynamic::UNIT)
+    });
+    engine
+}
+
+impl ScriptRule {
+    // Compiles the script and runs it once on a sample address, so type errors and scripts that
+    // never finish fail when the rules are loaded
+    pub fn new(source: &str, is_hex: bool, address_length: usize, budget: Duration) -> Result<Self, String> {
+        let engine = sandboxed_engine();
+        let ast = engine.compile(source).map_err(|e| format!("script: {}", e))?;
+        let has_function = |name: &str| ast.iter_functions().any(|f| f.name == name && f.params.len() == 3);
+        if !has_function("matches") {
+            return Err("script: missing fn matches(address, bytes, metrics)".to_string());
+        }
+        let has_score = has_function("score");
+        let rule = ScriptRule { engine, ast, has_score, is_hex, budget, failures: FailureCount::default(), disabled: AtomicBool::new(false) };
+        let sample = if is_hex { "0".repeat(address_length) } else { "1".repeat(address_length) };
+        rule.call_matches(sample.as_bytes()).map_err(|e| format!("script on {}: {}", sample, rule.describe_error(*e)))?;
+        if has_score {
+            rule.call_score(sample.as_bytes()).map_err(|e| format!("script score on {}: {}", sample, e))?;
+        }
+        Ok(rule)
+    }
+
+    fn call<T: Clone + Send + Sync + 'static>(&self, function: &str, address_no_prefix: &[u8]) -> Result<T, Box<EvalAltResult>> {
+        let address = String::from_utf8_lossy(address_no_prefix).into_owned();
+        let bytes: Blob = if self.is_hex {
+            hex::decode(address.to_ascii_lowercase()).map_err(|e| e.to_string())?
+        } else {
+            address_no_prefix.to_vec()
+        };
+        let metrics = self.metrics(address_no_prefix);
+        DEADLINE.with(|deadline| deadline.set(Some(Instant::now() + self.budget)));
+        let result = self.engine.call_fn_with_options(CallFnOptions::new().eval_ast(false), &mut Scope::new(), &self.ast, function, (address, bytes, metrics));
+        DEADLINE.with(|deadline| deadline.set(None));
+        result
+    }
+
+    fn call_matches(&self, address_no_prefix: &[u8]) -> Result<bool, Box<EvalAltResult>> {
+        self.call::<bool>("matches", address_no_prefix)
+    }
+
+    fn call_score(&self, address_no_prefix: &[u8]) -> Result<f64, String> {
+        let score = self.call::<Dynamic>("score", address_no_prefix).map_err(|e| self.describe_error(*e))?;
+        score.as_float()
+            .or_else(|_| score.as_int().map(|score| score as f64))
+            .map_err(|found| format!("score must return a number, not {}", found))
+    }
+
+    fn failed(&self, function: &str, address_no_prefix: &[u8], error: String, over_budget: bool) {
+        let (failures, print) = self.failures.record();
+        let first_over_budget = over_budget && !self.disabled.swap(true, Ordering::Relaxed);
+        if first_over_budget || print {
+            let disabled = if first_over_budget { ", disabled until the rules change" } else { "" };
+            println!("Script {} failed on {} (failure {}){}: {}", function, String::from_utf8_lossy(address_no_prefix), failures, disabled, error);
+        }
+    }
+
+    fn describe_error(&self, error: EvalAltResult) -> String {
+        match error {
+            EvalAltResult::ErrorTerminated(..) => format!("over the time budget of {} ms", self.budget.as_millis()),
+            error => error.to_string(),
+        }
+    }
+
+    fn metrics(&self, address_no_prefix: &[u8]) -> Map {
+        let (_, longest_run) = vanity_generator::longest_run(address_no_prefix);
+        let leading_run = address_no_prefix.iter().take_while(|&&c| c == address_no_prefix[0]).count();
+        let mut metrics = Map::new();
+        metrics.insert("entropy".into(), Dynamic::from_float(address_utils::calculate_entropy_bytes(address_no_prefix)));
+        metrics.insert("proximity".into(), Dynamic::from_float(address_utils::calculate_proximity_coefficient_bytes(address_no_prefix, 3)));
+        metrics.insert("longest_run".into(), Dynamic::from_int(longest_run as i64));
+        metrics.insert("leading_run".into(), Dynamic::from_int(leading_run as i64));
+        if self.is_hex {
+            let zero_bytes = address_no_prefix.chunks(2).filter(|pair| pair == b"00").count();
+            metrics.insert("zero_bytes".into(), Dynamic::from_int(zero_bytes as i64));
+        }
+        metrics
+    }
+}
+
+impl Rule for ScriptRule {
+    // a failing or disabled script does not match, the search goes on
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        if self.disabled.load(Ordering::Relaxed) {
+            return false;
+        }
+        self.call_matches(address_no_prefix).unwrap_or_else(|e| {
+            let over_budget = matches!(*e, EvalAltResult::ErrorTerminated(..));
+            self.failed("rule", address_no_prefix, self.describe_error(*e), over_budget);
+            false
+        })
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        if !self.apply_bytes(address_no_prefix) {
+            return None;
+        }
+        let score = if self.has_score {
+            self.call_score(address_no_prefix).unwrap_or_else(|e| {
+                self.failed("score", address_no_prefix, e, false);
+                1.0
+            })
+        } else {
+            1.0
+        };
+        Some(RuleMatch { parameters: json!({ "budget_ms": self.budget.as_millis() as u64 }), score, ..Default::default() })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(source: &str) -> Result<ScriptRule, String> {
+        ScriptRule::new(source, true, 40, Duration::from_millis(DEFAULT_BUDGET_MS))
+    }
+
+    #[test]
+    fn scripts_see_address_bytes_and_metrics() {
+        let dev = rule(r#"
+            fn matches(address, bytes, metrics) {
+                address.starts_with("de") && bytes[19] == 1 && metrics.zero_bytes >= 3
+            }
+            fn score(address, bytes, metrics) { metrics.zero_bytes * 2 + metrics.leading_run }
+        "#).unwrap();
+        let address = b"de00000000a1b2c3d4e5f6a7b8c9d0e1f2a3b401";
+        assert!(dev.apply_bytes(address));
+        assert!(!dev.apply_bytes(b"de00000000a1b2c3d4e5f6a7b8c9d0e1f2a3b402"));
+        assert_eq!(dev.find_match(address).unwrap().score, 9.0);
+
+        let entropy = rule("fn matches(address, bytes, metrics) { metrics.entropy < 1.0 && metrics.longest_run == 40 }").unwrap();
+        assert!(entropy.apply_bytes(&[b'7'; 40]));
+        assert!(!entropy.apply_bytes(address));
+    }
+
+    #[test]
+    fn scripts_are_sandboxed() {
+        assert!(rule("fn check(address) { true }").err().unwrap().contains("missing fn matches"));
+        assert!(rule("fn matches(address, bytes, metrics) { 1 }").is_err());
+        assert!(rule("fn matches(address, bytes, metrics) { eval(\"true\") }").is_err());
+        assert!(rule("fn matches(address, bytes, metrics) { import \"secrets\" as s; true }").is_err());
+
+        // far below the operations limit
+        let started = Instant::now();
+        let error = ScriptRule::new("fn matches(address, bytes, metrics) { loop { } }", true, 40, Duration::from_millis(1)).err().unwrap();
+        assert!(error.ends_with("over the time budget of 1 ms"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        // far above it
+        let error = ScriptRule::new("fn matches(address, bytes, metrics) { loop { } }", true, 40, Duration::from_secs(600)).err().unwrap();
+        assert!(error.ends_with("Too many operations"), "{}", error);
+    }
+
+    #[test]
+    fn failing_scripts_are_counted_and_slow_ones_disabled() {
+        // passes on the sample, fails on addresses starting with d
+        let failing = rule("fn matches(address, bytes, metrics) { if address.starts_with(\"d\") { return 1; } false }").unwrap();
+        for _ in 0..3 {
+            assert!(!failing.apply_bytes(b"de00000000a1b2c3d4e5f6a7b8c9d0e1f2a3b401"));
+        }
+        assert_eq!(failing.failures.get(), 3);
+        assert!(!failing.disabled.load(Ordering::Relaxed));
+
+        // passes on the sample, runs into the budget on others and is not run again
+        let slow = rule("fn matches(address, bytes, metrics) { if address.starts_with(\"0\") { return false; } loop { } }").unwrap();
+        assert!(!slow.apply_bytes(b"de00000000a1b2c3d4e5f6a7b8c9d0e1f2a3b401"));
+        let started = Instant::now();
+        assert!(!slow.apply_bytes(b"de00000000a1b2c3d4e5f6a7b8c9d0e1f2a3b401"));
+        assert!(started.elapsed() < Duration::from_millis(DEFAULT_BUDGET_MS));
+        assert_eq!(slow.failures.get(), 1);
+    }
+}
@@ -0,0 +1,76 @@
+// A value the workers read for every candidate and a reload thread replaces as a whole, e.g. the
+// active rule set or the open orders. A worker finishes the candidate at hand with the old value
+// and takes the new one through its Cached copy.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+pub struct Swappable<T> {
+    current: RwLock<Arc<T>>,
+    generation: AtomicU64,
+}
+
+impl<T> Swappable<T> {
+    pub fn new(value: T) -> Self {
+        Swappable { current: RwLock::new(Arc::new(value)), generation: AtomicU64::new(0) }
+    }
+
+    pub fn current(&self) -> Arc<T> {
+        self.current.read().unwrap().clone()
+    }
+
+    // Swaps in the value update returns for the current one, under the write lock so that two
+    // updates can not overwrite each other. false when update returned None
+    pub fn update(&self, update: impl FnOnce(&T) -> Option<T>) -> bool {
+        let mut current = self.current.write().unwrap();
+        match update(&current) {
+            Some(value) => {
+                *current = Arc::new(value);
+                self.generation.fetch_add(1, Ordering::Release);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn cache(&self) -> Cached<'_, T> {
+        let generation = self.generation.load(Ordering::Acquire);
+        Cached { swappable: self, generation, value: self.current() }
+    }
+}
+
+// A worker's copy, get() is a single atomic load unless the value was swapped
+pub struct Cached<'a, T> {
+    swappable: &'a Swappable<T>,
+    generation: u64,
+    value: Arc<T>,
+}
+
+impl<T> Cached<'_, T> {
+    pub fn get(&mut self) -> &T {
+        let generation = self.swappable.generation.load(Ordering::Acquire);
+        if generation != self.generation {
+            self.generation = generation;
+            self.value = self.swappable.current();
+        }
+        &self.value
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caches_take_the_value_after_an_update() {
+        let swappable = Swappable::new(1);
+        let mut first = swappable.cache();
+        let mut second = swappable.cache();
+        assert_eq!(*first.get(), 1);
+        assert!(!swappable.update(|_| None));
+        assert!(swappable.update(|value| Some(value + 1)));
+        assert_eq!(*first.get(), 2);
+        assert_eq!(*second.get(), 2);
+        assert_eq!(*swappable.current(), 2);
+    }
+}
@@ -1,25 +1,417 @@
-use crate::address::eth_wallet::Wallet;
-use crate::address::polka_wallet::PolkaWallet;
+use crate::address::eth_wallet::{self, Wallet};
+use crate::address::polka_wallet::{self, PolkaWallet};
+use crate::address_manager::{HdCandidate, HdSearchConfig};
 use crate::address_utils::address_utils;
+use crate::base58_prefix::{Base58PrefixFilter, BASE58_ALPHABET};
+use crate::rule_config::ActiveRuleSet;
+use crate::word_matcher::{Anchor, WordMatcher};
 
-use fancy_regex::Regex;
+use fancy_regex::{Regex, RegexBuilder};
 use lazy_static::lazy_static;
+use rand::distributions::{Distribution, WeightedIndex};
+use once_cell::unsync::OnceCell;
+use serde_json::{json, Value};
 use std::collections::HashMap;
-use std::cmp::{Ord, PartialOrd, Ordering};
+use std::cmp::Ord;
 use std::fmt;
+use std::ops::{Deref, RangeInclusive};
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+
+// One rule an address satisfies. rule_id is set by the RuleEntry the rule was checked through,
+// matched and span are the word or char that matched and its position [start, end) in the address.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleMatch {
+    pub rule_id: String,
+    pub parameters: Value,
+    pub matched: Option<String>,
+    pub span: Option<(usize, usize)>,
+    // the measured value, e.g. the zero byte count or the entropy. 1 for plain yes/no rules
+    pub score: f64,
+}
+
+impl RuleMatch {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "rule_id": self.rule_id,
+            "parameters": self.parameters,
+            "matched": self.matched,
+            "span": self.span.map(|(start, end)| vec![start, end]),
+            "score": self.score
+        })
+    }
+
+    // short form for logs, e.g. "start_word decaff", "zero_bytes 6" or "entropy 2.71"
+    pub fn describe(&self) -> String {
+        match &self.matched {
+            Some(matched) => format!("{} {}", self.rule_id, matched),
+            // counts stay integers
+            None if self.score.fract() == 0.0 => format!("{} {}", self.rule_id, self.score),
+            None => format!("{} {:.2}", self.rule_id, self.score),
+        }
+    }
+}
+
+pub fn matches_to_json(matches: &[RuleMatch]) -> Value {
+    Value::Array(matches.iter().map(|rule_match| rule_match.to_json()).collect())
+}
+
+// None without matches, otherwise all of them, e.g. "zero_bytes 6, start_word decaff"
+pub fn describe_matches(matches: &[RuleMatch]) -> Option<String> {
+    if matches.is_empty() {
+        return None;
+    }
+    Some(matches.iter().map(|rule_match| rule_match.describe()).collect::<Vec<_>>().join(", "))
+}
 
 pub trait Rule {
-    fn apply(&self, address_no_prefix: &String) -> bool;
+    // address_no_prefix as ascii bytes, e.g. the 40 lowercase hex chars of an eth address on the stack
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool;
+
+    #[cfg(test)]
+    fn apply(&self, address_no_prefix: &String) -> bool {
+        self.apply_bytes(address_no_prefix.as_bytes())
+    }
+
+    // Details of the match, only called for reporting, the search itself uses apply_bytes
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        if self.apply_bytes(address_no_prefix) {
+            Some(RuleMatch { score: 1.0, ..Default::default() })
+        } else {
+            None
+        }
+    }
+
+    // true for rules on the EIP-55 checksummed form of an eth address, see EthAddressBytes::matches
+    fn needs_checksum(&self) -> bool {
+        false
+    }
+
+    // Cheap check of a checksummed rule on the lowercase address, false when no casing of it can
+    // match, so EthAddressBytes does not compute the checksum for it
+    fn may_match_lowercase(&self, _address_lowercase: &[u8]) -> bool {
+        true
+    }
+
+    // chance that a random address matches, for the rules that know it
+    fn probability(&self) -> Option<f64> {
+        None
+    }
+
+    // an upper bound of the chance, for the rules that could only estimate it as too small to see
+    fn probability_below(&self) -> Option<f64> {
+        None
+    }
+
+    // Prefixes every address this rule matches starts with, from the chars of alphabet, and whether
+    // they are case sensitive. None for rules that can match anywhere, see PolkadotPrefixRules
+    fn start_prefixes(&self, _alphabet: &[u8]) -> Option<(Vec<String>, bool)> {
+        None
+    }
+}
+
+// Failures of a rule on single addresses. Counted, the log only gets the 1st, 10th, 100th..
+#[derive(Default)]
+pub struct FailureCount(AtomicU64);
+
+impl FailureCount {
+    // the count including this failure, and whether it is one to print
+    pub fn record(&self) -> (u64, bool) {
+        let failures = self.0.fetch_add(1, AtomicOrdering::Relaxed) + 1;
+        let mut power = failures;
+        while power.is_multiple_of(10) {
+            power /= 10;
+        }
+        (failures, power == 1)
+    }
+
+    #[cfg(test)]
+    pub fn get(&self) -> u64 {
+        self.0.load(AtomicOrdering::Relaxed)
+    }
+}
+
+// rules loaded from a rule config are boxed
+impl<R: Rule + ?Sized> Rule for Box<R> {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        (**self).apply_bytes(address_no_prefix)
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        (**self).find_match(address_no_prefix)
+    }
+
+    fn needs_checksum(&self) -> bool {
+        (**self).needs_checksum()
+    }
+
+    fn may_match_lowercase(&self, address_lowercase: &[u8]) -> bool {
+        (**self).may_match_lowercase(address_lowercase)
+    }
+
+    fn probability(&self) -> Option<f64> {
+        (**self).probability()
+    }
+
+    fn probability_below(&self) -> Option<f64> {
+        (**self).probability_below()
+    }
+
+    fn start_prefixes(&self, alphabet: &[u8]) -> Option<(Vec<String>, bool)> {
+        (**self).start_prefixes(alphabet)
+    }
+}
+
+// The wrapped rule applied to the checksummed address instead of the lowercase one
+pub struct ChecksummedRule<R: Rule>(pub R);
+
+impl<R: Rule> Rule for ChecksummedRule<R> {
+    fn apply_bytes(&self, address_checksummed: &[u8]) -> bool {
+        self.0.apply_bytes(address_checksummed)
+    }
+
+    fn find_match(&self, address_checksummed: &[u8]) -> Option<RuleMatch> {
+        self.0.find_match(address_checksummed)
+    }
+
+    fn needs_checksum(&self) -> bool {
+        true
+    }
+
+    fn may_match_lowercase(&self, address_lowercase: &[u8]) -> bool {
+        self.0.may_match_lowercase(address_lowercase)
+    }
+
+    fn probability(&self) -> Option<f64> {
+        self.0.probability()
+    }
+
+    fn probability_below(&self) -> Option<f64> {
+        self.0.probability_below()
+    }
+}
+
+impl<R: Rule> Deref for ChecksummedRule<R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        &self.0
+    }
+}
+
+pub type BoxedRule = Box<dyn Rule + Send + Sync>;
+
+// Combinators. The children all see the same form of the address, rule_config does not mix
+// checksummed and lowercase children.
+
+// at least `required` of the children match, the match lists what the matching children matched
+fn combined_match(rules: &[BoxedRule], address_no_prefix: &[u8], required: usize) -> Option<RuleMatch> {
+    let matches: Vec<RuleMatch> = rules.iter().filter_map(|rule| rule.find_match(address_no_prefix)).collect();
+    if matches.len() < required {
+        return None;
+    }
+    let matched: Vec<&str> = matches.iter().filter_map(|rule_match| rule_match.matched.as_deref()).collect();
+    Some(RuleMatch {
+        parameters: json!({ "matched": matches.len(), "of": rules.len() }),
+        matched: if matched.is_empty() { None } else { Some(matched.join(" ")) },
+        span: matches.iter().find_map(|rule_match| rule_match.span),
+        score: matches.len() as f64,
+        ..Default::default()
+    })
+}
+
+fn any_needs_checksum(rules: &[BoxedRule]) -> bool {
+    rules.iter().any(|rule| rule.needs_checksum())
+}
+
+pub struct AllOf(pub Vec<BoxedRule>);
+
+impl Rule for AllOf {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        self.0.iter().all(|rule| rule.apply_bytes(address_no_prefix))
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        combined_match(&self.0, address_no_prefix, self.0.len())
+    }
+
+    fn needs_checksum(&self) -> bool {
+        any_needs_checksum(&self.0)
+    }
+
+    fn may_match_lowercase(&self, address_lowercase: &[u8]) -> bool {
+        self.0.iter().all(|rule| rule.may_match_lowercase(address_lowercase))
+    }
+}
+
+pub struct AnyOf(pub Vec<BoxedRule>);
+
+impl Rule for AnyOf {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        self.0.iter().any(|rule| rule.apply_bytes(address_no_prefix))
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        combined_match(&self.0, address_no_prefix, 1)
+    }
+
+    fn needs_checksum(&self) -> bool {
+        any_needs_checksum(&self.0)
+    }
+
+    fn may_match_lowercase(&self, address_lowercase: &[u8]) -> bool {
+        self.0.iter().any(|rule| rule.may_match_lowercase(address_lowercase))
+    }
+}
+
+pub struct AtLeast(pub usize, pub Vec<BoxedRule>);
+
+impl Rule for AtLeast {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        let mut matched = 0;
+        for (checked, rule) in self.1.iter().enumerate() {
+            if rule.apply_bytes(address_no_prefix) {
+                matched += 1;
+                if matched >= self.0 {
+                    return true;
+                }
+            } else if self.1.len() - checked - 1 < self.0 - matched {
+                // not enough rules left
+                return false;
+            }
+        }
+        matched >= self.0
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        combined_match(&self.1, address_no_prefix, self.0)
+    }
+
+    fn needs_checksum(&self) -> bool {
+        any_needs_checksum(&self.1)
+    }
+
+    fn may_match_lowercase(&self, address_lowercase: &[u8]) -> bool {
+        self.1.iter().filter(|rule| rule.may_match_lowercase(address_lowercase)).count() >= self.0
+    }
+}
+
+pub struct Not(pub BoxedRule);
+
+impl Rule for Not {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        !self.0.apply_bytes(address_no_prefix)
+    }
+
+    fn needs_checksum(&self) -> bool {
+        self.0.needs_checksum()
+    }
+}
+
+// Lowercase hex of an eth address, the checksummed form is only computed once a rule needs it
+pub struct EthAddressBytes<'a> {
+    address_no_prefix: &'a [u8],
+    checksummed: OnceCell<[u8; 40]>,
+}
+
+impl<'a> EthAddressBytes<'a> {
+    pub fn new(address_no_prefix: &'a [u8]) -> Self {
+        EthAddressBytes { address_no_prefix, checksummed: OnceCell::new() }
+    }
+
+    // with a checksummed form that is already known, so it is not computed again
+    pub fn with_checksummed(self, checksummed: Option<&String>) -> Self {
+        if let Some(checksummed) = checksummed.and_then(|checksummed| <[u8; 40]>::try_from(checksummed.as_bytes()).ok()) {
+            let _ = self.checksummed.set(checksummed);
+        }
+        self
+    }
+
+    // None until a rule needed the checksummed form
+    pub fn computed_checksummed(&self) -> Option<&[u8; 40]> {
+        self.checksummed.get()
+    }
+
+    pub fn checksummed(&self) -> &[u8; 40] {
+        self.checksummed.get_or_init(|| {
+            let address_hex: &[u8; 40] = self.address_no_prefix.try_into().expect("eth addresses have 40 hex chars");
+            eth_wallet::checksummed_bytes(address_hex)
+        })
+    }
+
+    pub fn address_no_prefix(&self) -> &[u8] {
+        self.address_no_prefix
+    }
+
+    #[cfg(test)]
+    pub fn is_checksum_computed(&self) -> bool {
+        self.computed_checksummed().is_some()
+    }
+
+    pub fn matches(&self, rule: &dyn Rule) -> bool {
+        if rule.needs_checksum() {
+            rule.may_match_lowercase(self.address_no_prefix) && rule.apply_bytes(self.checksummed())
+        } else {
+            rule.apply_bytes(self.address_no_prefix)
+        }
+    }
+
+    pub fn find_match(&self, entry: &RuleEntry) -> Option<RuleMatch> {
+        let address = if entry.rule.needs_checksum() { &self.checksummed()[..] } else { self.address_no_prefix };
+        entry.find_match(address)
+    }
+
+    // the checksummed rules last, a hit of a lowercase rule needs no checksum
+    pub fn any_match(&self, rule_set: &RuleSet) -> bool {
+        let (lowercase, checksummed) = (|entry: &&RuleEntry| !entry.rule.needs_checksum(), |entry: &&RuleEntry| entry.rule.needs_checksum());
+        rule_set.entries.iter().filter(lowercase).any(|entry| self.matches(&entry.rule))
+            || rule_set.entries.iter().filter(checksummed).any(|entry| self.matches(&entry.rule))
+    }
+
+    pub fn find_matches(&self, rule_set: &RuleSet) -> Vec<RuleMatch> {
+        rule_set.entries.iter().filter_map(|entry| self.find_match(entry)).collect()
+    }
+}
+
+// A rule of a rule set, id names it in the stored matches
+pub struct RuleEntry {
+    pub id: String,
+    pub rule: BoxedRule,
+}
+
+impl RuleEntry {
+    pub fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        self.rule.find_match(address_no_prefix).map(|rule_match| RuleMatch { rule_id: self.id.clone(), ..rule_match })
+    }
+}
+
+// The rules of one profile of a chain, see rule_config
+pub struct RuleSet {
+    pub chain: String,
+    pub profile: String,
+    // stored with every result, see rule_config::version
+    pub version: String,
+    pub entries: Vec<RuleEntry>,
+}
+
+impl RuleSet {
+    // for addresses without a checksummed form, eth goes through EthAddressBytes
+    pub fn any_match(&self, address_no_prefix: &[u8]) -> bool {
+        self.entries.iter().any(|entry| entry.rule.apply_bytes(address_no_prefix))
+    }
+
+    pub fn find_matches(&self, address_no_prefix: &[u8]) -> Vec<RuleMatch> {
+        self.entries.iter().filter_map(|entry| entry.find_match(address_no_prefix)).collect()
+    }
 }
 
 pub struct MetamaskStartEndRule;
 
 impl Rule for MetamaskStartEndRule {
-    fn apply(&self, address_no_prefix: &String) -> bool {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
         // starts with 3, ends with 4 same
         let starting_count:usize = 3;
         let ending_count:usize = 4;
-        let bytes = address_no_prefix.as_bytes();
+        let bytes = address_no_prefix;
 
         for s in 1..starting_count {
             if bytes[s] != bytes[0] {
@@ -34,6 +426,15 @@ impl Rule for MetamaskStartEndRule {
     
         true
     }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        if !self.apply_bytes(address_no_prefix) {
+            return None;
+        }
+        let first_char = address_no_prefix[0] as char;
+        let last_char = address_no_prefix[address_no_prefix.len() - 1] as char;
+        Some(RuleMatch { matched: Some(format!("{}{}", first_char, last_char)), score: 1.0, ..Default::default() })
+    }
 }
 
 impl MetamaskStartEndRule {
@@ -46,11 +447,11 @@ impl MetamaskStartEndRule {
 pub struct PolkadotJsStartEndRule;
 
 impl Rule for PolkadotJsStartEndRule {
-    fn apply(&self, address_no_prefix: &String) -> bool {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
         // starts with 3, ends with 4 same
         let starting_count:usize = 6;
         let ending_count:usize = 6;
-        let bytes = address_no_prefix.as_bytes();
+        let bytes = address_no_prefix;
 
         for s in 1..starting_count {
             if bytes[s] != bytes[0] {
@@ -65,6 +466,15 @@ impl Rule for PolkadotJsStartEndRule {
     
         true
     }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        if !self.apply_bytes(address_no_prefix) {
+            return None;
+        }
+        let first_char = address_no_prefix[0] as char;
+        let last_char = address_no_prefix[address_no_prefix.len() - 1] as char;
+        Some(RuleMatch { matched: Some(format!("{}{}", first_char, last_char)), score: 1.0, ..Default::default() })
+    }
 }
 
 impl PolkadotJsStartEndRule {
@@ -74,58 +484,522 @@ impl PolkadotJsStartEndRule {
 }
 
 
-pub struct StartRule<'a> {
-    starting_words: &'a [&'a str],
-    starting_words_lowercase: Vec<String>,
-    case_sensitive: bool
+pub struct StartRule {
+    starting_words: Vec<String>,
+    case_sensitive: bool,
+    matcher: WordMatcher,
 }
 
-impl<'a> StartRule<'a> {
-    pub fn new(starting_words: &'a [&'a str], case_sensitive: bool) -> Self {
-        let starting_words_low_vec: Vec<String> = starting_words.iter().map(|&w| w.to_lowercase()).collect();
+impl StartRule {
+    pub fn new(starting_words: &[&str], case_sensitive: bool) -> Self {
         Self {
-            starting_words,
-            starting_words_lowercase: starting_words_low_vec,
+            starting_words: starting_words.iter().map(|&w| w.to_string()).collect(),
             case_sensitive,
+            matcher: WordMatcher::starts(starting_words, case_sensitive),
+        }
+    }
+    // the first of the words the address starts with, lowercase when case insensitive
+    fn matched_word_bytes(&self, address_no_prefix: &[u8]) -> Option<&str> {
+        self.matcher.first_match(address_no_prefix).map(|id| self.matcher.target(id).1)
+    }
+}
+
+impl Rule for StartRule {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        self.matched_word_bytes(address_no_prefix).is_some()
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let word = self.matched_word_bytes(address_no_prefix)?;
+        Some(RuleMatch {
+            parameters: json!({ "case_sensitive": self.case_sensitive }),
+            matched: Some(word.to_string()),
+            span: Some((0, word.len())),
+            score: word.len() as f64,
+            ..Default::default()
+        })
+    }
+
+    fn may_match_lowercase(&self, address_lowercase: &[u8]) -> bool {
+        self.starting_words.iter().any(|word| address_lowercase.get(..word.len()).is_some_and(|start| start.eq_ignore_ascii_case(word.as_bytes())))
+    }
+
+    fn start_prefixes(&self, _alphabet: &[u8]) -> Option<(Vec<String>, bool)> {
+        Some((self.starting_words.clone(), self.case_sensitive))
+    }
+}
+
+pub struct EndRule {
+    matcher: WordMatcher,
+}
+
+impl EndRule {
+    pub fn new(ending_words: &[&str], case_sensitive: bool) -> Self {
+        Self { matcher: WordMatcher::ends(ending_words, case_sensitive) }
+    }
+
+    fn matched_word(&self, address_no_prefix: &[u8]) -> Option<&str> {
+        self.matcher.first_match(address_no_prefix).map(|id| self.matcher.target(id).1)
+    }
+}
+
+impl Rule for EndRule {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        self.matched_word(address_no_prefix).is_some()
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let word = self.matched_word(address_no_prefix)?;
+        Some(RuleMatch {
+            parameters: json!({ "case_sensitive": self.matcher.case_sensitive() }),
+            matched: Some(word.to_string()),
+            span: Some((address_no_prefix.len() - word.len(), address_no_prefix.len())),
+            score: word.len() as f64,
+            ..Default::default()
+        })
+    }
+}
+
+// one of the words anywhere in the address, or starting within positions
+pub struct ContainsWordRule {
+    // the words as starts, checked from every position
+    matcher: WordMatcher,
+    positions: RangeInclusive<usize>,
+}
+
+impl ContainsWordRule {
+    pub fn new(words: &[&str], case_sensitive: bool) -> Self {
+        Self { matcher: WordMatcher::starts(words, case_sensitive), positions: 0..=usize::MAX }
+    }
+
+    // the word has to start at a position from min to max
+    pub fn at_positions(mut self, min: usize, max: usize) -> Self {
+        self.positions = min..=max;
+        self
+    }
+
+    // the first word of the list that is in the address, at its first position
+    fn find(&self, address_no_prefix: &[u8]) -> Option<(&str, usize)> {
+        let last = (*self.positions.end()).min(address_no_prefix.len().saturating_sub(1));
+        let mut first: Option<(usize, usize)> = None;
+        for position in *self.positions.start()..=last {
+            if let Some(id) = self.matcher.first_match(&address_no_prefix[position..]) {
+                if first.is_none_or(|(first, _)| id < first) {
+                    first = Some((id, position));
+                }
+            }
+        }
+        first.map(|(id, position)| (self.matcher.target(id).1, position))
+    }
+}
+
+impl Rule for ContainsWordRule {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        self.find(address_no_prefix).is_some()
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let (word, position) = self.find(address_no_prefix)?;
+        let mut parameters = json!({ "case_sensitive": self.matcher.case_sensitive() });
+        if *self.positions.end() != usize::MAX || *self.positions.start() != 0 {
+            parameters["min_position"] = json!(self.positions.start());
+            parameters["max_position"] = json!(self.positions.end());
+        }
+        Some(RuleMatch {
+            parameters,
+            matched: Some(word.to_string()),
+            span: Some((position, position + word.len())),
+            score: word.len() as f64,
+            ..Default::default()
+        })
+    }
+}
+
+// one of `starts` at the start and one of `ends` at the end, e.g. c0ffee...beef
+pub struct PrefixSuffixRule {
+    starts: WordMatcher,
+    ends: WordMatcher,
+    // start and end may share chars of short addresses
+    overlap: bool,
+    // case insensitive starts and ends for may_match_lowercase, when the rule is case sensitive
+    folded: Option<(WordMatcher, WordMatcher)>,
+}
+
+impl PrefixSuffixRule {
+    pub fn new(starts: &[&str], ends: &[&str], case_sensitive: bool) -> Self {
+        Self {
+            starts: WordMatcher::starts(starts, case_sensitive),
+            ends: WordMatcher::ends(ends, case_sensitive),
+            overlap: false,
+            folded: case_sensitive.then(|| (WordMatcher::starts(starts, false), WordMatcher::ends(ends, false))),
+        }
+    }
+
+    pub fn allowing_overlap(mut self) -> Self {
+        self.overlap = true;
+        self
+    }
+
+    // The first start that leaves room for an end, then the first end that fits after it.
+    // With overlap the first start and the first end
+    fn matched_words(&self, address_no_prefix: &[u8]) -> Option<(&str, &str)> {
+        let length = address_no_prefix.len();
+        let word_length = |matcher: &WordMatcher, id: usize| matcher.target(id).1.len();
+        let first_fitting = |matcher: &WordMatcher, room: usize| {
+            let mut first: Option<usize> = None;
+            matcher.for_each_match(address_no_prefix, |id| if word_length(matcher, id) <= room {
+                first = Some(first.map_or(id, |first| first.min(id)));
+            });
+            first
+        };
+        if self.overlap {
+            let start = first_fitting(&self.starts, length)?;
+            let end = first_fitting(&self.ends, length)?;
+            return Some((self.starts.target(start).1, self.ends.target(end).1));
+        }
+        // ends are reported shortest first
+        let mut shortest_end: Option<usize> = None;
+        self.ends.for_each_match(address_no_prefix, |id| { shortest_end.get_or_insert(word_length(&self.ends, id)); });
+        let start = first_fitting(&self.starts, length.checked_sub(shortest_end?)?)?;
+        let end = first_fitting(&self.ends, length - word_length(&self.starts, start))?;
+        Some((self.starts.target(start).1, self.ends.target(end).1))
+    }
+}
+
+impl Rule for PrefixSuffixRule {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        self.matched_words(address_no_prefix).is_some()
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let (start, end) = self.matched_words(address_no_prefix)?;
+        Some(RuleMatch {
+            parameters: json!({ "case_sensitive": self.starts.case_sensitive(), "overlap": self.overlap }),
+            matched: Some(format!("{}..{}", start, end)),
+            span: Some((0, address_no_prefix.len())),
+            score: (start.len() + end.len()) as f64,
+            ..Default::default()
+        })
+    }
+
+    fn may_match_lowercase(&self, address_lowercase: &[u8]) -> bool {
+        let (starts, ends) = self.folded.as_ref().map_or((&self.starts, &self.ends), |(starts, ends)| (starts, ends));
+        starts.first_match(address_lowercase).is_some() && ends.first_match(address_lowercase).is_some()
+    }
+}
+
+// Many start and end targets at once, the match lists every target the address has
+pub struct TargetsRule {
+    matcher: WordMatcher,
+}
+
+impl TargetsRule {
+    pub fn new(starts: &[&str], ends: &[&str], case_sensitive: bool) -> Self {
+        let targets: Vec<(Anchor, &str)> = starts.iter().map(|&word| (Anchor::Start, word))
+            .chain(ends.iter().map(|&word| (Anchor::End, word)))
+            .collect();
+        Self { matcher: WordMatcher::new(&targets, case_sensitive) }
+    }
+}
+
+impl Rule for TargetsRule {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        self.matcher.first_match(address_no_prefix).is_some()
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let ids = self.matcher.matches(address_no_prefix);
+        if ids.is_empty() {
+            return None;
+        }
+        let targets: Vec<Value> = ids.iter()
+            .map(|&id| match self.matcher.target(id) {
+                (Anchor::Start, word) => json!({ "start": word }),
+                (Anchor::End, word) => json!({ "end": word }),
+            })
+            .collect();
+        let words: Vec<&str> = ids.iter().map(|&id| self.matcher.target(id).1).collect();
+        Some(RuleMatch {
+            parameters: json!({ "case_sensitive": self.matcher.case_sensitive(), "targets": targets }),
+            matched: Some(words.join("+")),
+            score: ids.len() as f64,
+            ..Default::default()
+        })
+    }
+}
+
+// The first chars mirror the last ones, abc....cba. The score is the number of mirrored chars
+// at each end, half the length for a full palindrome.
+pub struct PalindromeRule {
+    min_length: usize,
+}
+
+impl PalindromeRule {
+    pub fn new(min_length: usize) -> Self {
+        Self { min_length }
+    }
+
+    fn mirrored_length(address_no_prefix: &[u8]) -> usize {
+        let length = address_no_prefix.len();
+        (0..length / 2).take_while(|&i| address_no_prefix[i] == address_no_prefix[length - 1 - i]).count()
+    }
+}
+
+impl Rule for PalindromeRule {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        let length = address_no_prefix.len();
+        self.min_length <= length / 2 && (0..self.min_length).all(|i| address_no_prefix[i] == address_no_prefix[length - 1 - i])
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let mirrored = PalindromeRule::mirrored_length(address_no_prefix);
+        if mirrored < self.min_length {
+            return None;
+        }
+        Some(RuleMatch {
+            parameters: json!({ "min": self.min_length, "full": mirrored == address_no_prefix.len() / 2 }),
+            matched: Some(String::from_utf8_lossy(&address_no_prefix[..mirrored]).into_owned()),
+            span: Some((0, mirrored)),
+            score: mirrored as f64,
+            ..Default::default()
+        })
+    }
+}
+
+// The address starts and ends with the same chars, abc....abc. The score is the length of the
+// longest such bookend, start and end do not overlap.
+pub struct BookendRule {
+    min_length: usize,
+}
+
+impl BookendRule {
+    pub fn new(min_length: usize) -> Self {
+        Self { min_length }
+    }
+
+    fn is_bookend(address_no_prefix: &[u8], length: usize) -> bool {
+        // an empty bookend is no bookend, also for rules built with a min of 0
+        if length == 0 || 2 * length > address_no_prefix.len() {
+            return false;
+        }
+        let end = address_no_prefix.len() - length;
+        // the first char rules out most lengths without comparing the rest
+        address_no_prefix[0] == address_no_prefix[end] && address_no_prefix[..length] == address_no_prefix[end..]
+    }
+}
+
+impl Rule for BookendRule {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        (self.min_length..=address_no_prefix.len() / 2).any(|length| BookendRule::is_bookend(address_no_prefix, length))
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let length = (self.min_length..=address_no_prefix.len() / 2).rev().find(|&length| BookendRule::is_bookend(address_no_prefix, length))?;
+        Some(RuleMatch {
+            parameters: json!({ "min": self.min_length }),
+            matched: Some(String::from_utf8_lossy(&address_no_prefix[..length]).into_owned()),
+            span: Some((0, length)),
+            score: length as f64,
+            ..Default::default()
+        })
+    }
+}
+
+// Masks like dead????????????????????????????????beef or 0000*, ? is any char and * any run of chars.
+// A mask without * has to cover the whole address. The parts between the *s are compiled to
+// fixed offset byte comparisons, the first part is checked at the start and the last at the end.
+pub struct MaskRule {
+    pattern: String,
+    segments: Vec<MaskSegment>,
+    case_sensitive: bool,
+    probability: Option<f64>,
+    // reported as approximate_probability, see match_probability
+    approximate: bool,
+}
+
+struct MaskSegment {
+    length: usize,
+    // (offset, byte) of the chars that are not ?
+    fixed: Vec<(usize, u8)>,
+}
+
+// chars of a mask, for the probability
+#[derive(Clone, Copy)]
+enum MaskToken {
+    Char(u8),
+    Any,
+    Run,
+}
+
+impl MaskRule {
+    pub fn new(pattern: &str, case_sensitive: bool) -> Result<Self, String> {
+        if pattern.is_empty() || !pattern.is_ascii() {
+            return Err(format!("mask \"{}\" must be a non empty ascii pattern", pattern));
+        }
+        let mut folded = if case_sensitive { pattern.to_string() } else { pattern.to_lowercase() };
+        while folded.contains("**") {
+            folded = folded.replace("**", "*");
+        }
+        // one bit per char in match_probability
+        if folded.len() > 63 {
+            return Err(format!("mask \"{}\" is longer than 63 chars", pattern));
+        }
+        let segments = folded.split('*')
+            .map(|segment| MaskSegment {
+                length: segment.len(),
+                fixed: segment.bytes().enumerate().filter(|&(_, c)| c != b'?').collect(),
+            })
+            .collect();
+        Ok(Self { pattern: folded, segments, case_sensitive, probability: None, approximate: false })
+    }
+
+    // without * the pattern has this length, with * it is the least
+    pub fn fixed_length(&self) -> usize {
+        self.segments.iter().map(|segment| segment.length).sum()
+    }
+
+    pub fn has_run(&self) -> bool {
+        self.segments.len() > 1
+    }
+
+    // the chars the pattern compares, ? and * removed
+    pub fn literals(&self) -> String {
+        self.pattern.chars().filter(|&c| c != '?' && c != '*').collect()
+    }
+
+    // the literal of masks like c0ffee*, which only fix the start
+    pub fn literal_prefix(&self) -> Option<&str> {
+        let (first, rest) = self.segments.split_first()?;
+        let only_start = first.length > 0 && first.fixed.len() == first.length && rest.len() == 1 && rest[0].length == 0;
+        only_start.then(|| &self.pattern[..first.length])
+    }
+
+    // stored to be reported with the matches
+    pub fn with_probability(mut self, probability: f64, approximate: bool) -> Self {
+        self.probability = Some(probability);
+        self.approximate = approximate;
+        self
+    }
+
+    fn is_at(&self, segment: &MaskSegment, address_no_prefix: &[u8], position: usize) -> bool {
+        segment.fixed.iter().all(|&(offset, c)| {
+            let found = address_no_prefix[position + offset];
+            if self.case_sensitive { found == c } else { found.to_ascii_lowercase() == c }
+        })
+    }
+
+    // [start, end) of the address the non-empty parts cover, the leftmost placement of the middle parts
+    fn find(&self, address_no_prefix: &[u8]) -> Option<(usize, usize)> {
+        let length = address_no_prefix.len();
+        let first = &self.segments[0];
+        let last = &self.segments[self.segments.len() - 1];
+        if !self.has_run() {
+            return (length == first.length && self.is_at(first, address_no_prefix, 0)).then_some((0, length));
+        }
+        if first.length + last.length > length {
+            return None;
+        }
+        let last_start = length - last.length;
+        if !self.is_at(first, address_no_prefix, 0) || !self.is_at(last, address_no_prefix, last_start) {
+            return None;
+        }
+        let mut span: Option<(usize, usize)> = (first.length > 0).then_some((0, first.length));
+        let mut position = first.length;
+        for segment in &self.segments[1..self.segments.len() - 1] {
+            let found = (position..=last_start.checked_sub(segment.length)?).find(|&start| self.is_at(segment, address_no_prefix, start))?;
+            span = Some((span.map_or(found, |(start, _)| start), found + segment.length));
+            position = found + segment.length;
+        }
+        if last.length > 0 {
+            span = Some((span.map_or(last_start, |(start, _)| start), length));
         }
+        Some(span.unwrap_or((0, length)))
     }
-    fn matched_word(&self, address_no_prefix: &String) -> &str {
-        for &word in self.starting_words {
-            if address_no_prefix.starts_with(word) {
-                return word;
+
+    // Exact chance that `length` independent chars drawn from alphabet, (char, probability) pairs,
+    // match the mask. Runs the pattern as an NFA over the sets of states it can be in, the
+    // alphabet is grouped into the chars of the pattern and one class for all other chars.
+    // Only lowercase hex addresses are such chars. Base58 chars are not independent, the first
+    // ones cover only part of the alphabet and the length varies, and the letters of a checksummed
+    // address are uppercase with about, not exactly, ½, so for those it is an approximation.
+    pub fn match_probability(&self, alphabet: &[(u8, f64)], length: usize) -> f64 {
+        let tokens: Vec<MaskToken> = self.pattern.bytes()
+            .map(|c| match c { b'?' => MaskToken::Any, b'*' => MaskToken::Run, c => MaskToken::Char(c) })
+            .collect();
+        let closure = |mut states: u64| {
+            for (i, token) in tokens.iter().enumerate() {
+                if states & (1 << i) != 0 && matches!(token, MaskToken::Run) {
+                    states |= 1 << (i + 1);
+                }
+            }
+            states
+        };
+        let step = |states: u64, c: Option<u8>| {
+            let mut next = 0u64;
+            for (i, token) in tokens.iter().enumerate() {
+                if states & (1 << i) == 0 {
+                    continue;
+                }
+                match token {
+                    MaskToken::Char(expected) if Some(*expected) == c => next |= 1 << (i + 1),
+                    MaskToken::Char(_) => {}
+                    MaskToken::Any => next |= 1 << (i + 1),
+                    MaskToken::Run => next |= 1 << i,
+                }
+            }
+            closure(next)
+        };
+
+        let mut classes: Vec<(Option<u8>, f64)> = Vec::new();
+        for &(c, probability) in alphabet {
+            let c = if self.case_sensitive { c } else { c.to_ascii_lowercase() };
+            let class = tokens.iter().any(|token| matches!(token, MaskToken::Char(expected) if *expected == c)).then_some(c);
+            match classes.iter_mut().find(|(other, _)| *other == class) {
+                Some((_, total)) => *total += probability,
+                None => classes.push((class, probability)),
             }
         }
-        ""
-    }
 
-    fn matched_word_case_insensitive(&self, address_no_prefix: &String) -> &str {
-        let address_no_prefix_lowercase = address_no_prefix.to_lowercase();
-        for word in &self.starting_words_lowercase {
-            if address_no_prefix_lowercase.starts_with(word) {
-                return &word;
+        let mut distribution: HashMap<u64, f64> = HashMap::from([(closure(1), 1.0)]);
+        for _ in 0..length {
+            let mut next: HashMap<u64, f64> = HashMap::new();
+            for (&states, &probability) in &distribution {
+                for &(class, class_probability) in &classes {
+                    let states = step(states, class);
+                    if states != 0 {
+                        *next.entry(states).or_insert(0.0) += probability * class_probability;
+                    }
+                }
             }
+            distribution = next;
         }
-        ""
+        let accept = 1u64 << tokens.len();
+        distribution.iter().filter(|(&states, _)| states & accept != 0).map(|(_, probability)| probability).sum()
     }
 }
 
-impl<'a> Rule for StartRule<'a> {
-    fn apply(&self, address_no_prefix: &String) -> bool {
-        if self.case_sensitive {
-            for &word in self.starting_words {
-                if address_no_prefix.starts_with(word) {
-                    return true;
-                }
-            }
-        } else {
-            let address_no_prefix_lowercase = address_no_prefix.to_lowercase();
-            for word in &self.starting_words_lowercase {
-                if address_no_prefix_lowercase.starts_with(word) {
-                    return true;
-                }
-            }
+impl Rule for MaskRule {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        self.find(address_no_prefix).is_some()
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let span = self.find(address_no_prefix)?;
+        let mut parameters = json!({ "pattern": self.pattern, "case_sensitive": self.case_sensitive });
+        if let Some(probability) = self.probability {
+            parameters[if self.approximate { "approximate_probability" } else { "probability" }] = json!(probability);
         }
-        false
+        Some(RuleMatch {
+            parameters,
+            matched: Some(String::from_utf8_lossy(&address_no_prefix[span.0..span.1]).into_owned()),
+            span: Some(span),
+            score: self.segments.iter().map(|segment| segment.fixed.len()).sum::<usize>() as f64,
+            ..Default::default()
+        })
+    }
+
+    fn probability(&self) -> Option<f64> {
+        self.probability
     }
 }
 
@@ -142,8 +1016,8 @@ impl ContainsConsecutiveCharsWindowRule {
 }
 
 impl Rule for ContainsConsecutiveCharsWindowRule {
-    fn apply(&self, address_no_prefix: &String) -> bool {
-        address_no_prefix.as_bytes()
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        address_no_prefix
             .windows(self.consecutive_chars_amount)
             .any(|window| window.iter().all(|&ch| ch == window[0]))
     }
@@ -172,8 +1046,222 @@ impl ContainsConsecutiveCharsRegexRule {
 }
 
 impl Rule for ContainsConsecutiveCharsRegexRule {
-    fn apply(&self, address_no_prefix: &String) -> bool {
-        self.regex.is_match(address_no_prefix).unwrap()
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        match std::str::from_utf8(address_no_prefix) {
+            Ok(address_no_prefix) => self.regex.is_match(address_no_prefix).unwrap(),
+            Err(_) => false,
+        }
+    }
+}
+
+// A user supplied pattern. Patterns without lookaround or backreferences run on the linear regex
+// engine, the others backtrack, so nested quantifiers are rejected and every match is limited to
+// REGEX_BACKTRACK_LIMIT steps.
+pub struct RegexRule {
+    pattern: String,
+    regex: Regex,
+    // (hits, samples) of with_sampled_probability
+    sampled: Option<(usize, usize)>,
+    // of patterns that are an anchored literal, ^dead or beef$
+    exact: Option<f64>,
+    failures: FailureCount,
+}
+
+const REGEX_BACKTRACK_LIMIT: usize = 100_000;
+
+impl RegexRule {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        check_nested_quantifiers(pattern)?;
+        let regex = RegexBuilder::new(pattern)
+            .backtrack_limit(REGEX_BACKTRACK_LIMIT)
+            .build()
+            .map_err(|e| format!("regex {}: {}", pattern, e))?;
+        Ok(Self { pattern: pattern.to_string(), regex, sampled: None, exact: None, failures: FailureCount::default() })
+    }
+
+    // Estimates the probability from random addresses of `length` chars drawn from alphabet,
+    // (char, probability) pairs, anchored literals get the exact one. Runs of a single char are
+    // tried first, they are the usual worst case for backtracking, a pattern that hits the limit on
+    // any of them is rejected.
+    pub fn with_sampled_probability(mut self, alphabet: &[(u8, f64)], length: usize, samples: usize) -> Result<Self, String> {
+        let distribution = WeightedIndex::new(alphabet.iter().map(|(_, probability)| *probability)).map_err(|e| e.to_string())?;
+        let mut rng = rand::thread_rng();
+        let runs = alphabet.iter().map(|&(c, _)| vec![c; length]);
+        let random = (0..samples).map(|_| (0..length).map(|_| alphabet[distribution.sample(&mut rng)].0).collect::<Vec<u8>>());
+        let mut hits = 0;
+        for (i, address) in runs.chain(random).enumerate() {
+            let text = std::str::from_utf8(&address).map_err(|e| e.to_string())?;
+            let matched = self.regex.is_match(text).map_err(|e| format!("regex {} on {}: {}", self.pattern, text, e))?;
+            if matched && i >= alphabet.len() {
+                hits += 1;
+            }
+        }
+        self.sampled = Some((hits, samples));
+        self.exact = anchored_literal(&self.pattern).map(|literal| match literal.len() <= length {
+            true => literal.bytes().map(|c| alphabet.iter().filter(|&&(a, _)| a == c).map(|(_, p)| p).sum::<f64>()).product(),
+            false => 0.0,
+        });
+        Ok(self)
+    }
+
+    // A failure is a candidate over the backtrack limit, which bounds its cost, so the rule stays on
+    fn find(&self, address_no_prefix: &[u8]) -> Option<(usize, usize)> {
+        let text = std::str::from_utf8(address_no_prefix).ok()?;
+        match self.regex.find(text) {
+            Ok(found) => found.map(|found| (found.start(), found.end())),
+            Err(e) => {
+                let (failures, print) = self.failures.record();
+                if print {
+                    println!("Regex {} failed on {} (failure {}): {}", self.pattern, text, failures, e);
+                }
+                None
+            }
+        }
+    }
+}
+
+// the literal of ^literal or literal$, alphanumeric chars only
+fn anchored_literal(pattern: &str) -> Option<&str> {
+    let literal = match (pattern.strip_prefix('^'), pattern.strip_suffix('$')) {
+        (Some(literal), None) | (None, Some(literal)) => literal,
+        _ => return None,
+    };
+    (!literal.is_empty() && literal.bytes().all(|c| c.is_ascii_alphanumeric())).then_some(literal)
+}
+
+// The alphanumeric chars the pattern matches literally, also in classes. Escapes, quantifier
+// counts, group flags and names, and class ranges like a-z are skipped.
+pub fn regex_literals(pattern: &str) -> Vec<char> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut literals = Vec::new();
+    let mut in_class = false;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' if !in_class => {
+                in_class = true;
+                if chars.get(i + 1) == Some(&'^') {
+                    i += 1;
+                }
+            }
+            ']' if in_class => in_class = false,
+            '{' if !in_class => {
+                while i < chars.len() && chars[i] != '}' {
+                    i += 1;
+                }
+            }
+            '(' if !in_class && chars.get(i + 1) == Some(&'?') => {
+                while i + 1 < chars.len() && !":)=!>".contains(chars[i + 1]) {
+                    i += 1;
+                }
+            }
+            _ if in_class && chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&end| end != ']') => i += 2,
+            c if c.is_ascii_alphanumeric() => literals.push(c),
+            _ => {}
+        }
+        i += 1;
+    }
+    literals
+}
+
+// (x+)+, (x*)*, (x+){2,} and the like, the patterns that backtrack exponentially
+fn check_nested_quantifiers(pattern: &str) -> Result<(), String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    // per open group: its start and whether it repeats something inside
+    let mut groups: Vec<(usize, bool)> = Vec::new();
+    let mut repeats = false;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' => {
+                // skip the class, a ] right after [ or [^ is a literal
+                i += 1;
+                if chars.get(i) == Some(&'^') {
+                    i += 1;
+                }
+                i += 1;
+                while i < chars.len() && chars[i] != ']' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            '(' => {
+                groups.push((i, repeats));
+                repeats = false;
+            }
+            ')' => {
+                let (start, outer_repeats) = groups.pop().ok_or_else(|| format!("regex {}: unbalanced ) at {}", pattern, i))?;
+                let repeated = is_repeating_quantifier(&chars[i + 1..]);
+                if repeats && repeated {
+                    return Err(format!("regex {}: the group at {} repeats a repetition, this can backtrack exponentially", pattern, start));
+                }
+                repeats = outer_repeats || repeats || repeated;
+            }
+            '*' | '+' => repeats = true,
+            '{' if is_repeating_quantifier(&chars[i..]) => repeats = true,
+            _ => {}
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+// *, + or {n,} / {n,m} with m > 1 at the start of rest
+fn is_repeating_quantifier(rest: &[char]) -> bool {
+    match rest.first() {
+        Some('*') | Some('+') => true,
+        Some('{') => {
+            let end = match rest.iter().position(|&c| c == '}') {
+                Some(end) => end,
+                None => return false,
+            };
+            let inner: String = rest[1..end].iter().collect();
+            match inner.split_once(',') {
+                Some((_, "")) => true,
+                Some((_, max)) => max.trim().parse::<usize>().is_ok_and(|max| max > 1),
+                None => inner.trim().parse::<usize>().is_ok_and(|count| count > 1),
+            }
+        }
+        _ => false,
+    }
+}
+
+impl Rule for RegexRule {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        self.find(address_no_prefix).is_some()
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let (start, end) = self.find(address_no_prefix)?;
+        let mut parameters = json!({ "pattern": self.pattern });
+        if let Some((hits, samples)) = self.sampled {
+            parameters["sample_hits"] = json!(hits);
+            parameters["samples"] = json!(samples);
+        }
+        Some(RuleMatch {
+            parameters,
+            matched: Some(String::from_utf8_lossy(&address_no_prefix[start..end]).into_owned()),
+            span: Some((start, end)),
+            score: (end - start) as f64,
+            ..Default::default()
+        })
+    }
+
+    // exact for anchored literals, else the sampled estimate, none without hits
+    fn probability(&self) -> Option<f64> {
+        self.exact.or_else(|| self.sampled.filter(|(hits, _)| *hits > 0).map(|(hits, samples)| hits as f64 / samples as f64))
+    }
+
+    // no hit in the samples
+    fn probability_below(&self) -> Option<f64> {
+        match self.probability() {
+            Some(_) => None,
+            None => self.sampled.map(|(_, samples)| 1.0 / samples as f64),
+        }
     }
 }
 
@@ -190,10 +1278,10 @@ impl ContainsConsecutiveCharsCounterRule {
 }
 
 impl Rule for ContainsConsecutiveCharsCounterRule {
-    fn apply(&self, address_no_prefix: &String) -> bool {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
         let mut counter = 0;
-        let mut last_char = ' ';
-        for c in address_no_prefix.chars() {
+        let mut last_char = b' ';
+        for &c in address_no_prefix {
             if c == last_char {
                 counter += 1;
                 if counter >= self.consecutive_chars_amount {
@@ -206,6 +1294,20 @@ impl Rule for ContainsConsecutiveCharsCounterRule {
         }
         false
     }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let (start, length) = longest_run(address_no_prefix);
+        if length < self.consecutive_chars_amount {
+            return None;
+        }
+        Some(RuleMatch {
+            parameters: json!({ "min": self.consecutive_chars_amount }),
+            matched: Some((address_no_prefix[start] as char).to_string()),
+            span: Some((start, start + length)),
+            score: length as f64,
+            ..Default::default()
+        })
+    }
 }
 
 pub struct StartsConsecutiveCharsCounterRule {
@@ -221,8 +1323,8 @@ impl StartsConsecutiveCharsCounterRule {
 }
 
 impl Rule for StartsConsecutiveCharsCounterRule {
-    fn apply(&self, address_no_prefix: &String) -> bool {
-        let bytes = address_no_prefix.as_bytes();
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        let bytes = address_no_prefix;
         for s in 1..self.consecutive_chars_amount {
             if bytes[s] != bytes[0] {
                 return false;
@@ -230,6 +1332,25 @@ impl Rule for StartsConsecutiveCharsCounterRule {
         }
         true
     }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        if !self.apply_bytes(address_no_prefix) {
+            return None;
+        }
+        let length = address_no_prefix.iter().take_while(|&&c| c == address_no_prefix[0]).count();
+        Some(RuleMatch {
+            parameters: json!({ "min": self.consecutive_chars_amount }),
+            matched: Some((address_no_prefix[0] as char).to_string()),
+            span: Some((0, length)),
+            score: length as f64,
+            ..Default::default()
+        })
+    }
+
+    // "1111", "2222", ... for every char of the alphabet
+    fn start_prefixes(&self, alphabet: &[u8]) -> Option<(Vec<String>, bool)> {
+        Some((alphabet.iter().map(|&c| (c as char).to_string().repeat(self.consecutive_chars_amount)).collect(), true))
+    }
 }
 
 pub struct CharCounterRule {
@@ -286,11 +1407,19 @@ impl CharCounterRule {
 }
 
 impl Rule for CharCounterRule {
-    fn apply(&self, address_no_prefix: &String) -> bool {
-        let score = self.evaluate_vanity_quality(address_no_prefix);
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        let score = self.evaluate_vanity_quality(std::str::from_utf8(address_no_prefix).unwrap_or(""));
         // Here you need to decide what score is considered "high quality"
         score > 1000000.0
     }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        if !self.apply_bytes(address_no_prefix) {
+            return None;
+        }
+        let score = self.evaluate_vanity_quality(std::str::from_utf8(address_no_prefix).unwrap_or(""));
+        Some(RuleMatch { parameters: json!({ "min": 1000000.0 }), score, ..Default::default() })
+    }
 }
 
 
@@ -301,8 +1430,21 @@ pub struct CharEntropyRule {
 }
 
 impl Rule for CharEntropyRule {
-    fn apply(&self, address_no_prefix: &String) -> bool {
-        address_utils::calculate_entropy(address_no_prefix) <= self.entropy_coefficient_max_boundary
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        address_utils::calculate_entropy_bytes(address_no_prefix) <= self.entropy_coefficient_max_boundary
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let entropy = address_utils::calculate_entropy_bytes(address_no_prefix);
+        if entropy > self.entropy_coefficient_max_boundary {
+            return None;
+        }
+        Some(RuleMatch { parameters: json!({ "max": self.entropy_coefficient_max_boundary }), score: entropy, ..Default::default() })
+    }
+
+    // uppercasing some letters only splits chars apart, the entropy never gets lower
+    fn may_match_lowercase(&self, address_lowercase: &[u8]) -> bool {
+        self.apply_bytes(address_lowercase)
     }
 }
 
@@ -322,8 +1464,25 @@ pub struct ProximityCoefficientRule {
 }
 
 impl Rule for ProximityCoefficientRule {
-    fn apply(&self, address_no_prefix: &String) -> bool {
-        address_utils::calculate_proximity_coefficient(address_no_prefix, self.proximity_max_distance) >= self.proximity_coefficient_min_boundary
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
+        address_utils::calculate_proximity_coefficient_bytes(address_no_prefix, self.proximity_max_distance) >= self.proximity_coefficient_min_boundary
+    }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let proximity = address_utils::calculate_proximity_coefficient_bytes(address_no_prefix, self.proximity_max_distance);
+        if proximity < self.proximity_coefficient_min_boundary {
+            return None;
+        }
+        Some(RuleMatch {
+            parameters: json!({ "min": self.proximity_coefficient_min_boundary, "max_distance": self.proximity_max_distance }),
+            score: proximity,
+            ..Default::default()
+        })
+    }
+
+    // uppercasing some letters only drops pairs of equal chars, the coefficient never gets higher
+    fn may_match_lowercase(&self, address_lowercase: &[u8]) -> bool {
+        self.apply_bytes(address_lowercase)
     }
 }
 
@@ -340,9 +1499,17 @@ pub struct ZeroBytesRule{
 }
 
 impl Rule for ZeroBytesRule {
-    fn apply(&self, address_no_prefix: &String) -> bool {
+    fn apply_bytes(&self, address_no_prefix: &[u8]) -> bool {
         self.count_zero_bytes(address_no_prefix) >= self.zero_bytes_count
     }
+
+    fn find_match(&self, address_no_prefix: &[u8]) -> Option<RuleMatch> {
+        let zero_bytes_count = self.count_zero_bytes(address_no_prefix);
+        if zero_bytes_count < self.zero_bytes_count {
+            return None;
+        }
+        Some(RuleMatch { parameters: json!({ "min": self.zero_bytes_count }), score: zero_bytes_count as f64, ..Default::default() })
+    }
 }
 
 impl ZeroBytesRule {
@@ -351,8 +1518,8 @@ impl ZeroBytesRule {
             zero_bytes_count
         }
     }
-    fn count_zero_bytes(&self, address_no_prefix: &String) -> usize {
-        let bytes = address_no_prefix.as_bytes();
+    fn count_zero_bytes(&self, address_no_prefix: &[u8]) -> usize {
+        let bytes = address_no_prefix;
         let mut count = 0;
         for i in (0..bytes.len()).step_by(2) {
             if bytes[i] == b'0' && bytes[i + 1] == b'0' {
@@ -366,33 +1533,38 @@ impl ZeroBytesRule {
 
 
 
+// distance of the proximity coefficients stored with every result
+const PROXIMITY_MAX_DISTANCE: usize = 3;
+
+lazy_static! {
+    // RULE_PROFILE of rules/eth.json, main checks it can be loaded before the search starts
+    pub static ref ETH_RULES: ActiveRuleSet = ActiveRuleSet::from_env("eth").unwrap_or_else(|e| panic!("{}", e));
+}
+
+
 lazy_static! {
-    pub static ref METAMASK_RULE: MetamaskStartEndRule = MetamaskStartEndRule::new();
-    pub static ref CONSECUTIVE_CHARS_RULE: ContainsConsecutiveCharsCounterRule = ContainsConsecutiveCharsCounterRule::new(9);
-    pub static ref START_RULE: StartRule<'static> = StartRule::new(&["decaff", "facade", "c0ffee", "dec0de", "01234567", "12345678", "abcdef", "fedcba", "98765432"], true);
-    pub static ref START_CONSECUTIVE_CHARS_RULE: StartsConsecutiveCharsCounterRule = StartsConsecutiveCharsCounterRule::new(7);
-    pub static ref ZERO_BYTES_RULE: ZeroBytesRule = ZeroBytesRule::new(5);
-    pub static ref CHAR_ENTROPY_RULE: CharEntropyRule = CharEntropyRule::new(2.8);
-    pub static ref PROXIMITY_RULE: ProximityCoefficientRule = ProximityCoefficientRule::new(23.0, 3);
-    pub static ref CHAR_ENTROPY_RULE_3: CharEntropyRule = CharEntropyRule::new(3.0);
-    pub static ref PROXIMITY_RULE_3_21: ProximityCoefficientRule = ProximityCoefficientRule::new(21.0, 3);
+    // RULE_PROFILE of rules/btc.json
+    pub static ref BTC_RULES: ActiveRuleSet = ActiveRuleSet::from_env("btc").unwrap_or_else(|e| panic!("{}", e));
 }
 
 
 lazy_static! {
-    pub static ref POLKADOT_POLKADOT_JS: PolkadotJsStartEndRule = PolkadotJsStartEndRule::new();
-    pub static ref POLKADOT_CONSECUTIVE_CHARS_RULE: ContainsConsecutiveCharsCounterRule = ContainsConsecutiveCharsCounterRule::new(6);
-    pub static ref POLKADOT_START_RULE: StartRule<'static> = StartRule::new(&["012345", "123456", "abcdef", "dev", "crypto", "web3", "coin", "chain", "wallet", "star", "galaxy", "zen", "future", "byte", "quantum"], false);
-    pub static ref POLKADOT_START_CONSECUTIVE_CHARS_RULE: StartsConsecutiveCharsCounterRule = StartsConsecutiveCharsCounterRule::new(4);
-    pub static ref POLKADOT_CHAR_ENTROPY_RULE: CharEntropyRule = CharEntropyRule::new(4.2);
-    pub static ref POLKADOT_PROXIMITY_RULE: ProximityCoefficientRule = ProximityCoefficientRule::new(11.8, 3);
+    // RULE_PROFILE of rules/polkadot.json, main checks it can be loaded before the search starts
+    pub static ref POLKADOT_RULES: ActiveRuleSet = ActiveRuleSet::from_env("polkadot").unwrap_or_else(|e| panic!("{}", e));
 }
 
 
 
 pub struct VanityResult {
     pub wallet: Wallet,
+    // describe_matches of matches
     pub matched_rule: Option<String>,
+    pub matches: Vec<RuleMatch>,
+    pub rule_set_version: String,
+    // the order this address fulfils, see orders
+    pub order_id: Option<i64>,
+    // the job this address was searched for, see jobs
+    pub job_id: Option<i64>,
     pub met_criteria: bool,
     pub entropy_coefficient: f64,
     pub proximity_coefficient: f64,
@@ -402,24 +1574,42 @@ pub struct VanityResult {
 
 pub struct PolkadotVanityResult {
     pub wallet: PolkaWallet,
+    // describe_matches of matches
     pub matched_rule: Option<String>,
+    pub matches: Vec<RuleMatch>,
+    pub rule_set_version: String,
     pub met_criteria: bool,
     pub entropy_coefficient: f64,
     pub proximity_coefficient: f64
 }
 
+pub struct MnemonicVanityResult {
+    pub result: VanityResult,
+    pub mnemonic: String,
+    pub index: u32,
+    pub derivation_path: String
+}
+
 impl fmt::Display for VanityResult {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
             "VanityResult {{
     Matched Rule: {:?},
+    Matches: {},
+    Rule Set: {},
+    Order: {:?},
+    Job: {:?},
     Entropy Coefficient: {:.2},
     Proximity Coefficient: {:.2},
     Entropy Coefficient (Checksummed): {:.2},
     Proximity Coefficient (Checksummed): {:.2}
 }}",
             self.matched_rule,
+            matches_to_json(&self.matches),
+            self.rule_set_version,
+            self.order_id,
+            self.job_id,
             self.entropy_coefficient,
             self.proximity_coefficient,
             self.entropy_coefficient_checksummed,
@@ -434,11 +1624,15 @@ impl fmt::Display for PolkadotVanityResult {
             f,
             "PolkadotVanityResult {{
     Matched Rule: {:?},
+    Matches: {},
+    Rule Set: {},
     Entropy Coefficient: {:.2},
     Proximity Coefficient: {:.2},
     mnemonic: {}
 }}",
             self.matched_rule,
+            matches_to_json(&self.matches),
+            self.rule_set_version,
             self.entropy_coefficient,
             self.proximity_coefficient,
             self.wallet.mnemonic
@@ -446,119 +1640,110 @@ impl fmt::Display for PolkadotVanityResult {
     }
 }
 
-pub fn does_polkadot_address_meet_criteria(wallet: &PolkaWallet) -> PolkadotVanityResult {
-    let address_no_prefix = &wallet.address;
+impl fmt::Display for MnemonicVanityResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MnemonicVanityResult {{
+    Address: {},
+    Result: {},
+    Index: {},
+    Derivation Path: {}
+}}",
+            self.result.wallet.address,
+            self.result,
+            self.index,
+            self.derivation_path
+        )
+    }
+}
 
-    let entropy_coefficient: f64 = address_utils::calculate_entropy(address_no_prefix);
-    let proximity_coefficient: f64 = address_utils::calculate_proximity_coefficient(address_no_prefix, POLKADOT_PROXIMITY_RULE.proximity_max_distance);
-
-    let mut matched_rule: Option<String> = None;
-    let mut met_criteria = false;
-
-    if POLKADOT_START_CONSECUTIVE_CHARS_RULE.apply(address_no_prefix) {
-        let consecutive_chars = max_consecutive_chars(address_no_prefix);
-        matched_rule = Some(format!("Start consecutive rule. Consecutive {}", consecutive_chars));
-        met_criteria = true;
-    }
-    else if POLKADOT_POLKADOT_JS.apply(address_no_prefix) {
-        let first_char = address_no_prefix.chars().nth(0).unwrap();
-        let last_char = address_no_prefix.chars().rev().nth(0).unwrap();
-        matched_rule = Some(format!("Polkadot js rule {}{}", first_char, last_char));
-        met_criteria = true;
-    }
-    else if POLKADOT_CONSECUTIVE_CHARS_RULE.apply(address_no_prefix) { 
-        let consecutive_chars = max_consecutive_chars(address_no_prefix);
-        matched_rule = Some(format!("Consecutive rule. Consecutive {}", consecutive_chars));
-        met_criteria = true;
-    }
-    else if POLKADOT_START_RULE.apply(address_no_prefix) {
-        let word = START_RULE.matched_word(address_no_prefix);
-        matched_rule = Some(format!("Start word rule. word {}", word));
-        met_criteria = true;
-    } 
-    else if POLKADOT_CHAR_ENTROPY_RULE.apply(address_no_prefix) {
-        matched_rule = Some(format!("Entropy < {}", POLKADOT_CHAR_ENTROPY_RULE.entropy_coefficient_max_boundary));
-        met_criteria = true;
-    } 
-    else if POLKADOT_PROXIMITY_RULE.apply(address_no_prefix) {
-        matched_rule = Some(format!("Proximity {} > {}", POLKADOT_PROXIMITY_RULE.proximity_max_distance, POLKADOT_PROXIMITY_RULE.proximity_coefficient_min_boundary));
-        met_criteria = true;
-    }
-
-    PolkadotVanityResult { 
-        wallet: wallet.clone(), 
-        matched_rule, 
-        met_criteria,
-        entropy_coefficient,
-        proximity_coefficient
+// The start rules of a polkadot rule set, checked on the raw public key so that the ss58 encoding
+// is only computed for keys whose address can start with one of their prefixes
+pub struct PolkadotPrefixRules {
+    // of the rule set they were taken from, a reload needs new ones
+    pub version: String,
+    filter: Base58PrefixFilter,
+    // the entries of the rule set the filter covers, a prefix only search checks only these
+    entries: Vec<usize>,
+}
+
+impl PolkadotPrefixRules {
+    pub fn new(rule_set: &RuleSet) -> Result<Self, String> {
+        let mut filter = Base58PrefixFilter::new(polka_wallet::ss58_payload_layout());
+        let mut entries = Vec::new();
+        for (index, entry) in rule_set.entries.iter().enumerate() {
+            if let Some((prefixes, case_sensitive)) = entry.rule.start_prefixes(BASE58_ALPHABET) {
+                let prefixes: Vec<&str> = prefixes.iter().map(|prefix| prefix.as_str()).collect();
+                filter.add_prefixes(&prefixes, case_sensitive);
+                entries.push(index);
+            }
+        }
+        if entries.is_empty() {
+            return Err(format!("Rule profile {} has no start_word or start_consecutive rule for a prefix only search", rule_set.profile));
+        }
+        Ok(Self { version: rule_set.version.clone(), filter, entries })
+    }
+
+    // Cheap check on the raw public key, the ss58 encoding is only needed when this returns true
+    pub fn could_match(&self, public_key: &[u8]) -> bool {
+        self.filter.might_match(public_key)
+    }
+
+    pub fn find_matches(&self, rule_set: &RuleSet, address: &[u8]) -> Vec<RuleMatch> {
+        self.entries.iter().filter_map(|&index| rule_set.entries[index].find_match(address)).collect()
+    }
+}
+
+// The rules see the whole ss58 address. With prefix_rules only the start rules are checked
+pub fn does_polkadot_address_meet_criteria(wallet: &PolkaWallet, rule_set: &RuleSet, prefix_rules: Option<&PolkadotPrefixRules>) -> PolkadotVanityResult {
+    let address = wallet.address.as_bytes();
+    let matches = match prefix_rules {
+        Some(prefix_rules) => prefix_rules.find_matches(rule_set, address),
+        None if rule_set.any_match(address) => rule_set.find_matches(address),
+        None => Vec::new(),
+    };
+
+    PolkadotVanityResult {
+        wallet: wallet.clone(),
+        matched_rule: describe_matches(&matches),
+        met_criteria: !matches.is_empty(),
+        matches,
+        rule_set_version: rule_set.version.clone(),
+        entropy_coefficient: address_utils::calculate_entropy(&wallet.address),
+        proximity_coefficient: address_utils::calculate_proximity_coefficient(&wallet.address, PROXIMITY_MAX_DISTANCE)
     }
 }
 
-pub fn does_address_meet_criteria(wallet: &Wallet) -> VanityResult {
+pub fn does_address_meet_rules(wallet: &Wallet, rules: &RuleSet) -> VanityResult {
     let address_no_prefix = &wallet.address;
-    let address_checksummed = &wallet.address_checksummed;
 
     let entropy_coefficient: f64 = address_utils::calculate_entropy(address_no_prefix);
-    let proximity_coefficient: f64 = address_utils::calculate_proximity_coefficient(address_no_prefix, PROXIMITY_RULE.proximity_max_distance);
-    let entropy_coefficient_checksummed: f64 = address_utils::calculate_entropy(address_checksummed);
-    let proximity_coefficient_checksummed: f64 = address_utils::calculate_proximity_coefficient(address_checksummed, PROXIMITY_RULE_3_21.proximity_max_distance);
-
-    let mut matched_rule: Option<String> = None;
-    let mut met_criteria = false;
-    if ZERO_BYTES_RULE.apply(address_no_prefix) {
-        let zero_bytes_count = ZERO_BYTES_RULE.count_zero_bytes(address_no_prefix);
-        matched_rule = Some(format!("Zero Bytes Rule {}", zero_bytes_count));
-        met_criteria = true;
-    }
-    else if address_checksummed.starts_with("DE") && (address_checksummed.ends_with("0001") || address_checksummed.ends_with("0000") || address_checksummed.ends_with("0002")) {
-    //else if address_checksummed.starts_with("DE") && address_checksummed.ends_with("01") && (entropy_coefficient_checksummed <= 3.6 || proximity_coefficient_checksummed > 14.0)  {
-        matched_rule = Some("DEV".parse().unwrap());
-        met_criteria = true;
-    }
-
-    else if START_CONSECUTIVE_CHARS_RULE.apply(address_no_prefix) {
-        let consecutive_chars = max_consecutive_chars(address_no_prefix);
-        matched_rule = Some(format!("Start consecutive rule. Consecutive {}", consecutive_chars));
-        met_criteria = true;
-    }
-    else if METAMASK_RULE.apply(address_no_prefix) {
-        let first_char = address_no_prefix.chars().nth(0).unwrap();
-        let last_char = address_no_prefix.chars().rev().nth(0).unwrap();
-        matched_rule = Some(format!("Metamask rule {}{}", first_char, last_char));
-        met_criteria = true;
-    }
-    else if CONSECUTIVE_CHARS_RULE.apply(address_no_prefix) { 
-        let consecutive_chars = max_consecutive_chars(address_no_prefix);
-        matched_rule = Some(format!("Consecutive rule. Consecutive {}", consecutive_chars));
-        met_criteria = true;
-    }
-    else if START_RULE.apply(address_no_prefix) {
-        let word = START_RULE.matched_word(address_no_prefix);
-        matched_rule = Some(format!("Start word rule. word {}", word));
-        met_criteria = true;
-    } 
-    else if CHAR_ENTROPY_RULE.apply(address_no_prefix) {
-        matched_rule = Some(format!("Entropy < {}", CHAR_ENTROPY_RULE.entropy_coefficient_max_boundary));
-        met_criteria = true;
-    } 
-    else if PROXIMITY_RULE.apply(address_no_prefix) {
-        matched_rule = Some(format!("Proximity {} > {}", PROXIMITY_RULE.proximity_max_distance, PROXIMITY_RULE.proximity_coefficient_min_boundary));
-        met_criteria = true;
-    } 
-    else if CHAR_ENTROPY_RULE_3.apply(address_checksummed) {
-        matched_rule = Some(format!("Checksummed Entropy < {}", CHAR_ENTROPY_RULE_3.entropy_coefficient_max_boundary));
-        met_criteria = true;
-    }
-    else if PROXIMITY_RULE_3_21.apply(address_checksummed) {
-        matched_rule = Some(format!("Checksummed Proximity {} > {}", PROXIMITY_RULE_3_21.proximity_max_distance, PROXIMITY_RULE_3_21.proximity_coefficient_min_boundary));
-        met_criteria = true;
+    let proximity_coefficient: f64 = address_utils::calculate_proximity_coefficient(address_no_prefix, PROXIMITY_MAX_DISTANCE);
+
+    let address = EthAddressBytes::new(address_no_prefix.as_bytes()).with_checksummed(wallet.known_address_checksummed());
+    let matches = address.find_matches(rules);
+    if let Some(checksummed) = address.computed_checksummed() {
+        wallet.set_address_checksummed(checksummed);
     }
+    let matched_rule = describe_matches(&matches);
+    let met_criteria = !matches.is_empty();
 
-    
+    // only hits are stored, the checksum of all other addresses is never computed
+    let (entropy_coefficient_checksummed, proximity_coefficient_checksummed) = if met_criteria {
+        let address_checksummed = wallet.address_checksummed();
+        (address_utils::calculate_entropy(address_checksummed), address_utils::calculate_proximity_coefficient(address_checksummed, PROXIMITY_MAX_DISTANCE))
+    } else {
+        (0.0, 0.0)
+    };
 
     VanityResult { 
         wallet: wallet.clone(), 
         matched_rule, 
+        matches,
+        rule_set_version: rules.version.clone(),
+        order_id: None,
+        job_id: None,
         met_criteria,
         entropy_coefficient,
         proximity_coefficient,
@@ -567,26 +1752,73 @@ pub fn does_address_meet_criteria(wallet: &Wallet) -> VanityResult {
     }
 }
 
-fn max_consecutive_chars(s: &str) -> usize {
-    let mut max_char = ' ';
+// The eth rules only need the address, so they also work for watch only keys without a Wallet
+pub fn eth_matched_rule(address_no_prefix: &String) -> Option<String> {
+    let address = EthAddressBytes::new(address_no_prefix.as_bytes());
+    if !eth_any_rule_matches(&address) {
+        return None;
+    }
+    describe_matches(&eth_rule_matches(&address))
+}
+
+// Allocation free and stops at the first match, the hot path of the raw candidate search.
+// The checksum is computed for the first rule that needs it and reused by the ones after.
+pub fn eth_any_rule_matches(address: &EthAddressBytes) -> bool {
+    address.any_match(&ETH_RULES.current())
+}
+
+// Every eth rule the address satisfies, in ETH_RULES order
+pub fn eth_rule_matches(address: &EthAddressBytes) -> Vec<RuleMatch> {
+    address.find_matches(&ETH_RULES.current())
+}
+
+// P2PKH addresses always start with '1', the rules look at the part after it
+pub fn btc_matched_rule(address: &String) -> Option<String> {
+    let address_no_prefix = &address.as_bytes()[1..];
+    let rules = BTC_RULES.current();
+    if !rules.any_match(address_no_prefix) {
+        return None;
+    }
+    describe_matches(&rules.find_matches(address_no_prefix))
+}
+
+pub fn does_mnemonic_meet_criteria(mnemonic: &str, candidates: &[HdCandidate], config: &HdSearchConfig) -> Vec<MnemonicVanityResult> {
+    let rules = ETH_RULES.current();
+    candidates.iter()
+        .map(|candidate| (candidate, does_address_meet_rules(&candidate.wallet, &rules)))
+        .filter(|(_, result)| result.met_criteria)
+        .map(|(candidate, result)| MnemonicVanityResult {
+            result,
+            mnemonic: mnemonic.to_string(),
+            index: candidate.index,
+            derivation_path: config.path_for_index(candidate.index)
+        })
+        .collect()
+}
+
+// start and length of the first longest run of one char
+pub(crate) fn longest_run(s: &[u8]) -> (usize, usize) {
+    let mut max_start = 0;
     let mut max_count = 0;
-    let mut last_char = ' ';
+    let mut last_char = b' ';
+    let mut last_start = 0;
     let mut last_count = 0;
 
-    for c in s.chars() {
+    for (i, &c) in s.iter().enumerate() {
         if c == last_char {
             last_count += 1;
         } else {
             last_char = c;
+            last_start = i;
             last_count = 1;
         }
 
         if last_count > max_count {
-            max_char = c;
+            max_start = last_start;
             max_count = last_count;
         }
     }
-    max_count
+    (max_start, max_count)
 }
 
 #[cfg(test)]
@@ -709,6 +1941,225 @@ mod tests {
         iter_test(&should_pass, &should_fail, &rule);
     }
 
+    // The default eth profile on strings, written without the byte level rules as the oracle for them
+    fn baseline_eth_rule_ids(address: &str, checksummed: &str) -> Vec<&'static str> {
+        let chars: Vec<char> = address.chars().collect();
+        let run_at_start = chars.iter().take_while(|&&c| c == chars[0]).count();
+        let run_at_end = chars.iter().rev().take_while(|&&c| c == chars[chars.len() - 1]).count();
+        let longest_run = chars.iter().fold((0, 0, ' '), |(longest, run, last), &c| {
+            let run = if c == last { run + 1 } else { 1 };
+            (longest.max(run), run, c)
+        }).0;
+        let zero_bytes = (0..address.len() / 2).filter(|i| &address[i * 2..i * 2 + 2] == "00").count();
+        let entropy = |s: &str| {
+            let mut counts = std::collections::HashMap::new();
+            for c in s.chars() {
+                *counts.entry(c).or_insert(0) += 1;
+            }
+            counts.values().map(|&count| {
+                let p = count as f64 / s.len() as f64;
+                -p * p.log2()
+            }).sum::<f64>()
+        };
+        let proximity = |s: &str| {
+            let chars: Vec<char> = s.chars().collect();
+            (0..chars.len()).map(|i| {
+                (i + 1..chars.len()).filter(|&j| chars[j] == chars[i]).take(3).map(|j| 1.0 / (j - i) as f64).sum::<f64>()
+            }).sum::<f64>()
+        };
+        let words = ["decaff", "facade", "c0ffee", "dec0de", "01234567", "12345678", "abcdef", "fedcba", "98765432"];
+
+        let mut ids = vec![];
+        if zero_bytes >= 5 { ids.push("zero_bytes"); }
+        if checksummed.starts_with("DE") && ["0001", "0000", "0002"].iter().any(|end| checksummed.ends_with(end)) { ids.push("dev"); }
+        if run_at_start >= 7 { ids.push("start_consecutive"); }
+        if run_at_start >= 3 && run_at_end >= 4 { ids.push("metamask"); }
+        if longest_run >= 9 { ids.push("consecutive"); }
+        if words.iter().any(|word| address.starts_with(word)) { ids.push("start_word"); }
+        if entropy(address) <= 2.8 { ids.push("entropy"); }
+        if proximity(address) >= 23.0 { ids.push("proximity"); }
+        if entropy(checksummed) <= 3.0 { ids.push("checksummed_entropy"); }
+        if proximity(checksummed) >= 21.0 { ids.push("checksummed_proximity"); }
+        ids
+    }
+
+    #[test]
+    fn byte_level_eth_rules_match_the_string_rules() {
+        let rule_set = crate::rule_config::load_rule_set(None, "eth", crate::rule_config::DEFAULT_PROFILE).unwrap();
+        let crafted = [
+            ("0000000000af5e170c3ec77a1233883c8a7e8a82", vec!["zero_bytes", "start_consecutive", "consecutive", "proximity", "checksummed_proximity"]),
+            ("c0ffee52af5e170c3ec77a1233883c8a75555555", vec!["start_word"]),
+            ("1111111af5e170c3ec77a1233883c8a7e8a827f0", vec!["start_consecutive"]),
+            ("222d5e170c3ec77a1233883c8a7e8a827f052222", vec!["metamask"]),
+            ("12545552af5e155555555557a1233883c8a75444", vec!["consecutive", "proximity", "checksummed_entropy", "checksummed_proximity"]),
+        ];
+        let random: Vec<String> = (0..200).map(|_| crate::address::eth_wallet::generate_random_wallet().address).collect();
+        let expected = crafted.iter().map(|(address, ids)| (address.to_string(), Some(ids.clone()))).chain(random.into_iter().map(|address| (address, None)));
+        for (address, crafted_ids) in expected {
+            let checksummed = crate::address::eth_wallet::checksummed(&address);
+            let baseline = baseline_eth_rule_ids(&address, &checksummed);
+            if let Some(crafted_ids) = crafted_ids {
+                assert_eq!(baseline, crafted_ids, "{}", address);
+            }
+            let bytes = EthAddressBytes::new(address.as_bytes());
+            let ids: Vec<String> = bytes.find_matches(&rule_set).into_iter().map(|rule_match| rule_match.rule_id).collect();
+            assert_eq!(ids, baseline, "{}", address);
+            assert_eq!(EthAddressBytes::new(address.as_bytes()).any_match(&rule_set), !baseline.is_empty(), "{}", address);
+        }
+        let matches = EthAddressBytes::new(crafted[1].0.as_bytes()).find_matches(&rule_set);
+        assert_eq!(matches[0].rule_id, "start_word");
+        assert_eq!(matches[0].matched.as_deref(), Some("c0ffee"));
+        assert_eq!(matches[0].span, Some((0, 6)));
+    }
+
+    #[test]
+    fn all_matching_rules_are_reported() {
+        let address = "decaff0000000000000000000000000000000009".to_string();
+        let matches = eth_rule_matches(&EthAddressBytes::new(address.as_bytes()));
+        let ids: Vec<&str> = matches.iter().map(|rule_match| rule_match.rule_id.as_str()).collect();
+        assert_eq!(&ids[..3], &["zero_bytes", "consecutive", "start_word"]);
+
+        assert_eq!(matches[0].score, 16.0);
+        assert_eq!(matches[0].parameters, json!({ "min": 5 }));
+        assert_eq!(matches[1].matched.as_deref(), Some("0"));
+        assert_eq!(matches[1].span, Some((6, 39)));
+        assert_eq!(matches[2].matched.as_deref(), Some("decaff"));
+
+        let json = matches_to_json(&matches);
+        assert_eq!(json[2]["rule_id"], "start_word");
+        assert_eq!(json[2]["span"], json!([0, 6]));
+        assert!(describe_matches(&matches).unwrap().starts_with("zero_bytes 16, consecutive 0, start_word decaff"));
+        assert_eq!(eth_matched_rule(&address), describe_matches(&matches));
+        assert_eq!(describe_matches(&[]), None);
+    }
+
+    #[test]
+    fn polkadot_js_rule_reports_its_chars() {
+        let rule = PolkadotJsStartEndRule::new();
+        let rule_match = rule.find_match(b"GGGGGGa1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8g999999").unwrap();
+        assert_eq!(rule_match.matched.as_deref(), Some("G9"));
+        assert!(rule.find_match(b"GGGGGa1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8g9999999").is_none());
+    }
+
+    #[test]
+    fn polkadot_prefix_rules_only_skip_keys_that_can_not_match() {
+        use sp_core::crypto::Ss58Codec;
+        let rule_set = crate::rule_config::parse_rule_sets("polkadot", r#"{"default": ["start(\"5G\")", "consecutive(>=6)"]}"#).unwrap().pop().unwrap();
+        let prefix_rules = PolkadotPrefixRules::new(&rule_set).unwrap();
+        let mut kept = 0;
+        for _ in 0..500 {
+            let public_key: [u8; 32] = rand::random();
+            let address = sp_core::sr25519::Public::from_raw(public_key).to_ss58check();
+            let wallet = PolkaWallet { secret_key: String::new(), public_key: hex::encode(public_key), address, mnemonic: String::new() };
+            let result = does_polkadot_address_meet_criteria(&wallet, &rule_set, Some(&prefix_rules));
+            assert_eq!(result.met_criteria, wallet.address.starts_with("5G"), "{}", wallet.address);
+            assert_eq!(result.rule_set_version, rule_set.version);
+            if result.met_criteria {
+                assert_eq!(result.matches[0].rule_id, "start(\"5G\")");
+                assert_eq!(result.matches[0].span, Some((0, 2)));
+            }
+            if prefix_rules.could_match(&public_key) {
+                kept += 1;
+            } else {
+                assert!(!result.met_criteria);
+            }
+        }
+        assert!(kept > 0 && kept < 500, "{}", kept);
+
+        let mirrored = crate::rule_config::load_rule_set(None, "polkadot", "mirrored").unwrap();
+        assert!(PolkadotPrefixRules::new(&mirrored).is_err());
+    }
+
+    // a rule of the default eth profile
+    fn default_eth_rule(id: &str) -> BoxedRule {
+        let rule_set = crate::rule_config::load_rule_set(None, "eth", crate::rule_config::DEFAULT_PROFILE).unwrap();
+        rule_set.entries.into_iter().find(|entry| entry.id == id).unwrap().rule
+    }
+
+    #[test]
+    fn checksum_is_only_computed_for_rules_that_need_it() {
+        assert!(!default_eth_rule("start_word").needs_checksum());
+        assert!(default_eth_rule("checksummed_entropy").needs_checksum());
+
+        // matched before the first checksum rule
+        let zero_bytes = EthAddressBytes::new(b"0000000000af5e170c3ec77a1233883c8a7e8a82");
+        assert!(eth_any_rule_matches(&zero_bytes));
+        assert!(!zero_bytes.is_checksum_computed());
+
+        let address = "de00000000000000000000000000000000000001".to_string();
+        let no_hit = EthAddressBytes::new(address.as_bytes());
+        assert!(zero_bytes.matches(&*default_eth_rule("zero_bytes")));
+        assert!(!no_hit.matches(&*default_eth_rule("start_word")));
+        assert!(!no_hit.is_checksum_computed());
+        assert_eq!(no_hit.matches(&*default_eth_rule("dev")), crate::address::eth_wallet::checksummed(&address).starts_with("DE"));
+        assert!(no_hit.is_checksum_computed());
+        assert_eq!(&no_hit.checksummed()[..], crate::address::eth_wallet::checksummed(&address).as_bytes());
+    }
+
+    #[test]
+    fn checksum_is_not_computed_for_addresses_no_casing_can_match() {
+        let rule_set = crate::rule_config::load_rule_set(None, "eth", crate::rule_config::DEFAULT_PROFILE).unwrap();
+        assert!(rule_set.entries.iter().any(|entry| entry.rule.needs_checksum()));
+        let no_hit = EthAddressBytes::new(b"a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0");
+        assert!(!no_hit.any_match(&rule_set));
+        assert!(!no_hit.is_checksum_computed());
+
+        // starts with de, the checksum decides
+        let address = "de3f0a9b1c2d4e5f6a7b8c9d0e1f2a3b4c5d0001".to_string();
+        let dev = EthAddressBytes::new(address.as_bytes());
+        assert_eq!(dev.matches(&*default_eth_rule("dev")), crate::address::eth_wallet::checksummed(&address).starts_with("DE"));
+        assert!(dev.is_checksum_computed());
+
+        // no casing changes entropy or proximity in the direction of a match
+        for address in ["a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0", "aaaaaaaaaabbbbbbbbbbccccccccccdddddddd01"] {
+            let checksummed = crate::address::eth_wallet::checksummed(&address.to_string());
+            for rule in [default_eth_rule("checksummed_entropy"), default_eth_rule("checksummed_proximity")] {
+                assert!(!rule.apply_bytes(checksummed.as_bytes()) || rule.may_match_lowercase(address.as_bytes()), "{}", address);
+            }
+        }
+    }
+
+    #[test]
+    fn combinators_match_like_their_children() {
+        let start = || -> BoxedRule { Box::new(StartRule::new(&["dead"], true)) };
+        let zero_bytes = || -> BoxedRule { Box::new(ZeroBytesRule::new(3)) };
+        let consecutive = || -> BoxedRule { Box::new(ContainsConsecutiveCharsCounterRule::new(8)) };
+        let addresses = [
+            "dead000000a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5",
+            "dead1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f",
+            "a1b2c3d4000000e5f6a7b8c9d0e1f2a3b4c5d6e7",
+            "a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0",
+            "deadffffffff1a2b3c4d5e6f7a8b9c0d1e2f3a4b",
+        ];
+        for address in addresses {
+            let bytes = address.as_bytes();
+            let children = [start().apply_bytes(bytes), zero_bytes().apply_bytes(bytes), consecutive().apply_bytes(bytes)];
+            let matched = children.iter().filter(|&&matched| matched).count();
+            assert_eq!(AllOf(vec![start(), zero_bytes(), consecutive()]).apply_bytes(bytes), matched == 3, "{}", address);
+            assert_eq!(AnyOf(vec![start(), zero_bytes(), consecutive()]).apply_bytes(bytes), matched >= 1, "{}", address);
+            assert_eq!(Not(start()).apply_bytes(bytes), !children[0], "{}", address);
+            for required in 1..=3 {
+                let at_least = AtLeast(required, vec![start(), zero_bytes(), consecutive()]);
+                assert_eq!(at_least.apply_bytes(bytes), matched >= required, "{} {}", address, required);
+                assert_eq!(at_least.find_match(bytes).is_some(), matched >= required, "{} {}", address, required);
+            }
+        }
+        let both = AllOf(vec![start(), zero_bytes()]).find_match(addresses[0].as_bytes()).unwrap();
+        assert_eq!(both.matched.as_deref(), Some("dead"));
+        assert_eq!(both.score, 2.0);
+        assert!(!AllOf(vec![start(), zero_bytes()]).needs_checksum());
+        assert!(Not(Box::new(ChecksummedRule(ZeroBytesRule::new(3)))).needs_checksum());
+    }
+
+    #[test]
+    fn case_insensitive_start_rule_on_bytes() {
+        let rule = StartRule::new(&["Dev", "zen"], false);
+        assert!(rule.apply_bytes(b"dEVxyz"));
+        assert!(rule.apply_bytes(b"ZENxyz"));
+        assert!(!rule.apply_bytes(b"ze"));
+        assert!(!rule.apply_bytes(b"xdev"));
+    }
+
     #[test]
     fn start_rule_test() {
         let rule = StartRule::new(&["decaff", "facade", "c0ffee", "dec0de", "01234567", "12345678", "abcdef", "fedcba", "98765432"], true);
@@ -728,4 +2179,217 @@ mod tests {
         iter_test(&should_pass, &should_fail, &rule);
     }
 
+    #[test]
+    fn end_rule_test() {
+        let rule = EndRule::new(&["beef", "c0ffee"], true);
+        let should_pass = [
+            "55555532af5e170c3ec77a1233883c8a7e8abeef",
+            "44445552af5e170c3ec77a1233883c8a7c0ffee",
+        ];
+        let should_fail = [
+            "beef5532af5e170c3ec77a1233883c8a7e8a827f",
+            "55555532af5e170c3ec77a1233883c8a7e8beefa",
+            "55555532af5e170c3ec77a1233883c8a7e8aBEEF",
+        ];
+        iter_test(&should_pass, &should_fail, &rule);
+
+        let rule = EndRule::new(&["BEEF"], false);
+        assert!(rule.apply_bytes(b"1234beEF"));
+        let rule_match = rule.find_match(b"1234beef").unwrap();
+        assert_eq!((rule_match.matched.as_deref(), rule_match.span), (Some("beef"), Some((4, 8))));
+        assert!(!rule.apply_bytes(b"eef"));
+    }
+
+    #[test]
+    fn contains_word_rule_test() {
+        let rule = ContainsWordRule::new(&["cafe", "f00d"], true);
+        assert!(rule.apply_bytes(b"0123cafe4567"));
+        assert!(rule.apply_bytes(b"cafe"));
+        assert!(rule.apply_bytes(b"01234567f00d"));
+        assert!(!rule.apply_bytes(b"0123caf4567e"));
+        assert!(!rule.apply_bytes(b"0123CAFE4567"));
+        assert_eq!(rule.find_match(b"01f00d23cafe").unwrap().span, Some((8, 12)));
+
+        let rule = ContainsWordRule::new(&["Cafe"], false).at_positions(2, 4);
+        assert!(rule.apply_bytes(b"00CAFE000000"));
+        assert!(rule.apply_bytes(b"0000cafe0000"));
+        assert!(!rule.apply_bytes(b"0cafe0000000"));
+        assert!(!rule.apply_bytes(b"00000cafe000"));
+        assert!(!rule.apply_bytes(b"000caf"));
+        let rule_match = rule.find_match(b"000cafe00000").unwrap();
+        assert_eq!(rule_match.parameters, json!({ "case_sensitive": false, "min_position": 2, "max_position": 4 }));
+        assert_eq!((rule_match.matched.as_deref(), rule_match.span), (Some("cafe"), Some((3, 7))));
+    }
+
+    #[test]
+    fn prefix_suffix_rule_test() {
+        let rule = PrefixSuffixRule::new(&["c0ffee", "c0ff"], &["beef", "eebeef"], true);
+        let should_pass = [
+            "c0ffee32af5e170c3ec77a1233883c8a7e8abeef",
+            "c0ff5552af5e170c3ec77a1233883c8a7eebeef",
+        ];
+        let should_fail = [
+            "c0ffee32af5e170c3ec77a1233883c8a7e8abee0",
+            "0c0ffe32af5e170c3ec77a1233883c8a7e8abeef",
+            "c0ffee32af5e170c3ec77a1233883c8a7e8aBEEF",
+        ];
+        iter_test(&should_pass, &should_fail, &rule);
+        assert_eq!(rule.find_match(b"c0ffee32af5e170c3ec77a1233883c8a7e8abeef").unwrap().matched.as_deref(), Some("c0ffee..beef"));
+
+        // start and end must not share chars, the shorter start leaves room for the end
+        assert!(!PrefixSuffixRule::new(&["c0ffee"], &["eebeef"], true).apply_bytes(b"c0ffeebeef"));
+        let overlapping = PrefixSuffixRule::new(&["c0ffee", "c0ff"], &["eebeef"], true);
+        assert_eq!(overlapping.find_match(b"c0ffeebeef").unwrap().matched.as_deref(), Some("c0ff..eebeef"));
+        assert!(PrefixSuffixRule::new(&["C0FFEE"], &["BEEF"], false).apply_bytes(b"c0FFee1234BeeF"));
+
+        // with overlap the words may share chars
+        let sharing = PrefixSuffixRule::new(&["c0ffee"], &["eebeef"], true).allowing_overlap();
+        assert_eq!(sharing.find_match(b"c0ffeebeef").unwrap().matched.as_deref(), Some("c0ffee..eebeef"));
+        assert!(!sharing.may_match_lowercase(b"c0ffee1234"));
+        assert!(sharing.may_match_lowercase(b"c0ffee1234eebeef"));
+    }
+
+    #[test]
+    fn palindrome_rule_test() {
+        let rule = PalindromeRule::new(4);
+        assert!(rule.apply(&"abcd00000000000000000000000000000000dcba".to_string()));
+        assert!(!rule.apply(&"abcd00000000000000000000000000000000dcbb".to_string()));
+        assert!(!rule.apply(&"abcd00000000000000000000000000000000abcd".to_string()));
+        let partial = rule.find_match(b"abcde1000000000000000000000000000002edcba").unwrap();
+        assert_eq!((partial.matched.as_deref(), partial.score, partial.span), (Some("abcde"), 5.0, Some((0, 5))));
+        assert_eq!(partial.parameters["full"], json!(false));
+
+        // base58, odd length with a middle char
+        let full = rule.find_match(b"Bob1234567xyx7654321boB").unwrap();
+        assert_eq!((full.score, full.parameters["full"].clone()), (11.0, json!(true)));
+        assert!(rule.find_match(b"Bob1234567xyz7654321bob").is_none());
+        assert!(!PalindromeRule::new(12).apply_bytes(b"Bob1234567xyx7654321boB"));
+    }
+
+    #[test]
+    fn bookend_rule_test() {
+        let rule = BookendRule::new(3);
+        assert!(rule.apply(&"c0ffee0000000000000000000000000000c0ffee".to_string()));
+        assert!(!rule.apply(&"c0ffee00000000000000000000000000000c0fef".to_string()));
+        let rule_match = rule.find_match(b"c0ffee0000000000000000000000000000c0ffee").unwrap();
+        assert_eq!((rule_match.matched.as_deref(), rule_match.score), (Some("c0ffee"), 6.0));
+        // the longest bookend, not the first one found
+        assert_eq!(rule.find_match(b"abcab00000000000000000000000000000abcab").unwrap().matched.as_deref(), Some("abcab"));
+        // shorter bookends count even if longer lengths do not match
+        assert_eq!(BookendRule::new(2).find_match(b"ab1111111111111111111111111111111111ab").unwrap().score, 2.0);
+        // start and end never overlap, even for an address of one repeated char
+        assert_eq!(BookendRule::new(2).find_match(&[b'7'; 40]).unwrap().score, 20.0);
+        assert!(BookendRule::new(3).find_match(b"ZzzXzz").is_none());
+        assert_eq!(BookendRule::new(3).find_match(b"Zz3Zz3").unwrap().matched.as_deref(), Some("Zz3"));
+        assert!(!BookendRule::new(0).apply_bytes(b"ab1111111111111111111111111111111111cd"));
+        assert!(BookendRule::new(0).find_match(b"").is_none());
+    }
+
+    #[test]
+    fn mask_rule_test() {
+        let rule = MaskRule::new("dead????????????????????????????????beef", true).unwrap();
+        let should_pass = [
+            "dead5532af5e170c3ec77a1233883c8a7e8abeef",
+            "deadbeefbeefbeefbeefbeefbeefbeefbeefbeef",
+        ];
+        let should_fail = [
+            "dead5532af5e170c3ec77a1233883c8a7e8abee0",
+            "dead5532af5e170c3ec77a1233883c8a7e8abeef0",
+            "deadbeef",
+        ];
+        iter_test(&should_pass, &should_fail, &rule);
+
+        let rule = MaskRule::new("0000*", true).unwrap();
+        assert!(rule.apply_bytes(b"0000"));
+        assert!(rule.apply_bytes(b"00001234"));
+        assert!(!rule.apply_bytes(b"0001234"));
+
+        let rule = MaskRule::new("*C0?FEE*F00D", false).unwrap();
+        assert!(rule.apply_bytes(b"12c0ffee3456f00d"));
+        assert!(rule.apply_bytes(b"c0xfeef00d"));
+        assert!(!rule.apply_bytes(b"c0ffeeff00"));
+        assert!(!rule.apply_bytes(b"c0ffef00d"));
+        let rule_match = rule.find_match(b"12c0ffee3456f00d").unwrap();
+        assert_eq!((rule_match.matched.as_deref(), rule_match.span), (Some("c0ffee3456f00d"), Some((2, 16))));
+        assert_eq!(rule_match.score, 9.0);
+    }
+
+    #[test]
+    fn regex_rule_test() {
+        let rule = RegexRule::new("^(?:dead|beef)[0-9]{4}").unwrap();
+        let should_pass = [
+            "dead1234af5e170c3ec77a1233883c8a7e8a827f",
+            "beef0000af5e170c3ec77a1233883c8a7e8a827f",
+        ];
+        let should_fail = [
+            "dead123aaf5e170c3ec77a1233883c8a7e8a827f",
+            "0dead1234f5e170c3ec77a1233883c8a7e8a827f",
+        ];
+        iter_test(&should_pass, &should_fail, &rule);
+
+        let rule = RegexRule::new(r"(\w)\1{5}").unwrap();
+        let rule_match = rule.find_match(b"12c0ffffff3456f00d").unwrap();
+        assert_eq!((rule_match.matched.as_deref(), rule_match.span), (Some("ffffff"), Some((4, 10))));
+    }
+
+    #[test]
+    fn regex_rule_limits() {
+        assert!(RegexRule::new("(a+)+b").err().unwrap().contains("the group at 0 repeats a repetition"));
+        assert!(RegexRule::new("(?:x(a*)){2,}").is_err());
+        assert!(RegexRule::new("((ab)*c)+").is_err());
+        assert!(RegexRule::new("(a+)?b").is_ok());
+        assert!(RegexRule::new("([a+]*)b{2}").is_ok());
+        assert!(RegexRule::new("(dead){2}").is_ok());
+        assert!(RegexRule::new("(dead").is_err());
+
+        let hex: Vec<(u8, f64)> = b"0123456789abcdef".iter().map(|&c| (c, 1.0 / 16.0)).collect();
+        // lookahead puts it on the backtracking engine, alternatives overlap
+        let slow = RegexRule::new("^(?=.)(?:a|a?){20}(?:a|a?){20}x").unwrap();
+        assert!(slow.with_sampled_probability(&hex, 40, 100).err().unwrap().contains("on aaaaaaaa"));
+
+        let starts_with_zero = RegexRule::new("^0").unwrap().with_sampled_probability(&hex, 40, 20_000).unwrap();
+        let probability = starts_with_zero.probability().unwrap();
+        assert!((probability - 1.0 / 16.0).abs() < 0.01, "{}", probability);
+        let rare = RegexRule::new("0{4}[a-f]{4}").unwrap().with_sampled_probability(&hex, 40, 1000).unwrap();
+        assert_eq!((rare.probability(), rare.probability_below()), (None, Some(1.0 / 1000.0)));
+        // anchored literals are computed, far below what the samples can see
+        let dead = RegexRule::new("^deadbeef").unwrap().with_sampled_probability(&hex, 40, 1000).unwrap();
+        assert_eq!((dead.probability(), dead.probability_below()), (Some(16f64.powi(-8)), None));
+        assert_eq!(RegexRule::new("c0ffee$").unwrap().with_sampled_probability(&hex, 40, 1000).unwrap().probability(), Some(16f64.powi(-6)));
+        assert_eq!(RegexRule::new("^z").unwrap().with_sampled_probability(&hex, 40, 1000).unwrap().probability(), Some(0.0));
+
+        assert_eq!(regex_literals("^(?:dead|beef)[0-9a-f]{4}x"), ['d', 'e', 'a', 'd', 'b', 'e', 'e', 'f', 'x']);
+        assert_eq!(regex_literals(r"(?i)(?P<word>z\d)[^Gq]+$"), ['z', 'G', 'q']);
+    }
+
+    #[test]
+    fn mask_probability_is_exact() {
+        let hex: Vec<(u8, f64)> = b"0123456789abcdef".iter().map(|&c| (c, 1.0 / 16.0)).collect();
+        let close = |a: f64, b: f64| (a - b).abs() <= 1e-12 * b;
+        assert!(close(MaskRule::new("dead????????????????????????????????beef", true).unwrap().match_probability(&hex, 40), 16f64.powi(-8)));
+        assert!(close(MaskRule::new("0000*", true).unwrap().match_probability(&hex, 40), 16f64.powi(-4)));
+        assert_eq!(MaskRule::new("0000", true).unwrap().match_probability(&hex, 40), 0.0);
+
+        // against counting the matches of every string of a small alphabet
+        let alphabet: Vec<(u8, f64)> = vec![(b'a', 0.5), (b'b', 0.25), (b'c', 0.25)];
+        let length = 8;
+        for pattern in ["*ab*", "a*b", "*a?a*", "?b*ba*", "*", "a?*?c", "abcabcab", "*aa*aa*"] {
+            let mask = MaskRule::new(pattern, true).unwrap();
+            let mut expected = 0.0;
+            for n in 0..3usize.pow(length as u32) {
+                let (mut address, mut probability, mut rest) = (Vec::new(), 1.0, n);
+                for _ in 0..length {
+                    let (c, p) = alphabet[rest % 3];
+                    address.push(c);
+                    probability *= p;
+                    rest /= 3;
+                }
+                if mask.apply_bytes(&address) {
+                    expected += probability;
+                }
+            }
+            assert!(close(mask.match_probability(&alphabet, length), expected), "{}", pattern);
+        }
+    }
+
 }
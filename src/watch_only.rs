@@ -0,0 +1,202 @@
+// Vanity search on a customer xpub: only public children are derived (BIP32 CKDpub),
+// so the result is just the child index and no private key ever exists on our side.
+use crate::address::eth_wallet;
+use crate::vanity_generator;
+use bitcoin_hashes::hmac::{Hmac, HmacEngine};
+use bitcoin_hashes::{hash160, sha256d, sha512, Hash, HashEngine};
+use secp256k1::{PublicKey, Scalar, Secp256k1, Verification};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+const EXTENDED_KEY_LENGTH: usize = 78;
+pub const HARDENED_OFFSET: u32 = 1 << 31;
+
+#[derive(Debug, Clone)]
+pub struct ExtendedPubKey {
+    pub depth: u8,
+    pub chain_code: [u8; 32],
+    pub public_key: PublicKey,
+}
+
+impl FromStr for ExtendedPubKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let data = bs58::decode(s).into_vec().map_err(|e| format!("Invalid base58: {}", e))?;
+        if data.len() != EXTENDED_KEY_LENGTH + 4 {
+            return Err(format!("Invalid extended key length {}", data.len()));
+        }
+        let (payload, checksum) = data.split_at(EXTENDED_KEY_LENGTH);
+        if &sha256d::Hash::hash(payload)[..4] != checksum {
+            return Err("Invalid extended key checksum".to_string());
+        }
+        if payload[45] != 0x02 && payload[45] != 0x03 {
+            return Err("Not an extended public key".to_string());
+        }
+        // version, parent fingerprint and child number are not needed to derive children
+        Ok(ExtendedPubKey {
+            depth: payload[4],
+            chain_code: payload[13..45].try_into().unwrap(),
+            public_key: PublicKey::from_slice(&payload[45..78]).map_err(|e| format!("Invalid public key: {}", e))?,
+        })
+    }
+}
+
+impl ExtendedPubKey {
+    // CKDpub: K_i = parse256(IL) * G + K_par with I = HMAC-SHA512(chain code, serP(K_par) || ser32(i))
+    pub fn child_public_key<C: Verification>(&self, secp: &Secp256k1<C>, index: u32) -> Result<PublicKey, String> {
+        if index >= HARDENED_OFFSET {
+            return Err(format!("Hardened index {} can not be derived from a public key", index));
+        }
+        let mut engine: HmacEngine<sha512::Hash> = HmacEngine::new(&self.chain_code);
+        engine.input(&self.public_key.serialize());
+        engine.input(&index.to_be_bytes());
+        let i = Hmac::from_engine(engine).to_byte_array();
+
+        let tweak = Scalar::from_be_bytes(i[..32].try_into().unwrap())
+            .map_err(|_| format!("Index {} gives an invalid key, skip it", index))?;
+        self.public_key.add_exp_tweak(secp, &tweak)
+            .map_err(|_| format!("Index {} gives an invalid key, skip it", index))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Chain {
+    Eth,
+    Btc,
+}
+
+impl FromStr for Chain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eth" => Ok(Chain::Eth),
+            "btc" => Ok(Chain::Btc),
+            _ => Err(format!("Unknown chain {}, expected eth or btc", s)),
+        }
+    }
+}
+
+// eth: hex without 0x, btc: P2PKH of the compressed key
+pub fn address_for(chain: Chain, public_key: &PublicKey) -> String {
+    match chain {
+        Chain::Eth => hex::encode(eth_wallet::public_key_address(public_key)),
+        Chain::Btc => {
+            let mut payload = vec![0x00];
+            payload.extend_from_slice(&hash160::Hash::hash(&public_key.serialize())[..]);
+            let checksum = sha256d::Hash::hash(&payload);
+            payload.extend_from_slice(&checksum[..4]);
+            bs58::encode(payload).into_string()
+        }
+    }
+}
+
+pub fn matched_rule(chain: Chain, address: &String) -> Option<String> {
+    match chain {
+        Chain::Eth => vanity_generator::eth_matched_rule(address),
+        Chain::Btc => vanity_generator::btc_matched_rule(address),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WatchOnlyHit {
+    pub index: u32,
+    pub address: String,
+    pub matched_rule: String,
+}
+
+impl fmt::Display for WatchOnlyHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WatchOnlyHit {{ Index: {}, Address: {}, Matched Rule: {} }}", self.index, self.address, self.matched_rule)
+    }
+}
+
+// Scans start, start + step, ... below end and below `best`, the lowest index any worker found so
+// far, u32::MAX before the first hit. Every index below the final best is scanned by some worker.
+pub fn scan<F>(xpub: &ExtendedPubKey, chain: Chain, start: u32, end: u32, step: u32, best: &AtomicU32, rule: F) -> Option<WatchOnlyHit>
+where
+    F: Fn(&String) -> Option<String>,
+{
+    let secp = Secp256k1::verification_only();
+    let end = end.min(HARDENED_OFFSET);
+    let mut index = start;
+    while index < end && index < best.load(Ordering::Relaxed) {
+        if let Ok(public_key) = xpub.child_public_key(&secp, index) {
+            let address = address_for(chain, &public_key);
+            if let Some(matched_rule) = rule(&address) {
+                best.fetch_min(index, Ordering::Relaxed);
+                return Some(WatchOnlyHit { index, address, matched_rule });
+            }
+        }
+        index = match index.checked_add(step) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BIP32 test vector 1, m/0H and m/0H/1
+    const XPUB_0H: &str = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw";
+    const XPUB_0H_1: &str = "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ";
+
+    #[test]
+    fn child_public_key_matches_bip32_vector() {
+        let parent = ExtendedPubKey::from_str(XPUB_0H).unwrap();
+        let child = ExtendedPubKey::from_str(XPUB_0H_1).unwrap();
+        let secp = Secp256k1::verification_only();
+        assert_eq!(parent.child_public_key(&secp, 1).unwrap(), child.public_key);
+        assert_eq!(child.depth, 2);
+        assert!(parent.child_public_key(&secp, HARDENED_OFFSET).is_err());
+    }
+
+    #[test]
+    fn invalid_xpub_is_rejected() {
+        let mut broken = XPUB_0H.to_string();
+        broken.pop();
+        broken.push('x');
+        assert!(ExtendedPubKey::from_str(&broken).is_err());
+        // xprv of BIP32 test vector 1, m
+        assert!(ExtendedPubKey::from_str("xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi").is_err());
+    }
+
+    #[test]
+    fn btc_address_of_known_key() {
+        // compressed public key of private key 1
+        let public_key = PublicKey::from_str("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap();
+        assert_eq!(address_for(Chain::Btc, &public_key), "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH");
+        assert_eq!(address_for(Chain::Eth, &public_key), "7e5f4552091a69125d5dfcb7b8c2659029395bdf");
+    }
+
+    #[test]
+    fn scan_returns_the_first_matching_index() {
+        let xpub = ExtendedPubKey::from_str(XPUB_0H).unwrap();
+        let secp = Secp256k1::verification_only();
+        let starts_with_zero = |address: &String| if address.starts_with('0') { Some("zero".to_string()) } else { None };
+
+        let best = AtomicU32::new(u32::MAX);
+        let hit = scan(&xpub, Chain::Eth, 0, 1000, 1, &best, starts_with_zero).unwrap();
+        assert_eq!(best.load(Ordering::Relaxed), hit.index);
+        assert_eq!(address_for(Chain::Eth, &xpub.child_public_key(&secp, hit.index).unwrap()), hit.address);
+        for index in 0..hit.index {
+            assert!(!address_for(Chain::Eth, &xpub.child_public_key(&secp, index).unwrap()).starts_with('0'));
+        }
+
+        // another worker already found this one
+        assert!(scan(&xpub, Chain::Eth, 0, 1000, 1, &best, starts_with_zero).is_none());
+
+        // the worker of the other indexes finishes first with a higher one, this one still scans up to it
+        let best = AtomicU32::new(u32::MAX);
+        let other = scan(&xpub, Chain::Eth, 1 - hit.index % 2, 1000, 2, &best, starts_with_zero).unwrap();
+        assert!(other.index > hit.index);
+        assert_eq!(scan(&xpub, Chain::Eth, hit.index % 2, 1000, 2, &best, starts_with_zero).unwrap().index, hit.index);
+        assert_eq!(best.load(Ordering::Relaxed), hit.index);
+    }
+}
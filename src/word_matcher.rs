@@ -0,0 +1,208 @@
+// Many start and end words checked in one pass over the first and last chars of an address.
+// The words are compiled into two tries, one over the starts and one over the reversed ends.
+// A trie node has one transition per distinct char of the words, a byte that is in no word
+// ends the walk at once. Case insensitive matchers fold both the words and the address chars
+// through the same class table, nothing is lowercased per address.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    Start,
+    End,
+}
+
+pub struct WordMatcher {
+    targets: Vec<(Anchor, String)>,
+    case_sensitive: bool,
+    // byte -> 1 + index of its transition, 0 for bytes in no word
+    classes: [u16; 256],
+    width: usize,
+    starts: Trie,
+    ends: Trie,
+}
+
+struct Trie {
+    // width transitions per node, 0 is no transition since the root is never a child
+    transitions: Vec<u32>,
+    // ids of the targets ending at each node
+    outputs: Vec<Vec<u32>>,
+    // longest word, the walk never goes further
+    depth: usize,
+}
+
+impl Trie {
+    fn new(width: usize) -> Self {
+        Trie { transitions: vec![0; width], outputs: vec![Vec::new()], depth: 0 }
+    }
+
+    fn insert(&mut self, classes: &[u16; 256], width: usize, word: impl Iterator<Item = u8>, id: u32) {
+        let mut node = 0;
+        let mut depth = 0;
+        for c in word {
+            let slot = node * width + classes[c as usize] as usize - 1;
+            if self.transitions[slot] == 0 {
+                self.transitions[slot] = self.outputs.len() as u32;
+                self.transitions.extend(std::iter::repeat_n(0, width));
+                self.outputs.push(Vec::new());
+            }
+            node = self.transitions[slot] as usize;
+            depth += 1;
+        }
+        self.outputs[node].push(id);
+        self.depth = self.depth.max(depth);
+    }
+
+    fn walk(&self, classes: &[u16; 256], width: usize, chars: impl Iterator<Item = u8>, f: &mut impl FnMut(usize)) {
+        // empty words
+        for &id in &self.outputs[0] {
+            f(id as usize);
+        }
+        let mut node = 0;
+        for c in chars.take(self.depth) {
+            let class = classes[c as usize] as usize;
+            if class == 0 {
+                return;
+            }
+            node = self.transitions[node * width + class - 1] as usize;
+            if node == 0 {
+                return;
+            }
+            for &id in &self.outputs[node] {
+                f(id as usize);
+            }
+        }
+    }
+}
+
+impl WordMatcher {
+    // target ids are the indices of targets, an empty word matches every address
+    pub fn new(targets: &[(Anchor, &str)], case_sensitive: bool) -> Self {
+        let fold = |c: u8| if case_sensitive { c } else { c.to_ascii_lowercase() };
+        let mut classes = [0u16; 256];
+        let mut width = 0;
+        for (_, word) in targets {
+            for c in word.bytes().map(fold) {
+                if classes[c as usize] == 0 {
+                    width += 1;
+                    classes[c as usize] = width as u16;
+                }
+            }
+        }
+        if !case_sensitive {
+            for c in b'A'..=b'Z' {
+                classes[c as usize] = classes[c.to_ascii_lowercase() as usize];
+            }
+        }
+
+        let mut starts = Trie::new(width);
+        let mut ends = Trie::new(width);
+        for (id, (anchor, word)) in targets.iter().enumerate() {
+            match anchor {
+                Anchor::Start => starts.insert(&classes, width, word.bytes(), id as u32),
+                Anchor::End => ends.insert(&classes, width, word.bytes().rev(), id as u32),
+            }
+        }
+        WordMatcher {
+            targets: targets.iter().map(|&(anchor, word)| (anchor, if case_sensitive { word.to_string() } else { word.to_ascii_lowercase() })).collect(),
+            case_sensitive,
+            classes,
+            width,
+            starts,
+            ends,
+        }
+    }
+
+    pub fn starts(words: &[&str], case_sensitive: bool) -> Self {
+        let targets: Vec<(Anchor, &str)> = words.iter().map(|&word| (Anchor::Start, word)).collect();
+        WordMatcher::new(&targets, case_sensitive)
+    }
+
+    pub fn ends(words: &[&str], case_sensitive: bool) -> Self {
+        let targets: Vec<(Anchor, &str)> = words.iter().map(|&word| (Anchor::End, word)).collect();
+        WordMatcher::new(&targets, case_sensitive)
+    }
+
+    pub fn case_sensitive(&self) -> bool {
+        self.case_sensitive
+    }
+
+    // the anchor and word of a target id, lowercase for case insensitive matchers
+    pub fn target(&self, id: usize) -> (Anchor, &str) {
+        let (anchor, word) = &self.targets[id];
+        (*anchor, word.as_str())
+    }
+
+    // Calls f with the id of every target the address starts or ends with, the starts first,
+    // each group from the shortest word to the longest
+    pub fn for_each_match(&self, address_no_prefix: &[u8], mut f: impl FnMut(usize)) {
+        self.starts.walk(&self.classes, self.width, address_no_prefix.iter().copied(), &mut f);
+        self.ends.walk(&self.classes, self.width, address_no_prefix.iter().rev().copied(), &mut f);
+    }
+
+    pub fn matches(&self, address_no_prefix: &[u8]) -> Vec<usize> {
+        let mut ids = Vec::new();
+        self.for_each_match(address_no_prefix, |id| ids.push(id));
+        ids
+    }
+
+    // the matching target that was added first, like checking the words in order
+    pub fn first_match(&self, address_no_prefix: &[u8]) -> Option<usize> {
+        let mut first: Option<usize> = None;
+        self.for_each_match(address_no_prefix, |id| first = Some(first.map_or(id, |first| first.min(id))));
+        first
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_every_matching_target() {
+        let matcher = WordMatcher::new(&[
+            (Anchor::Start, "dead"),
+            (Anchor::End, "beef"),
+            (Anchor::Start, "de"),
+            (Anchor::Start, "deadbeef"),
+            (Anchor::End, "f"),
+            (Anchor::Start, "c0ffee"),
+        ], true);
+        assert_eq!(matcher.matches(b"deadbeef00000000beef"), [2, 0, 3, 4, 1]);
+        assert_eq!(matcher.first_match(b"deadbeef00000000beef"), Some(0));
+        assert_eq!(matcher.matches(b"c0ffee1234"), [5]);
+        assert_eq!(matcher.matches(b"DEADBEEF"), Vec::<usize>::new());
+        assert_eq!(matcher.matches(b"dexad"), [2]);
+        assert_eq!(matcher.matches(b"dxad"), Vec::<usize>::new());
+        assert_eq!(matcher.matches(b""), Vec::<usize>::new());
+        assert_eq!(matcher.target(1), (Anchor::End, "beef"));
+    }
+
+    #[test]
+    fn case_insensitive_matchers_fold_words_and_address() {
+        let matcher = WordMatcher::starts(&["Bob", "ALICE", "zed"], false);
+        assert_eq!(matcher.matches(b"bOB123"), [0]);
+        assert_eq!(matcher.matches(b"alice123"), [1]);
+        assert_eq!(matcher.matches(b"ZeD"), [2]);
+        assert_eq!(matcher.target(1), (Anchor::Start, "alice"));
+        assert_eq!(WordMatcher::starts(&["ok", ""], false).matches(b"xyz"), [1]);
+    }
+
+    #[test]
+    fn thousands_of_targets_match_like_starts_with() {
+        let words: Vec<String> = (0..5000u32).map(|i| format!("{:x}", i.wrapping_mul(2654435761) >> (i % 20))).collect();
+        let word_refs: Vec<&str> = words.iter().map(|word| word.as_str()).collect();
+        let starts = WordMatcher::starts(&word_refs, true);
+        let ends = WordMatcher::ends(&word_refs, true);
+        for _ in 0..200 {
+            let address = crate::address::eth_wallet::generate_random_wallet().address;
+            let expected_starts: Vec<usize> = (0..words.len()).filter(|&i| address.starts_with(&words[i])).collect();
+            let expected_ends: Vec<usize> = (0..words.len()).filter(|&i| address.ends_with(&words[i])).collect();
+            let mut found_starts = starts.matches(address.as_bytes());
+            let mut found_ends = ends.matches(address.as_bytes());
+            found_starts.sort();
+            found_ends.sort();
+            assert_eq!(found_starts, expected_starts, "{}", address);
+            assert_eq!(found_ends, expected_ends, "{}", address);
+        }
+    }
+}